@@ -0,0 +1,561 @@
+//! Transactions whose writes are applied all at once.
+//!
+//! `SledTransaction` writes through to the trees as it goes, so an error
+//! halfway through an operation leaves whatever was written before it. A
+//! `BatchedTransaction` instead stages every write in memory, one overlay per
+//! tree, and reads through the overlays so it sees its own pending writes.
+//! `commit` applies the overlays in a single sled transaction over all the
+//! trees; `rollback`, or dropping the transaction, discards them.
+//!
+//! The overlays take the place of `IndraSledBatch` here: a `sled::Batch` can't
+//! be read back, and `IndraSledBatch::apply` writes its trees one after the
+//! other.
+
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::io::Cursor;
+
+use indradb::{util, Edge, Identifier, Json, Vertex};
+use sled::transaction::{ConflictableTransactionResult, TransactionError};
+use sled::{Batch, IVec, Transactional, Tree};
+use uuid::Uuid;
+
+use crate::counters::{decode_count, encode_count};
+use crate::datastore::SledHolder;
+use crate::errors::{map_err, DSError};
+use crate::latency::timed_iter;
+use crate::managers::edge_manager::EdgeManager;
+use crate::managers::edge_property_manager::EdgePropertyManager;
+use crate::managers::edge_type_manager::EdgeTypeManager;
+use crate::managers::vertex_manager::VertexManager;
+use crate::managers::vertex_property_manager::VertexPropertyManager;
+use crate::reverse_edge;
+use crate::transaction::SledTransaction;
+
+/// Pending writes to one tree: `Some` to insert a value, `None` to remove
+/// the key.
+#[derive(Default)]
+struct StagedTree(BTreeMap<Vec<u8>, Option<Vec<u8>>>);
+
+impl StagedTree {
+    fn get(&self, tree: &Tree, key: &[u8]) -> indradb::Result<Option<Vec<u8>>> {
+        match self.0.get(key) {
+            Some(staged) => Ok(staged.clone()),
+            None => Ok(map_err(timed!(Get, tree.get(key)))?.map(|value| value.to_vec())),
+        }
+    }
+
+    fn insert(&mut self, key: Vec<u8>, value: Vec<u8>) {
+        self.0.insert(key, Some(value));
+    }
+
+    fn remove(&mut self, key: Vec<u8>) {
+        self.0.insert(key, None);
+    }
+
+    /// Gets the entries of `tree` whose keys start with `prefix`, with the
+    /// pending writes applied.
+    fn scan_prefix(&self, tree: &Tree, prefix: &[u8]) -> indradb::Result<Vec<(Vec<u8>, Vec<u8>)>> {
+        let mut merged = BTreeMap::new();
+        for item in timed_iter(tree.scan_prefix(prefix)) {
+            let (k, v) = map_err(item)?;
+            merged.insert(k.to_vec(), v.to_vec());
+        }
+        let staged = self
+            .0
+            .range(prefix.to_vec()..)
+            .take_while(|(k, _)| k.starts_with(prefix));
+        for (k, v) in staged {
+            if let Some(v) = v {
+                merged.insert(k.clone(), v.clone());
+            } else {
+                merged.remove(k);
+            }
+        }
+        Ok(merged.into_iter().collect())
+    }
+
+    fn to_batch(&self) -> Batch {
+        let mut batch = Batch::default();
+        for (k, v) in &self.0 {
+            match v {
+                Some(v) => batch.insert(k.as_slice(), v.as_slice()),
+                None => batch.remove(k.as_slice()),
+            }
+        }
+        batch
+    }
+}
+
+/// A transaction that stages its writes in memory until `commit`. Created
+/// with `SledDatastore::batched_transaction`.
+pub struct BatchedTransaction<'a> {
+    inner: SledTransaction<'a>,
+    vertices: StagedTree,
+    edges: StagedTree,
+    edge_ranges: StagedTree,
+    reversed_edge_ranges: StagedTree,
+    edge_types: StagedTree,
+    vertex_properties: StagedTree,
+    edge_properties: StagedTree,
+    vertex_property_values: StagedTree,
+    edge_property_values: StagedTree,
+    // The change in the number of edges of each type, applied to the
+    // counters in the metadata tree on commit
+    type_count_deltas: HashMap<Identifier, i64>,
+    // The same for vertices
+    vertex_type_count_deltas: HashMap<Identifier, i64>,
+}
+
+impl<'a> BatchedTransaction<'a> {
+    pub(crate) fn new(holder: &'a SledHolder) -> Self {
+        BatchedTransaction {
+            inner: holder.transaction(),
+            vertices: StagedTree::default(),
+            edges: StagedTree::default(),
+            edge_ranges: StagedTree::default(),
+            reversed_edge_ranges: StagedTree::default(),
+            edge_types: StagedTree::default(),
+            vertex_properties: StagedTree::default(),
+            edge_properties: StagedTree::default(),
+            vertex_property_values: StagedTree::default(),
+            edge_property_values: StagedTree::default(),
+            type_count_deltas: HashMap::new(),
+            vertex_type_count_deltas: HashMap::new(),
+        }
+    }
+
+    fn holder(&self) -> &'a SledHolder {
+        self.inner.holder
+    }
+
+    /// Gets a vertex, including vertices created by this transaction.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn vertex(&self, id: Uuid) -> indradb::Result<Option<Vertex>> {
+        let key = self.inner.vertex_manager.key(id);
+        Ok(self.vertices.get(&self.holder().vertices, &key)?.map(|value| {
+            let mut cursor = Cursor::new(value.as_slice());
+            Vertex::with_id(id, util::read_identifier(&mut cursor))
+        }))
+    }
+
+    /// Checks whether an edge exists, including edges created by this
+    /// transaction.
+    ///
+    /// # Arguments
+    /// * `edge`: The edge to look up.
+    pub fn has_edge(&self, edge: &Edge) -> indradb::Result<bool> {
+        let key = self.inner.edge_manager.key(edge.clone());
+        Ok(self.edges.get(&self.holder().edges, &key)?.is_some())
+    }
+
+    /// Gets a vertex property, including pending writes.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `name`: The name of the property.
+    pub fn vertex_property(&self, id: Uuid, name: Identifier) -> indradb::Result<Option<Json>> {
+        let key = self.inner.vertex_property_manager.key(id, name);
+        match self.vertex_properties.get(&self.holder().vertex_properties, &key)? {
+            Some(value) => Ok(Some(Json::new(
+                self.inner.vertex_property_manager.codec.decode(&value)?,
+            ))),
+            None => Ok(None),
+        }
+    }
+
+    /// Gets an edge property, including pending writes.
+    ///
+    /// # Arguments
+    /// * `edge`: The edge.
+    /// * `name`: The name of the property.
+    pub fn edge_property(&self, edge: &Edge, name: Identifier) -> indradb::Result<Option<Json>> {
+        let key = self.inner.edge_property_manager.key(edge, name);
+        match self.edge_properties.get(&self.holder().edge_properties, &key)? {
+            Some(value) => Ok(Some(Json::new(self.inner.edge_property_manager.codec.decode(&value)?))),
+            None => Ok(None),
+        }
+    }
+
+    /// Stages the creation of a vertex. Returns `false` if it already exists.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create.
+    pub fn create_vertex(&mut self, vertex: &Vertex) -> indradb::Result<bool> {
+        self.inner.check_writable()?;
+        if self.vertex(vertex.id)?.is_some() {
+            return Ok(false);
+        }
+        let key = self.inner.vertex_manager.key(vertex.id);
+        self.vertices
+            .insert(key, util::build(&[util::Component::Identifier(vertex.t)]));
+        *self.vertex_type_count_deltas.entry(vertex.t).or_insert(0) += 1;
+        Ok(true)
+    }
+
+    /// Stages the creation of an edge. Returns `false` if either of its
+    /// vertices doesn't exist.
+    ///
+    /// # Arguments
+    /// * `edge`: The edge to create.
+    pub fn create_edge(&mut self, edge: &Edge) -> indradb::Result<bool> {
+        self.inner.check_writable()?;
+        if self.vertex(edge.outbound_id)?.is_none() || self.vertex(edge.inbound_id)?.is_none() {
+            return Ok(false);
+        }
+        if !self.has_edge(edge)? {
+            *self.type_count_deltas.entry(edge.t).or_insert(0) += 1;
+        }
+        self.edges.insert(self.inner.edge_manager.key(edge.clone()), Vec::new());
+        self.edge_ranges
+            .insert(self.inner.edge_range_manager.key(edge), Vec::new());
+        if self.inner.holder.reversed_edges {
+            self.reversed_edge_ranges
+                .insert(self.inner.edge_range_manager_rev.key(&reverse_edge(edge)), Vec::new());
+        }
+        self.edge_types
+            .insert(EdgeTypeManager::new(self.inner.holder).key(edge), Vec::new());
+        Ok(true)
+    }
+
+    /// Stages setting a property on each of the given vertices.
+    ///
+    /// # Arguments
+    /// * `vertices`: The ids of the vertices.
+    /// * `name`: The name of the property.
+    /// * `value`: The value of the property.
+    pub fn set_vertex_properties(
+        &mut self,
+        vertices: Vec<Uuid>,
+        name: Identifier,
+        value: &Json,
+    ) -> indradb::Result<()> {
+        self.inner.check_writable()?;
+        let value_json = self.inner.vertex_property_manager.codec.encode(value)?;
+        for id in vertices {
+            self.delete_vertex_property(id, name)?;
+            let key = self.inner.vertex_property_manager.key(id, name);
+            self.vertex_properties.insert(key, value_json.clone());
+            if self.inner.holder.property_value_index {
+                self.vertex_property_values.insert(
+                    VertexPropertyManager::key_value_index(&id, value, name),
+                    value_json.clone(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Stages setting a property on each of the given edges.
+    ///
+    /// # Arguments
+    /// * `edges`: The edges.
+    /// * `name`: The name of the property.
+    /// * `value`: The value of the property.
+    pub fn set_edge_properties(&mut self, edges: Vec<Edge>, name: Identifier, value: &Json) -> indradb::Result<()> {
+        self.inner.check_writable()?;
+        let value_json = self.inner.edge_property_manager.codec.encode(value)?;
+        for edge in edges {
+            self.delete_edge_property(&edge, name)?;
+            let key = self.inner.edge_property_manager.key(&edge, name);
+            self.edge_properties.insert(key, value_json.clone());
+            if self.inner.holder.property_value_index {
+                self.edge_property_values.insert(
+                    EdgePropertyManager::key_value_index(&edge, value, name),
+                    value_json.clone(),
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Stages the deletion of vertices, along with their properties and
+    /// outbound edges.
+    ///
+    /// # Arguments
+    /// * `vertices`: The vertices to delete.
+    pub fn delete_vertices(&mut self, vertices: Vec<Vertex>) -> indradb::Result<()> {
+        self.inner.check_writable()?;
+        for vertex in vertices {
+            if let Some(stored) = self.vertex(vertex.id)? {
+                *self.vertex_type_count_deltas.entry(stored.t).or_insert(0) -= 1;
+            }
+            self.vertices.remove(self.inner.vertex_manager.key(vertex.id));
+
+            let prefix = util::build(&[util::Component::Uuid(vertex.id)]);
+            let properties = self
+                .vertex_properties
+                .scan_prefix(&self.holder().vertex_properties, &prefix)?;
+            for (key, _) in properties {
+                let (id, name) = VertexPropertyManager::read_key(IVec::from(key));
+                self.delete_vertex_property(id, name)?;
+            }
+
+            let edges = self.edge_ranges.scan_prefix(&self.holder().edge_ranges, &prefix)?;
+            for (key, _) in edges {
+                let mut cursor = Cursor::new(key.as_slice());
+                let outbound_id = util::read_uuid(&mut cursor);
+                let t = util::read_identifier(&mut cursor);
+                let inbound_id = util::read_uuid(&mut cursor);
+                self.delete_edge(&Edge {
+                    outbound_id,
+                    t,
+                    inbound_id,
+                })?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stages the deletion of edges, along with their properties. Edges whose
+    /// outbound vertex doesn't exist are skipped.
+    ///
+    /// # Arguments
+    /// * `edges`: The edges to delete.
+    pub fn delete_edges(&mut self, edges: Vec<Edge>) -> indradb::Result<()> {
+        self.inner.check_writable()?;
+        for edge in edges {
+            if self.vertex(edge.outbound_id)?.is_some() {
+                self.delete_edge(&edge)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stages the deletion of vertex properties.
+    ///
+    /// # Arguments
+    /// * `props`: The vertex ids and names of the properties to delete.
+    pub fn delete_vertex_properties(&mut self, props: Vec<(Uuid, Identifier)>) -> indradb::Result<()> {
+        self.inner.check_writable()?;
+        for (id, name) in props {
+            self.delete_vertex_property(id, name)?;
+        }
+        Ok(())
+    }
+
+    /// Stages the deletion of edge properties.
+    ///
+    /// # Arguments
+    /// * `props`: The edges and names of the properties to delete.
+    pub fn delete_edge_properties(&mut self, props: Vec<(Edge, Identifier)>) -> indradb::Result<()> {
+        self.inner.check_writable()?;
+        for (edge, name) in props {
+            self.delete_edge_property(&edge, name)?;
+        }
+        Ok(())
+    }
+
+    fn delete_edge(&mut self, edge: &Edge) -> indradb::Result<()> {
+        if self.has_edge(edge)? {
+            *self.type_count_deltas.entry(edge.t).or_insert(0) -= 1;
+        }
+        self.edges.remove(self.inner.edge_manager.key(edge.clone()));
+        self.edge_ranges.remove(self.inner.edge_range_manager.key(edge));
+        if self.inner.holder.reversed_edges {
+            self.reversed_edge_ranges
+                .remove(self.inner.edge_range_manager_rev.key(&reverse_edge(edge)));
+        }
+        self.edge_types
+            .remove(EdgeTypeManager::new(self.inner.holder).key(edge));
+
+        let prefix = self.inner.edge_manager.key(edge.clone());
+        let properties = self
+            .edge_properties
+            .scan_prefix(&self.holder().edge_properties, &prefix)?;
+        for (key, _) in properties {
+            let (edge, name) = EdgePropertyManager::read_key(IVec::from(key));
+            self.delete_edge_property(&edge, name)?;
+        }
+        Ok(())
+    }
+
+    fn delete_vertex_property(&mut self, id: Uuid, name: Identifier) -> indradb::Result<()> {
+        let key = self.inner.vertex_property_manager.key(id, name);
+        let old = if self.inner.holder.property_value_index {
+            self.vertex_properties.get(&self.holder().vertex_properties, &key)?
+        } else {
+            None
+        };
+        if let Some(old) = old {
+            let old_value = self.inner.vertex_property_manager.codec.decode(&old)?;
+            self.vertex_property_values
+                .remove(VertexPropertyManager::key_value_index(&id, &old_value, name));
+        }
+        self.vertex_properties.remove(key);
+        Ok(())
+    }
+
+    fn delete_edge_property(&mut self, edge: &Edge, name: Identifier) -> indradb::Result<()> {
+        let key = self.inner.edge_property_manager.key(edge, name);
+        let old = if self.inner.holder.property_value_index {
+            self.edge_properties.get(&self.holder().edge_properties, &key)?
+        } else {
+            None
+        };
+        if let Some(old) = old {
+            let old_value = self.inner.edge_property_manager.codec.decode(&old)?;
+            self.edge_property_values
+                .remove(EdgePropertyManager::key_value_index(edge, &old_value, name));
+        }
+        self.edge_properties.remove(key);
+        Ok(())
+    }
+
+    /// Applies every staged write in one sled transaction, so either all of
+    /// them reach the trees or none do.
+    pub fn commit(self) -> indradb::Result<()> {
+        self.inner.check_writable()?;
+        let holder = self.holder();
+        // The deltas only make sense on top of counters that are already built
+        self.inner.edge_manager.ensure_type_counts()?;
+        self.inner.vertex_manager.ensure_type_counts()?;
+        let mut type_counts = Vec::with_capacity(self.type_count_deltas.len() + self.vertex_type_count_deltas.len());
+        for (t, delta) in &self.type_count_deltas {
+            type_counts.push((EdgeManager::type_count_key(*t)?, *delta));
+        }
+        for (t, delta) in &self.vertex_type_count_deltas {
+            type_counts.push((VertexManager::type_count_key(*t)?, *delta));
+        }
+
+        let staged = [
+            (&holder.vertices, &self.vertices),
+            (&holder.edges, &self.edges),
+            (&holder.edge_ranges, &self.edge_ranges),
+            (&holder.reversed_edge_ranges, &self.reversed_edge_ranges),
+            (&holder.edge_types, &self.edge_types),
+            (&holder.vertex_properties, &self.vertex_properties),
+            (&holder.edge_properties, &self.edge_properties),
+            (&holder.vertex_property_values, &self.vertex_property_values),
+            (&holder.edge_property_values, &self.edge_property_values),
+        ];
+        let batches: Vec<Batch> = staged.iter().map(|(_, tree)| tree.to_batch()).collect();
+        let mut trees: Vec<&Tree> = staged.iter().map(|(tree, _)| *tree).collect();
+        trees.push(&holder.metadata);
+
+        let result = timed!(
+            ApplyBatch,
+            trees
+                .as_slice()
+                .transaction(|views| -> ConflictableTransactionResult<(), Infallible> {
+                    for (view, batch) in views.iter().zip(&batches) {
+                        view.apply_batch(batch)?;
+                    }
+                    let metadata = &views[batches.len()];
+                    for (key, delta) in &type_counts {
+                        let count = metadata.get(key)?.map_or(0, |value| decode_count(&value));
+                        metadata.insert(key.as_slice(), &encode_count(count.saturating_add_signed(*delta)))?;
+                    }
+                    Ok(())
+                })
+        );
+        match result {
+            Ok(()) => Ok(()),
+            Err(TransactionError::Storage(err)) => Err(DSError::from(err).into()),
+            Err(TransactionError::Abort(never)) => match never {},
+        }
+    }
+
+    /// Discards every staged write. Dropping the transaction does the same.
+    pub fn rollback(self) {}
+}
+
+#[cfg(test)]
+mod test {
+    use indradb::{Datastore, Edge, Error, Identifier, Json, Transaction, Vertex};
+    use serde_json::json;
+
+    use super::BatchedTransaction;
+    use crate::SledDatastore;
+
+    /// Creates two vertices joined by an edge, with a property on each, and
+    /// fails before returning if `fail` is set.
+    fn load(txn: &mut BatchedTransaction, a: &Vertex, b: &Vertex, fail: bool) -> indradb::Result<()> {
+        let name = Identifier::new("name").unwrap();
+        txn.create_vertex(a)?;
+        txn.create_vertex(b)?;
+        txn.set_vertex_properties(vec![a.id, b.id], name, &Json::new(json!("x")))?;
+        let edge = Edge::new(a.id, a.t, b.id);
+        assert!(txn.create_edge(&edge)?);
+        txn.set_edge_properties(vec![edge], name, &Json::new(json!(1)))?;
+        if fail {
+            return Err(Error::Unsupported);
+        }
+        Ok(())
+    }
+
+    #[test]
+    fn test_reads_see_pending_writes() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edge = Edge::new(a.id, t, b.id);
+
+        let mut txn = ds.batched_transaction();
+        load(&mut txn, &a, &b, false).unwrap();
+        assert_eq!(txn.vertex(a.id).unwrap(), Some(a.clone()));
+        assert!(txn.has_edge(&edge).unwrap());
+        assert_eq!(txn.vertex_property(a.id, name).unwrap(), Some(Json::new(json!("x"))));
+        assert_eq!(txn.edge_property(&edge, name).unwrap(), Some(Json::new(json!(1))));
+        assert_eq!(ds.transaction().vertex_count(), 0);
+
+        txn.delete_vertices(vec![a.clone()]).unwrap();
+        assert_eq!(txn.vertex(a.id).unwrap(), None);
+        assert!(!txn.has_edge(&edge).unwrap());
+        assert_eq!(txn.edge_property(&edge, name).unwrap(), None);
+        txn.commit().unwrap();
+
+        let txn = ds.transaction();
+        assert_eq!(txn.vertex_count(), 1);
+        assert_eq!(txn.edge_count(), 0);
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_injected_failure_writes_nothing() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+
+        let mut txn = ds.batched_transaction();
+        assert!(load(&mut txn, &a, &b, true).is_err());
+        txn.rollback();
+        let txn = ds.transaction();
+        assert_eq!(txn.vertex_count(), 0);
+        assert_eq!(txn.edge_count(), 0);
+        for tree in ds.holder.named_trees().iter().filter(|(name, _)| *name != "metadata") {
+            assert!(tree.1.is_empty(), "{} was written", tree.0);
+        }
+    }
+
+    #[test]
+    fn test_commit_writes_everything() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edge = Edge::new(a.id, t, b.id);
+
+        let mut txn = ds.batched_transaction();
+        load(&mut txn, &a, &b, false).unwrap();
+        txn.commit().unwrap();
+
+        let txn = ds.transaction();
+        assert_eq!(txn.vertex_count(), 2);
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 1);
+        assert_eq!(txn.distinct_vertex_types().unwrap(), vec![t]);
+        assert_eq!(txn.edge_property(&edge, name).unwrap(), Some(Json::new(json!(1))));
+        let found: Vec<Edge> = txn.edges_with_type(t).unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(found, vec![edge]);
+        let with_value: Vec<_> = txn
+            .vertex_property_manager
+            .iterate_for_property_name_and_value(name, &json!("x"))
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(with_value.len(), 2);
+    }
+}
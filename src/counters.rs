@@ -0,0 +1,197 @@
+//! Persistent counters and sequence allocation on top of sled trees.
+
+use std::sync::Mutex;
+
+use sled::Tree;
+
+use crate::errors::{map_err, DSError};
+
+/// What a counter does when an update would take it out of the range of a
+/// `u64`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CounterBehavior {
+    /// Clamps the counter to `0` or `u64::MAX`.
+    Saturate,
+    /// Leaves the counter unchanged and returns an error.
+    Error,
+}
+
+pub(crate) fn encode_count(value: u64) -> [u8; 8] {
+    value.to_be_bytes()
+}
+
+pub(crate) fn decode_count(buf: &[u8]) -> u64 {
+    u64::from_be_bytes(buf.try_into().unwrap_or_default())
+}
+
+/// A `u64` counter stored under a single key, updated atomically.
+pub struct PersistentCounter<'tree> {
+    tree: &'tree Tree,
+    key: Vec<u8>,
+    behavior: CounterBehavior,
+}
+
+impl<'tree> PersistentCounter<'tree> {
+    /// Creates a new counter. A missing key counts as `0`.
+    ///
+    /// # Arguments
+    /// * `tree`: The tree holding the counter.
+    /// * `key`: The key of the counter.
+    /// * `behavior`: What to do on underflow or overflow.
+    pub fn new(tree: &'tree Tree, key: Vec<u8>, behavior: CounterBehavior) -> Self {
+        PersistentCounter { tree, key, behavior }
+    }
+
+    /// Gets the current value.
+    pub fn get(&self) -> indradb::Result<u64> {
+        let value = map_err(timed!(Get, self.tree.get(&self.key)))?;
+        Ok(value.map_or(0, |value| decode_count(&value)))
+    }
+
+    /// Overwrites the current value.
+    ///
+    /// # Arguments
+    /// * `value`: The new value.
+    pub fn set(&self, value: u64) -> indradb::Result<()> {
+        map_err(timed!(Insert, self.tree.insert(&self.key, &encode_count(value))))?;
+        Ok(())
+    }
+
+    /// Adds `delta` to the counter, returning the new value.
+    ///
+    /// # Arguments
+    /// * `delta`: The amount to add, negative to subtract.
+    pub fn add(&self, delta: i64) -> indradb::Result<u64> {
+        let mut out_of_range = false;
+        let updated = map_err(timed!(
+            Insert,
+            self.tree.update_and_fetch(&self.key, |old| {
+                let count = old.map_or(0, decode_count);
+                out_of_range = false;
+                let updated = match count.checked_add_signed(delta) {
+                    Some(updated) => updated,
+                    None if self.behavior == CounterBehavior::Saturate => count.saturating_add_signed(delta),
+                    None => {
+                        out_of_range = true;
+                        count
+                    }
+                };
+                Some(encode_count(updated).to_vec())
+            })
+        ))?;
+        if out_of_range {
+            return Err(DSError::CounterOutOfRange.into());
+        }
+        Ok(updated.map_or(0, |value| decode_count(&value)))
+    }
+}
+
+/// Hands out strictly increasing sequence numbers that are never reused,
+/// even across restarts.
+///
+/// Numbers are reserved from a persisted high-water mark in blocks, so most
+/// calls don't touch the tree. The unused tail of a block is discarded when
+/// the allocator is dropped, leaving gaps in the sequence.
+pub struct SequenceAllocator<'tree> {
+    counter: PersistentCounter<'tree>,
+    block_size: u64,
+    // the next number to hand out and the end of the reserved block
+    block: Mutex<(u64, u64)>,
+}
+
+impl<'tree> SequenceAllocator<'tree> {
+    /// Creates a new allocator.
+    ///
+    /// # Arguments
+    /// * `tree`: The tree holding the high-water mark.
+    /// * `key`: The key of the high-water mark.
+    /// * `block_size`: How many numbers to reserve at once.
+    pub fn new(tree: &'tree Tree, key: Vec<u8>, block_size: u64) -> Self {
+        SequenceAllocator {
+            counter: PersistentCounter::new(tree, key, CounterBehavior::Error),
+            block_size: block_size.max(1),
+            block: Mutex::new((0, 0)),
+        }
+    }
+
+    /// Gets the next sequence number.
+    pub fn next(&self) -> indradb::Result<u64> {
+        let mut block = self.block.lock().map_err(DSError::from)?;
+        if block.0 == block.1 {
+            let end = self.counter.add(self.block_size as i64)?;
+            *block = (end - self.block_size, end);
+        }
+        let sequence = block.0;
+        block.0 += 1;
+        Ok(sequence)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+
+    use sled::Config;
+
+    use super::*;
+
+    fn tree() -> Tree {
+        let db = Config::new().temporary(true).open().unwrap();
+        db.open_tree("counters").unwrap()
+    }
+
+    #[test]
+    fn test_counter_underflow() {
+        let tree = tree();
+        let saturating = PersistentCounter::new(&tree, b"saturating".to_vec(), CounterBehavior::Saturate);
+        assert_eq!(saturating.add(1).unwrap(), 1);
+        assert_eq!(saturating.add(-5).unwrap(), 0);
+        assert_eq!(saturating.get().unwrap(), 0);
+
+        let strict = PersistentCounter::new(&tree, b"strict".to_vec(), CounterBehavior::Error);
+        assert_eq!(strict.add(2).unwrap(), 2);
+        assert!(strict.add(-3).is_err());
+        assert_eq!(strict.get().unwrap(), 2);
+        strict.set(u64::MAX).unwrap();
+        assert!(strict.add(1).is_err());
+        assert_eq!(strict.get().unwrap(), u64::MAX);
+    }
+
+    #[test]
+    fn test_counter_concurrent_increments() {
+        let tree = tree();
+        let counter = PersistentCounter::new(&tree, b"counter".to_vec(), CounterBehavior::Error);
+        thread::scope(|scope| {
+            for _ in 0..8 {
+                scope.spawn(|| {
+                    for _ in 0..500 {
+                        counter.add(1).unwrap();
+                    }
+                });
+            }
+        });
+        assert_eq!(counter.get().unwrap(), 4_000);
+    }
+
+    #[test]
+    fn test_sequence_monotonic_across_restarts() {
+        let tree = tree();
+        let mut last = None;
+        for _ in 0..3 {
+            let allocator = SequenceAllocator::new(&tree, b"sequence".to_vec(), 16);
+            for _ in 0..5 {
+                let sequence = allocator.next().unwrap();
+                if let Some(last) = last {
+                    assert!(sequence > last);
+                }
+                last = Some(sequence);
+            }
+        }
+        assert_eq!(
+            PersistentCounter::new(&tree, b"sequence".to_vec(), CounterBehavior::Error)
+                .get()
+                .unwrap(),
+            48
+        );
+    }
+}
@@ -1,22 +1,75 @@
-use std::path::Path;
+use std::collections::BTreeMap;
+use std::env;
+use std::fmt;
+use std::fs;
+use std::io::{Read, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, SyncSender};
+use std::sync::{Arc, OnceLock};
+use std::thread;
+use std::time::{Duration, Instant};
 
-use indradb::{Datastore, Result};
+use indradb::{util, Datastore, Identifier, Result, Transaction};
+use serde::Serialize;
 use sled::{Config, Db, Tree};
+use uuid::Uuid;
 
+use crate::batched::BatchedTransaction;
+use crate::counters::{decode_count, encode_count};
+use crate::encoding::{PropertyCodec, PropertyEncoding, ValueCodec};
+use crate::integrity::IntegrityReport;
+use crate::json_export::{self, ImportStats};
+#[cfg(feature = "latency-metrics")]
+use crate::latency::{self, LatencyReport};
+use crate::managers;
 use crate::managers::edge_manager::EdgeManager;
 use crate::managers::edge_property_manager::EdgePropertyManager;
 use crate::managers::edge_range_manager::EdgeRangeManager;
-use crate::managers::metadata::MetaDataManager;
+use crate::managers::metadata::{MetaDataManager, PROPERTY_ENCODING_KEY, PROPERTY_VALUE_INDEX_KEY, REVERSED_EDGES_KEY};
 use crate::managers::vertex_manager::VertexManager;
 use crate::managers::vertex_property_manager::VertexPropertyManager;
+use crate::migrations;
+use crate::read_only::SledDatastoreReadOnly;
+use crate::snapshot;
 use crate::transaction::SledTransaction;
+use crate::watch::VertexWatcher;
 
-use super::errors::map_err;
+use super::errors::{map_err, DSError};
 
-#[derive(Copy, Clone, Default, Debug)]
+/// Called with the error when a dropped datastore fails to flush, see
+/// `SledConfig::on_drop_error`.
+#[derive(Clone)]
+pub(crate) struct DropErrorCallback(Arc<dyn Fn(&indradb::Error) + Send + Sync>);
+
+impl fmt::Debug for DropErrorCallback {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("DropErrorCallback")
+    }
+}
+
+#[derive(Clone, Default, Debug)]
 pub struct SledConfig {
     use_compression: bool,
     compression_factor: Option<i32>,
+    cache_capacity: Option<u64>,
+    flush_every_ms: Option<u64>,
+    segment_size: Option<usize>,
+    idgen_persist_interval: Option<u64>,
+    temporary: bool,
+    read_only: bool,
+    // Set by `maintain_reversed_edges`, `None` keeps the stored setting
+    reversed_edges: Option<bool>,
+    // Set by `maintain_property_value_index`, `None` keeps the stored setting
+    property_value_index: Option<bool>,
+    property_encoding: Option<PropertyEncoding>,
+    value_codec: Option<Arc<dyn ValueCodec>>,
+    // Set by `flush_on_drop(false)`
+    skip_flush_on_drop: bool,
+    drop_error_callback: Option<DropErrorCallback>,
+    // Set by `graph_name` or `namespace`, see `graph_prefix`
+    graph_name: Option<String>,
+    sled_config: Option<Config>,
 }
 
 impl SledConfig {
@@ -26,26 +79,381 @@ impl SledConfig {
     /// * `factor`: The zstd compression factor to use. If unspecified, this
     ///   will default to 5.
     pub fn with_compression(factor: Option<i32>) -> SledConfig {
+        SledConfig::default().compression(factor)
+    }
+
+    /// Creates a new sled config on top of a fully configured `sled::Config`,
+    /// for sled options that aren't wrapped by this crate. The path of
+    /// `config` is replaced by the one passed to `open`, and the options set
+    /// on this struct only apply where `config` still has sled's defaults.
+    ///
+    /// # Arguments
+    /// * `config`: The sled config to build on.
+    pub fn from_sled_config(config: Config) -> SledConfig {
         SledConfig {
-            use_compression: true,
-            compression_factor: factor,
+            sled_config: Some(config),
+            ..SledConfig::default()
         }
     }
 
+    /// Enables zstd compression.
+    ///
+    /// # Arguments
+    /// * `factor`: The zstd compression factor to use. If unspecified, this
+    ///   will default to 5.
+    pub fn compression(mut self, factor: Option<i32>) -> Self {
+        self.use_compression = true;
+        self.compression_factor = factor;
+        self
+    }
+
+    /// Sets the maximum size in bytes of sled's page cache.
+    ///
+    /// # Arguments
+    /// * `capacity`: The cache capacity in bytes.
+    pub fn cache_capacity(mut self, capacity: u64) -> Self {
+        self.cache_capacity = Some(capacity);
+        self
+    }
+
+    /// Sets how often sled flushes dirty pages to disk in the background.
+    /// Sled defaults to every 500ms.
+    ///
+    /// Writes made since the last flush are lost on a crash, so a longer
+    /// interval trades durability for write throughput. An interval of `0`
+    /// disables periodic flushing entirely, leaving explicit `sync()` calls
+    /// as the only durability point.
+    ///
+    /// # Arguments
+    /// * `ms`: The flush interval in milliseconds, or `0` to disable.
+    pub fn flush_every_ms(mut self, ms: u64) -> Self {
+        self.flush_every_ms = Some(ms);
+        self
+    }
+
+    /// Sets the size in bytes of sled's on-disk segments, which defaults to
+    /// 512KiB. It must be a power of two between 256 bytes and 16MiB, and in
+    /// practice at least a few hundred KiB. Larger segments mean fewer of
+    /// them to rewrite under many small updates, at the cost of more space
+    /// held by partly live segments.
+    ///
+    /// The segment size is fixed when the database is created: opening an
+    /// existing database with a different size fails.
+    ///
+    /// # Arguments
+    /// * `size`: The segment size in bytes.
+    pub fn segment_size(mut self, size: usize) -> Self {
+        self.segment_size = Some(size);
+        self
+    }
+
+    /// Sets how many ids sled hands out between persisting its id
+    /// generator, which defaults to 1,000,000. Lower values write the
+    /// generator more often; higher ones skip more ids after a crash.
+    ///
+    /// # Arguments
+    /// * `interval`: The number of ids between persists.
+    pub fn idgen_persist_interval(mut self, interval: u64) -> Self {
+        self.idgen_persist_interval = Some(interval);
+        self
+    }
+
+    /// Makes the database temporary, deleting its files once the datastore
+    /// is dropped.
+    pub fn temporary(mut self) -> Self {
+        self.temporary = true;
+        self
+    }
+
+    /// Opens the datastore in read-only mode, where every mutating
+    /// transaction method returns an error instead of writing. Sled itself
+    /// doesn't enforce this, so it only guards writes made through this crate.
+    pub fn read_only(mut self) -> Self {
+        self.read_only = true;
+        self
+    }
+
+    /// Sets whether edges are also indexed by their inbound vertex in the
+    /// reversed edge ranges, which is the default. Without them every edge
+    /// write touches one tree fewer, but `range_reversed_edges` and the
+    /// inbound degree and neighbor queries fail with an unsupported error.
+    ///
+    /// The setting is stored in the datastore. Reopening with it turned off
+    /// clears the reversed edge ranges, and turning it back on rebuilds them
+    /// from the edges. Configs that don't set it, including the one used by
+    /// `SledDatastore::new` and the backup and restore methods, keep the
+    /// stored setting, as do read-only datastores.
+    ///
+    /// # Arguments
+    /// * `maintain`: Whether to maintain the reversed edge ranges.
+    pub fn maintain_reversed_edges(mut self, maintain: bool) -> Self {
+        self.reversed_edges = Some(maintain);
+        self
+    }
+
+    /// Sets whether property values are indexed, which is the default.
+    /// Without the value indexes, setting and deleting properties writes
+    /// only the property trees, but `index_property` fails with an
+    /// unsupported error and no property counts as indexed, so queries by
+    /// property value return `None`.
+    ///
+    /// The setting is stored in the datastore. Reopening with it turned off
+    /// clears the value indexes, and turning it back on rebuilds them from
+    /// the properties. Configs that don't set it keep the stored setting, as
+    /// for `maintain_reversed_edges`.
+    ///
+    /// # Arguments
+    /// * `maintain`: Whether to maintain the property value indexes.
+    pub fn maintain_property_value_index(mut self, maintain: bool) -> Self {
+        self.property_value_index = Some(maintain);
+        self
+    }
+
+    /// Sets how property values are serialized. A store that has properties
+    /// keeps the encoding they were written with, and opening it with a
+    /// different one fails with `DSError::PropertyEncodingMismatch` rather
+    /// than mixing formats. Without this, the store's own encoding is used,
+    /// which is JSON for new stores.
+    ///
+    /// # Arguments
+    /// * `encoding`: The encoding of property values.
+    pub fn property_encoding(mut self, encoding: PropertyEncoding) -> Self {
+        self.property_encoding = Some(encoding);
+        self
+    }
+
+    /// Transforms property values with `codec` after they're serialized and
+    /// before they're parsed, e.g. to encrypt them at rest. Keys, including
+    /// those of the value indexes, aren't transformed. The codec isn't
+    /// recorded in the datastore, so it has to be given every time the
+    /// datastore is opened.
+    ///
+    /// # Arguments
+    /// * `codec`: The codec to apply to property values.
+    pub fn with_value_codec(mut self, codec: Arc<dyn ValueCodec>) -> Self {
+        self.value_codec = Some(codec);
+        self
+    }
+
+    /// Sets whether dropping the datastore flushes it, which is the default.
+    /// Without it, writes that weren't followed by `Transaction::sync` may
+    /// be lost if the process exits soon after the datastore is dropped.
+    /// Read-only datastores are never flushed.
+    ///
+    /// # Arguments
+    /// * `flush`: Whether to flush the datastore when it's dropped.
+    pub fn flush_on_drop(mut self, flush: bool) -> Self {
+        self.skip_flush_on_drop = !flush;
+        self
+    }
+
+    /// Calls `callback` with the error if flushing the datastore when it's
+    /// dropped fails. Otherwise the error is ignored, since a drop can't
+    /// return it.
+    ///
+    /// # Arguments
+    /// * `callback`: The function to report the error to.
+    pub fn on_drop_error<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(&indradb::Error) + Send + Sync + 'static,
+    {
+        self.drop_error_callback = Some(DropErrorCallback(Arc::new(callback)));
+        self
+    }
+
+    /// Stores the graph in trees prefixed with `name` (e.g. "tenant1.edges"),
+    /// so that several independent graphs can share one sled database.
+    /// Vertices are kept in a "`name`.vertices" tree rather than the default
+    /// tree. The "." is reserved as the separator, so opening fails with
+    /// `DSError::InvalidGraphName` if `name` is empty or contains one.
+    /// Replaces any `namespace` or `with_namespace`.
+    ///
+    /// # Arguments
+    /// * `name`: The name of the graph.
+    pub fn graph_name<S: Into<String>>(mut self, name: S) -> Self {
+        self.graph_name = Some(name.into());
+        self
+    }
+
+    /// Same as `graph_name`, for keeping the graph's trees clear of the
+    /// application's own trees in the same database. The trees are named
+    /// like "`namespace`.vertices", with the same "." separator as graph
+    /// names rather than an underscore, which tree names can contain.
+    ///
+    /// # Arguments
+    /// * `namespace`: The namespace of the graph's trees.
+    pub fn namespace<S: Into<String>>(self, namespace: S) -> Self {
+        self.graph_name(namespace)
+    }
+
+    /// Same as `graph_name`, so the graph's trees are named like
+    /// "`namespace`.edges". Datastores over the same sled database but in
+    /// different namespaces don't see each other's data.
+    ///
+    /// # Arguments
+    /// * `namespace`: The namespace of the graph's trees.
+    pub fn with_namespace(self, namespace: &str) -> Self {
+        self.graph_name(namespace)
+    }
+
     /// Creates a new sled datastore.
     pub fn open<P: AsRef<Path>>(self, path: P) -> Result<SledDatastore> {
         Ok(SledDatastore {
             holder: SledHolder::new(path, self)?,
         })
     }
+
+    /// Opens an existing sled datastore whose transactions can only read.
+    /// Unlike `read_only`, which makes mutating methods fail at runtime,
+    /// this returns a datastore with its own transaction type.
+    pub fn open_read_only<P: AsRef<Path>>(self, path: P) -> Result<SledDatastoreReadOnly> {
+        Ok(SledDatastoreReadOnly {
+            holder: SledHolder::new(path, self.read_only())?,
+        })
+    }
+
+    /// Creates a new sled datastore, along with a report of the state it was
+    /// found in. Gathering the report scans every tree.
+    pub fn open_with_report<P: AsRef<Path>>(self, path: P) -> Result<(SledDatastore, RecoveryReport)> {
+        let datastore = self.open(path)?;
+        let report = datastore.recovery_report()?;
+        Ok((datastore, report))
+    }
+
+    pub(crate) fn to_sled_config<P: AsRef<Path>>(&self, path: P) -> Config {
+        let defaults = Config::default();
+        let mut config = match self.sled_config {
+            // `Config::path` requires sole ownership of the config, so
+            // re-setting the mode first forces a private copy.
+            Some(ref config) => config.clone().mode(config.mode).path(path),
+            None => Config::default().path(path),
+        };
+
+        if self.use_compression {
+            config = config.use_compression(true);
+        }
+
+        if let Some(compression_factor) = self.compression_factor {
+            if config.compression_factor == defaults.compression_factor {
+                config = config.compression_factor(compression_factor);
+            }
+        }
+
+        if let Some(cache_capacity) = self.cache_capacity {
+            if config.cache_capacity == defaults.cache_capacity {
+                config = config.cache_capacity(cache_capacity);
+            }
+        }
+
+        if let Some(segment_size) = self.segment_size {
+            if config.segment_size == defaults.segment_size {
+                config = config.segment_size(segment_size);
+            }
+        }
+
+        if let Some(idgen_persist_interval) = self.idgen_persist_interval {
+            if config.idgen_persist_interval == defaults.idgen_persist_interval {
+                config = config.idgen_persist_interval(idgen_persist_interval);
+            }
+        }
+
+        if self.temporary {
+            config = config.temporary(true);
+        }
+
+        if let Some(flush_every_ms) = self.flush_every_ms {
+            if config.flush_every_ms == defaults.flush_every_ms {
+                config = config.flush_every_ms(Some(flush_every_ms).filter(|ms| *ms > 0));
+            }
+        }
+
+        config
+    }
+}
+
+/// The approximate size of one of the datastore's trees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TreeSize {
+    /// The number of keys in the tree.
+    pub keys: u64,
+    /// The total length of the keys and values in the tree. This ignores
+    /// sled's own overhead, so it is only an approximation of the space used.
+    pub bytes: u64,
+    /// The total length of the values in the tree.
+    pub value_bytes: u64,
+}
+
+/// Statistics about a datastore, as returned by `SledDatastore::stats`.
+#[derive(Clone, Debug, Serialize)]
+pub struct DatastoreStats {
+    /// The size of each tree, by tree name.
+    pub trees: BTreeMap<String, TreeSize>,
+    /// The number of indexed properties.
+    pub indexed_properties: u64,
+}
+
+/// The size of a datastore on disk along with the number of entries in its
+/// trees, as returned by `SledDatastore::storage_stats` and
+/// `SledDatastore::storage_stats_exact`.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct StorageStats {
+    /// The size of the database on disk, in bytes.
+    pub size_on_disk: u64,
+    /// The number of entries in each tree, by tree name. Approximate stats
+    /// only have the trees whose size is known without a scan.
+    pub entries: BTreeMap<String, u64>,
+    /// Whether every tree was counted.
+    pub exact: bool,
+}
+
+/// What `SledDatastore::rebuild_edge_ranges` changed in the range trees.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RebuildStats {
+    /// The number of range entries added for edges that were missing one.
+    pub added: u64,
+    /// The number of range entries removed because their edge doesn't exist.
+    pub removed: u64,
+}
+
+/// The size of a datastore before and after `SledDatastore::compact`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CompactStats {
+    /// The size of the database on disk before compacting, in bytes.
+    pub bytes_before: u64,
+    /// The size of the database on disk after compacting, in bytes.
+    pub bytes_after: u64,
+}
+
+impl CompactStats {
+    /// The number of bytes freed.
+    pub fn reclaimed(&self) -> u64 {
+        self.bytes_before.saturating_sub(self.bytes_after)
+    }
+}
+
+/// The state a datastore was found in when opened, as returned by
+/// `SledConfig::open_with_report`.
+#[derive(Clone, Debug, Serialize)]
+pub struct RecoveryReport {
+    /// Whether sled recovered existing data, rather than creating a new
+    /// database.
+    pub was_recovered: bool,
+    /// The number of keys in each tree, by tree name.
+    pub tree_keys: BTreeMap<String, u64>,
+    /// The number of indexed properties, or why they couldn't be loaded
+    /// from the metadata tree.
+    pub indexed_properties: std::result::Result<u64, String>,
 }
 
 /// The meat of a Sled datastore
 pub struct SledHolder {
-    pub(crate) db: Db, // Derefs to Tree, holds the vertices
+    pub(crate) db: Db,
+    pub(crate) vertices: Tree,
     pub(crate) edges: Tree,
     pub(crate) edge_ranges: Tree,
     pub(crate) reversed_edge_ranges: Tree,
+    // for type -> edge prefix-indexed lookup
+    pub(crate) edge_types: Tree,
     pub(crate) vertex_properties: Tree,
     pub(crate) edge_properties: Tree,
     // for prop-name -> value -> ID prefix-indexed lookup
@@ -53,6 +461,28 @@ pub struct SledHolder {
     // for prop-name -> value -> UUID prefix-indexed lookup
     pub(crate) vertex_property_values: Tree,
     pub(crate) metadata: Tree,
+    pub(crate) is_read_only: bool,
+    // Whether edges are written to `reversed_edge_ranges`, see
+    // `SledConfig::maintain_reversed_edges`
+    pub(crate) reversed_edges: bool,
+    // Whether property values are written to the value index trees, see
+    // `SledConfig::maintain_property_value_index`
+    pub(crate) property_value_index: bool,
+    pub(crate) property_encoding: PropertyEncoding,
+    // See `SledConfig::with_value_codec`
+    pub(crate) value_codec: Option<Arc<dyn ValueCodec>>,
+    // See `SledConfig::flush_on_drop` and `SledConfig::on_drop_error`
+    pub(crate) flush_on_drop: bool,
+    pub(crate) drop_error_callback: Option<DropErrorCallback>,
+    // Wakes the thread serving `SledTransaction::request_flush`, started on
+    // the first request. The thread exits once the holder is dropped.
+    flush_requests: OnceLock<SyncSender<()>>,
+    // The path the database was opened from, if it was opened from a path
+    // by this crate
+    pub(crate) path: Option<PathBuf>,
+    // The config the datastore was opened with, the default one if it was
+    // opened without a config
+    pub(crate) config: SledConfig,
 }
 
 impl SledHolder {
@@ -62,30 +492,341 @@ impl SledHolder {
     /// * `path`: The file path to the Sled database.
     /// * `opts`: Sled options to pass in.
     pub fn new<P: AsRef<Path>>(path: P, opts: SledConfig) -> Result<SledHolder> {
-        let mut config = Config::default().path(path);
+        let db = open_db(&opts.to_sled_config(&path))?;
+        let mut holder = match opts.graph_name {
+            Some(ref name) => SledHolder::open_with_prefix(db, &graph_prefix(name)?, opts.read_only)?,
+            None => {
+                // Vertices live in the default tree of a database owned by the datastore
+                let vertices = Tree::clone(&db);
+                SledHolder::with_vertices(db, vertices, "", opts.read_only)?
+            }
+        };
+        holder.path = Some(path.as_ref().to_path_buf());
+        holder.apply_config(opts)?;
+        Ok(holder)
+    }
 
-        if opts.use_compression {
-            config = config.use_compression(true);
+    /// Creates a holder on top of an already opened sled database. Vertices
+    /// are kept in a dedicated "vertices" tree rather than the default tree,
+    /// so the default tree stays free for the caller's own keys.
+    ///
+    /// # Arguments
+    /// * `db`: The sled database to use.
+    pub fn open_with_db(db: Db) -> Result<SledHolder> {
+        SledHolder::open_with_config(db, SledConfig::default())
+    }
+
+    /// Creates a holder for the graph called `name` inside an already opened
+    /// sled database. All of its trees are prefixed with "`name`.", see
+    /// `SledConfig::graph_name`.
+    ///
+    /// # Arguments
+    /// * `db`: The sled database to use.
+    /// * `name`: The name of the graph.
+    pub fn open_graph_with_db(db: Db, name: &str) -> Result<SledHolder> {
+        SledHolder::open_with_config(db, SledConfig::default().graph_name(name))
+    }
+
+    /// Opens the holder's trees in `db` under the graph name of `opts`, if
+    /// any, and applies the rest of the options that don't configure sled
+    /// itself.
+    fn open_with_config(db: Db, opts: SledConfig) -> Result<SledHolder> {
+        let prefix = match opts.graph_name {
+            Some(ref name) => graph_prefix(name)?,
+            None => String::new(),
+        };
+        let mut holder = SledHolder::open_with_prefix(db, &prefix, opts.read_only)?;
+        holder.apply_config(opts)?;
+        Ok(holder)
+    }
+
+    /// Applies the options of `opts` that concern the holder rather than
+    /// sled, and keeps `opts` as the holder's config.
+    fn apply_config(&mut self, opts: SledConfig) -> Result<()> {
+        self.value_codec = opts.value_codec.clone();
+        self.flush_on_drop = !opts.skip_flush_on_drop;
+        self.drop_error_callback = opts.drop_error_callback.clone();
+        self.set_property_encoding(opts.property_encoding)?;
+        if let Some(maintain) = opts.reversed_edges {
+            self.set_reversed_edges(maintain)?;
+        }
+        if let Some(maintain) = opts.property_value_index {
+            self.set_property_value_index(maintain)?;
+        }
+        self.config = opts;
+        Ok(())
+    }
+
+    /// Queues a flush on the holder's flush thread, starting the thread if
+    /// needed. Does nothing if a flush is already queued.
+    pub(crate) fn request_flush(&self) {
+        let sender = self.flush_requests.get_or_init(|| {
+            let (sender, receiver) = mpsc::sync_channel(1);
+            let db = self.db.clone();
+            thread::spawn(move || {
+                for () in receiver {
+                    let _ = timed!(Flush, db.flush());
+                }
+            });
+            sender
+        });
+        let _ = sender.try_send(());
+    }
+
+    fn open_with_prefix(db: Db, prefix: &str, read_only: bool) -> Result<SledHolder> {
+        let vertices = map_err(db.open_tree(format!("{}vertices", prefix)))?;
+        SledHolder::with_vertices(db, vertices, prefix, read_only)
+    }
+
+    /// The trees holding the datastore's data, along with the names they are
+    /// known by in snapshots.
+    pub(crate) fn named_trees(&self) -> [(&'static str, &Tree); 10] {
+        [
+            ("vertices", &self.vertices),
+            ("edges", &self.edges),
+            ("edge_ranges", &self.edge_ranges),
+            ("reversed_edge_ranges", &self.reversed_edge_ranges),
+            ("edge_types", &self.edge_types),
+            ("vertex_properties", &self.vertex_properties),
+            ("edge_properties", &self.edge_properties),
+            ("vertex_property_values", &self.vertex_property_values),
+            ("edge_property_values", &self.edge_property_values),
+            ("metadata", &self.metadata),
+        ]
+    }
+
+    /// Opens a transaction over the holder's trees.
+    pub(crate) fn transaction(&self) -> SledTransaction<'_> {
+        SledTransaction {
+            holder: self,
+            vertex_manager: VertexManager::new(self),
+            edge_manager: EdgeManager::new(self),
+            edge_range_manager: EdgeRangeManager::new(self),
+            edge_range_manager_rev: EdgeRangeManager::new_reversed(self),
+            edge_property_manager: self.edge_property_manager(),
+            vertex_property_manager: self.vertex_property_manager(),
+            meta_data_manager: MetaDataManager::new(&self.metadata).unwrap(),
         }
+    }
 
-        if let Some(compression_factor) = opts.compression_factor {
-            config = config.compression_factor(compression_factor);
+    /// How the holder's property values are stored.
+    pub(crate) fn property_codec(&self) -> PropertyCodec {
+        PropertyCodec {
+            encoding: self.property_encoding,
+            value_codec: self.value_codec.clone(),
         }
+    }
 
-        let db = map_err(config.open())?;
+    /// A manager of the vertex properties that follows the holder's
+    /// property settings.
+    pub(crate) fn vertex_property_manager(&self) -> VertexPropertyManager<'_> {
+        VertexPropertyManager::new(&self.vertex_properties, &self.vertex_property_values)
+            .with_value_index(self.property_value_index)
+            .with_codec(self.property_codec())
+    }
 
-        Ok(SledHolder {
-            edges: map_err(db.open_tree("edges"))?,
-            edge_ranges: map_err(db.open_tree("edge_ranges"))?,
-            reversed_edge_ranges: map_err(db.open_tree("reversed_edge_ranges"))?,
-            vertex_properties: map_err(db.open_tree("vertex_properties"))?,
-            edge_properties: map_err(db.open_tree("edge_properties"))?,
-            vertex_property_values: map_err(db.open_tree("vertex_property_values"))?,
-            edge_property_values: map_err(db.open_tree("edge_property_values"))?,
-            metadata: map_err(db.open_tree("metadata"))?,
+    /// A manager of the edge properties that follows the holder's property
+    /// settings.
+    pub(crate) fn edge_property_manager(&self) -> EdgePropertyManager<'_> {
+        EdgePropertyManager::new(&self.edge_properties, &self.edge_property_values)
+            .with_value_index(self.property_value_index)
+            .with_codec(self.property_codec())
+    }
+
+    fn with_vertices(db: Db, vertices: Tree, prefix: &str, read_only: bool) -> Result<SledHolder> {
+        let open_tree = |name: &str| map_err(db.open_tree(format!("{}{}", prefix, name)));
+        let mut holder = SledHolder {
+            vertices,
+            edges: open_tree("edges")?,
+            edge_ranges: open_tree("edge_ranges")?,
+            reversed_edge_ranges: open_tree("reversed_edge_ranges")?,
+            edge_types: open_tree("edge_types")?,
+            vertex_properties: open_tree("vertex_properties")?,
+            edge_properties: open_tree("edge_properties")?,
+            vertex_property_values: open_tree("vertex_property_values")?,
+            edge_property_values: open_tree("edge_property_values")?,
+            metadata: open_tree("metadata")?,
+            is_read_only: read_only,
+            reversed_edges: true,
+            property_value_index: true,
+            property_encoding: PropertyEncoding::Json,
+            value_codec: None,
+            flush_on_drop: true,
+            drop_error_callback: None,
+            flush_requests: OnceLock::new(),
+            path: None,
+            config: SledConfig::default(),
             db,
-        })
+        };
+        migrations::prepare(&holder)?;
+        holder.reversed_edges = holder.stored_flag(REVERSED_EDGES_KEY)?;
+        holder.property_value_index = holder.stored_flag(PROPERTY_VALUE_INDEX_KEY)?;
+        if let Some(encoding) = holder.stored_encoding()? {
+            holder.property_encoding = encoding;
+        }
+        Ok(holder)
+    }
+
+    /// Reads the recorded property encoding, if there is one.
+    fn stored_encoding(&self) -> Result<Option<PropertyEncoding>> {
+        let Some(stored) = map_err(timed!(Get, self.metadata.get(flag_key(PROPERTY_ENCODING_KEY)?)))? else {
+            return Ok(None);
+        };
+        let id = decode_count(&stored);
+        match PropertyEncoding::from_id(id) {
+            Some(encoding) => Ok(Some(encoding)),
+            None => Err(DSError::PropertyEncoding(format!("unknown property encoding {}", id)).into()),
+        }
+    }
+
+    fn store_encoding(&self) -> Result<()> {
+        let value = encode_count(self.property_encoding.id());
+        map_err(timed!(
+            Insert,
+            self.metadata.insert(flag_key(PROPERTY_ENCODING_KEY)?, &value)
+        ))?;
+        Ok(())
+    }
+
+    /// Checks the configured property encoding against the store's. A store
+    /// without properties takes the configured encoding instead, unless the
+    /// holder is read-only.
+    fn set_property_encoding(&mut self, configured: Option<PropertyEncoding>) -> Result<()> {
+        let Some(configured) = configured else {
+            return Ok(());
+        };
+        if configured == self.property_encoding {
+            return Ok(());
+        }
+        let unused = self.vertex_properties.is_empty() && self.edge_properties.is_empty();
+        if !unused || self.is_read_only {
+            return Err(DSError::PropertyEncodingMismatch {
+                stored: self.property_encoding,
+                configured,
+            }
+            .into());
+        }
+        self.property_encoding = configured;
+        self.store_encoding()
+    }
+
+    /// Reads an on/off setting from the metadata. Stores that predate the
+    /// setting have it on.
+    fn stored_flag(&self, name: &str) -> Result<bool> {
+        let stored = map_err(timed!(Get, self.metadata.get(flag_key(name)?)))?;
+        Ok(stored.is_none_or(|value| decode_count(&value) != 0))
+    }
+
+    fn store_flag(&self, name: &str, value: bool) -> Result<()> {
+        map_err(timed!(
+            Insert,
+            self.metadata.insert(flag_key(name)?, &encode_count(value as u64))
+        ))?;
+        Ok(())
+    }
+
+    /// Empties every tree of the datastore, including the metadata and the
+    /// counters kept in it, then re-stamps the settings and flushes.
+    pub(crate) fn clear(&self) -> Result<()> {
+        for (_, tree) in self.named_trees() {
+            map_err(tree.clear())?;
+        }
+        self.store_settings()?;
+        map_err(self.db.flush())?;
+        Ok(())
+    }
+
+    /// Writes the settings that differ from what a store without them is
+    /// taken to have, e.g. after the metadata has been cleared.
+    pub(crate) fn store_settings(&self) -> Result<()> {
+        if !self.reversed_edges {
+            self.store_flag(REVERSED_EDGES_KEY, false)?;
+        }
+        if !self.property_value_index {
+            self.store_flag(PROPERTY_VALUE_INDEX_KEY, false)?;
+        }
+        if self.property_encoding != PropertyEncoding::Json {
+            self.store_encoding()?;
+        }
+        Ok(())
+    }
+
+    /// Turns the maintenance of the reversed edge ranges on or off, building
+    /// them from the edges or clearing them if the setting changes. Read-only
+    /// holders keep the stored setting.
+    fn set_reversed_edges(&mut self, maintain: bool) -> Result<()> {
+        if maintain == self.reversed_edges || self.is_read_only {
+            return Ok(());
+        }
+        if maintain {
+            EdgeRangeManager::new_reversed(self).rebuild(&EdgeManager::new(self), true)?;
+            self.reversed_edges = true;
+            self.store_flag(REVERSED_EDGES_KEY, true)
+        } else {
+            // Stored first, so that an interrupted clear isn't mistaken for
+            // an up to date index
+            self.reversed_edges = false;
+            self.store_flag(REVERSED_EDGES_KEY, false)?;
+            map_err(self.reversed_edge_ranges.clear())
+        }
     }
+
+    /// Turns the maintenance of the property value indexes on or off,
+    /// rebuilding them from the properties or clearing them if the setting
+    /// changes. Read-only holders keep the stored setting.
+    fn set_property_value_index(&mut self, maintain: bool) -> Result<()> {
+        if maintain == self.property_value_index || self.is_read_only {
+            return Ok(());
+        }
+        if maintain {
+            self.vertex_property_manager().rebuild_value_index(None)?;
+            self.edge_property_manager().rebuild_value_index(None)?;
+            self.property_value_index = true;
+            self.store_flag(PROPERTY_VALUE_INDEX_KEY, true)
+        } else {
+            // Stored first, like the reversed edge ranges
+            self.property_value_index = false;
+            self.store_flag(PROPERTY_VALUE_INDEX_KEY, false)?;
+            map_err(self.vertex_property_values.clear())?;
+            map_err(self.edge_property_values.clear())
+        }
+    }
+}
+
+/// The prefix of the tree names of the graph called `name`. The datastore's
+/// own tree names have no ".", so rejecting names with one means every tree
+/// name maps back to exactly one graph.
+fn graph_prefix(name: &str) -> Result<String> {
+    if name.is_empty() || name.contains('.') {
+        return Err(DSError::InvalidGraphName(name.to_string()).into());
+    }
+    Ok(format!("{}.", name))
+}
+
+/// How long opening a database waits for sled's file lock. Sled's
+/// background threads can keep a dropped database's lock for a moment, so
+/// reopening a path right after dropping its datastore, as `restore_from` and
+/// `compact` do, could otherwise fail.
+const LOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Opens the database, retrying while its lock is held until `LOCK_TIMEOUT`
+/// has passed.
+fn open_db(config: &Config) -> Result<Db> {
+    let start = Instant::now();
+    loop {
+        match config.open() {
+            Err(sled::Error::Io(ref err))
+                if err.to_string().starts_with("could not acquire lock") && start.elapsed() < LOCK_TIMEOUT =>
+            {
+                thread::sleep(Duration::from_millis(10));
+            }
+            result => return map_err(result),
+        }
+    }
+}
+
+fn flag_key(name: &str) -> Result<Vec<u8>> {
+    Ok(util::build(&[util::Component::Identifier(Identifier::new(name)?)]))
 }
 
 /// A datastore that is backed by Sled.
@@ -103,6 +844,645 @@ impl SledDatastore {
             holder: SledHolder::new(path, SledConfig::default())?,
         })
     }
+
+    /// Creates a new Sled datastore in a fresh directory under the system's
+    /// temporary directory, which is deleted once the datastore is dropped.
+    pub fn new_temporary() -> Result<SledDatastore> {
+        let path = env::temp_dir().join(format!("indradb-sled-{}", util::generate_uuid_v1()));
+        SledConfig::default().temporary().open(path)
+    }
+
+    /// Creates a new Sled datastore inside an existing sled database, next to
+    /// the caller's own trees.
+    ///
+    /// Vertices are stored in a "vertices" tree instead of the default tree,
+    /// so a database populated through `new` can't be reopened this way.
+    ///
+    /// # Arguments
+    /// * `db`: The sled database to use.
+    pub fn open_with_db(db: Db) -> Result<SledDatastore> {
+        Ok(SledDatastore {
+            holder: SledHolder::open_with_db(db)?,
+        })
+    }
+
+    /// Opens the graph called `name` in the sled database at `path`. See
+    /// `SledConfig::graph_name`.
+    ///
+    /// Sled locks its directory, so graphs sharing a path within one process
+    /// have to be opened with `open_graph_with_db` on a shared `sled::Db`.
+    ///
+    /// # Arguments
+    /// * `path`: The file path to the Sled database.
+    /// * `name`: The name of the graph.
+    pub fn open_graph<P: AsRef<Path>>(path: P, name: &str) -> Result<SledDatastore> {
+        SledConfig::default().graph_name(name).open(path)
+    }
+
+    /// Opens the graph called `name` inside an existing sled database, which
+    /// may hold other graphs as well.
+    ///
+    /// # Arguments
+    /// * `db`: The sled database to use.
+    /// * `name`: The name of the graph.
+    pub fn open_graph_with_db(db: Db, name: &str) -> Result<SledDatastore> {
+        Ok(SledDatastore {
+            holder: SledHolder::open_graph_with_db(db, name)?,
+        })
+    }
+
+    /// Gets the path the datastore was opened from, or `None` if it was
+    /// opened inside an existing sled database.
+    pub fn path(&self) -> Option<&Path> {
+        self.holder.path.as_deref()
+    }
+
+    /// Gets the config the datastore was opened with. Datastores opened
+    /// without one, e.g. with `new` or `open_with_db`, have the default
+    /// config, with the graph name for `open_graph_with_db`.
+    pub fn config(&self) -> &SledConfig {
+        &self.holder.config
+    }
+
+    /// Runs `f` against a fresh transaction. The transaction can't escape the
+    /// closure, which makes this convenient for callers that can't carry the
+    /// transaction's lifetime around.
+    ///
+    /// # Arguments
+    /// * `f`: The function to run.
+    pub fn with_transaction<R>(&self, f: impl FnOnce(&mut SledTransaction<'_>) -> R) -> R {
+        let mut transaction = self.transaction();
+        f(&mut transaction)
+    }
+
+    /// Starts a transaction that stages its writes in memory until it's
+    /// committed, and then applies them atomically. See `BatchedTransaction`.
+    pub fn batched_transaction(&self) -> BatchedTransaction<'_> {
+        BatchedTransaction::new(&self.holder)
+    }
+
+    /// Subscribes to the changes of one vertex. Only changes made after this
+    /// returns are seen.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex to watch.
+    pub fn watch_vertex(&self, id: Uuid) -> VertexWatcher {
+        VertexWatcher {
+            subscriber: self
+                .holder
+                .vertices
+                .watch_prefix(VertexManager::new(&self.holder).key(id)),
+        }
+    }
+
+    /// Subscribes to the changes of every vertex. Only changes made after
+    /// this returns are seen.
+    pub fn watch_all_vertices(&self) -> VertexWatcher {
+        VertexWatcher {
+            subscriber: self.holder.vertices.watch_prefix(Vec::new()),
+        }
+    }
+
+    /// Writes a consistent snapshot of every tree to `writer`, after flushing
+    /// the database. The snapshot can be loaded back with `import_snapshot`.
+    ///
+    /// # Arguments
+    /// * `writer`: Where to write the snapshot.
+    pub fn export_snapshot<W: Write>(&self, writer: W) -> Result<()> {
+        snapshot::export(&self.holder, writer)
+    }
+
+    /// Replaces the contents of the datastore with a snapshot written by
+    /// `export_snapshot`. Existing data is cleared first.
+    ///
+    /// # Arguments
+    /// * `reader`: Where to read the snapshot from.
+    pub fn import_snapshot<R: Read>(&self, reader: R) -> Result<()> {
+        snapshot::import(&self.holder, reader)
+    }
+
+    /// Copies every tree, including the indexed-property metadata, into a
+    /// new sled database at `path`, which can then be opened with `new`.
+    /// `path` must not exist yet or be an empty directory. The datastore
+    /// stays usable during the backup, but a write made while it runs may or
+    /// may not be part of the copy.
+    ///
+    /// # Arguments
+    /// * `path`: The file path of the backup database.
+    pub fn backup_to<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        check_empty_target(path.as_ref())?;
+        let target = SledHolder::new(path, SledConfig::default())?;
+        snapshot::backup(&self.holder, &target)
+    }
+
+    /// Restores a backup written by `backup_to` into a new datastore at
+    /// `target`, which must not exist yet or be an empty directory. Backups
+    /// that were cut short are rejected.
+    ///
+    /// # Arguments
+    /// * `backup`: The file path of the backup database.
+    /// * `target`: The file path of the restored database.
+    pub fn restore_from<P: AsRef<Path>, Q: AsRef<Path>>(backup: P, target: Q) -> Result<SledDatastore> {
+        let (backup, target) = (backup.as_ref(), target.as_ref());
+        check_restore_paths(backup, target)?;
+        let backup = SledHolder::new(backup, SledConfig::default().read_only())?;
+        snapshot::verify_backup(&backup)?;
+        {
+            let datastore = SledDatastore::new(target)?;
+            snapshot::restore(&backup, &datastore.holder)?;
+        }
+        // Reopened so that the settings restored into the metadata apply
+        SledDatastore::new(target)
+    }
+
+    /// Same as `backup_to`.
+    ///
+    /// # Arguments
+    /// * `dest`: The file path of the backup database.
+    pub fn backup<P: AsRef<Path>>(&self, dest: P) -> Result<()> {
+        self.backup_to(dest)
+    }
+
+    /// Same as `restore_from`: the backup is checked for completeness before
+    /// anything is copied out of it.
+    ///
+    /// # Arguments
+    /// * `src`: The file path of the backup database.
+    /// * `dest`: The file path of the restored database.
+    pub fn restore<P: AsRef<Path>>(src: P, dest: P) -> Result<SledDatastore> {
+        SledDatastore::restore_from(src, dest)
+    }
+
+    /// Copies every tree of the datastore into a new database at `dest`, like
+    /// `backup_to`, but opens the copy with the config this datastore was
+    /// opened with, so that options such as compression, the graph name and
+    /// the property encoding carry over, and returns it. A temporary
+    /// datastore's copy isn't temporary. The copy can later be reopened from
+    /// `dest` with the same config. Writes made while the checkpoint is taken
+    /// may be only partly in the copy.
+    ///
+    /// Only datastores opened from a path by this crate can be checkpointed,
+    /// since the others don't keep their vertices where `open` looks for
+    /// them.
+    ///
+    /// # Arguments
+    /// * `dest`: The file path of the checkpoint.
+    pub fn checkpoint<P: AsRef<Path>>(&self, dest: P) -> Result<SledDatastore> {
+        if self.holder.path.is_none() {
+            return Err(
+                DSError::Unsupported("only datastores opened from a path can be checkpointed".to_string()).into(),
+            );
+        }
+        let mut config = self.holder.config.clone();
+        config.temporary = false;
+        let dest = dest.as_ref();
+        check_empty_target(dest)?;
+        self.holder.transaction().sync()?;
+        {
+            let writable = SledConfig {
+                read_only: false,
+                ..config.clone()
+            };
+            let target = SledHolder::new(dest, writable)?;
+            snapshot::copy(&self.holder, &target)?;
+        }
+        config.open(dest)
+    }
+
+    /// Writes the whole graph, including properties, as a JSON document. The
+    /// document is streamed to `writer` rather than built in memory.
+    ///
+    /// # Arguments
+    /// * `writer`: Where to write the document.
+    pub fn export_json<W: Write>(&self, writer: W) -> Result<()> {
+        json_export::export(self, writer)
+    }
+
+    /// Creates the vertices, edges and properties of a document written by
+    /// `export_json`. Existing data is kept.
+    ///
+    /// # Arguments
+    /// * `reader`: Where to read the document from.
+    pub fn import_json<R: Read>(&self, reader: R) -> Result<()> {
+        json_export::import(self, reader)
+    }
+
+    /// Removes all vertices, edges, properties and indexes, then flushes.
+    /// Property indexes have to be recreated afterwards. Trees that aren't
+    /// part of the datastore, such as a caller's own trees next to an
+    /// `open_with_db` datastore, are left alone.
+    pub fn clear(&self) -> Result<()> {
+        if self.holder.is_read_only {
+            return Err(DSError::ReadOnly.into());
+        }
+        self.holder.clear()
+    }
+
+    /// Streams the whole graph as newline-delimited JSON: vertices, then
+    /// edges, then vertex properties, then edge properties, one record per
+    /// line with a `type` field.
+    ///
+    /// The stream is canonical: vertices are ordered by id, edges by
+    /// outbound id, type and inbound id, properties by owner and then name,
+    /// and the keys of JSON objects are sorted. Exports of the same graph are
+    /// byte-identical, however it was written.
+    ///
+    /// # Arguments
+    /// * `writer`: Where to write the stream.
+    pub fn export_ndjson<W: Write>(&self, writer: &mut W) -> Result<()> {
+        json_export::export_ndjson(self, writer)
+    }
+
+    /// Computes the SHA-256 hash of the stream `export_ndjson` would write,
+    /// without writing it anywhere. Stores holding the same graph have the
+    /// same export checksum, unlike `checksum`, which also covers indexes and
+    /// metadata.
+    pub fn export_checksum(&self) -> Result<[u8; 32]> {
+        json_export::export_checksum(self)
+    }
+
+    /// Loads a stream written by `export_ndjson` using bulk inserts.
+    /// Existing data is kept. See `import_from_reader`.
+    ///
+    /// # Arguments
+    /// * `reader`: Where to read the stream from.
+    pub fn import_ndjson<R: Read>(&self, reader: R) -> Result<()> {
+        self.import_from_reader(reader)?;
+        Ok(())
+    }
+
+    /// Like `export_ndjson`, but takes the writer by value. Nothing is
+    /// buffered beyond a single record.
+    ///
+    /// # Arguments
+    /// * `writer`: Where to write the stream to.
+    pub fn export_to_writer<W: Write>(&self, writer: W) -> Result<()> {
+        json_export::export_ndjson(self, writer)
+    }
+
+    /// Loads a stream written by `export_ndjson` or `export_to_writer` using
+    /// bulk inserts of ten thousand records each, then flushes. Existing
+    /// data is kept. A malformed line aborts the import with an error naming
+    /// the line.
+    ///
+    /// # Arguments
+    /// * `reader`: Where to read the stream from.
+    pub fn import_from_reader<R: Read>(&self, reader: R) -> Result<ImportStats> {
+        self.import_from_reader_in_chunks(reader, json_export::NDJSON_IMPORT_CHUNK_SIZE)
+    }
+
+    /// Like `import_from_reader`, but with the number of records passed to
+    /// each bulk insert set by the caller.
+    ///
+    /// # Arguments
+    /// * `reader`: Where to read the stream from.
+    /// * `chunk_size`: The number of records per bulk insert.
+    pub fn import_from_reader_in_chunks<R: Read>(&self, reader: R, chunk_size: usize) -> Result<ImportStats> {
+        json_export::import_ndjson(self, reader, chunk_size)
+    }
+
+    /// Whether sled recovered existing data when the database was opened,
+    /// rather than creating a new one.
+    pub fn was_recovered(&self) -> bool {
+        self.holder.db.was_recovered()
+    }
+
+    fn recovery_report(&self) -> Result<RecoveryReport> {
+        let tree_keys = self
+            .holder
+            .named_trees()
+            .into_iter()
+            .map(|(name, tree)| (name.to_string(), managers::count_keys(tree)))
+            .collect();
+        let indexed_properties = MetaDataManager::new(&self.holder.metadata)
+            .and_then(|manager| manager.indexed_properties())
+            .map(|properties| properties.len() as u64)
+            .map_err(|err| err.to_string());
+        Ok(RecoveryReport {
+            was_recovered: self.was_recovered(),
+            tree_keys,
+            indexed_properties,
+        })
+    }
+
+    /// Persists the counters and indexed properties, then flushes the
+    /// database without blocking the calling thread, like an awaitable
+    /// `Transaction::sync`. Writes are only durable once the returned future
+    /// has completed successfully.
+    #[cfg(feature = "async")]
+    pub async fn flush_async(&self) -> Result<()> {
+        self.holder.transaction().sync_async().await
+    }
+
+    /// Gets the size of the database on disk, in bytes.
+    pub fn size_on_disk(&self) -> Result<u64> {
+        map_err(self.holder.db.size_on_disk())
+    }
+
+    /// Gets the size of the database on disk along with the number of entries
+    /// in the trees whose size is known without a scan. These are the edge
+    /// trees, whose counts are taken from the per-type edge counters, so
+    /// they can be off if the trees drifted apart after a crash. Use
+    /// `storage_stats_exact` to count every tree.
+    pub fn storage_stats(&self) -> Result<StorageStats> {
+        let edges = EdgeManager::new(&self.holder).count_from_type_counters()?;
+        let mut entries = BTreeMap::new();
+        for name in ["edges", "edge_ranges", "edge_types"] {
+            entries.insert(name.to_string(), edges);
+        }
+        let reversed = if self.holder.reversed_edges { edges } else { 0 };
+        entries.insert("reversed_edge_ranges".to_string(), reversed);
+        Ok(StorageStats {
+            size_on_disk: self.size_on_disk()?,
+            entries,
+            exact: false,
+        })
+    }
+
+    /// Like `storage_stats`, but counts the entries of every tree. Every tree
+    /// is scanned.
+    pub fn storage_stats_exact(&self) -> Result<StorageStats> {
+        let entries = self
+            .holder
+            .named_trees()
+            .into_iter()
+            .map(|(name, tree)| (name.to_string(), managers::count_keys(tree)))
+            .collect();
+        Ok(StorageStats {
+            size_on_disk: self.size_on_disk()?,
+            entries,
+            exact: true,
+        })
+    }
+
+    /// Reclaims the space left behind by deleted data and reports the size of
+    /// the database before and after. Sled has no compaction routine of its
+    /// own, so this copies every tree into a fresh database next to the
+    /// original, swaps the two directories and reopens the datastore. It
+    /// takes `&mut self` because the database has to be closed for the swap,
+    /// which can't happen while a transaction borrows it. A datastore shared
+    /// through `&` or `Arc` can use `reclaim` instead.
+    ///
+    /// This takes time and extra disk space proportional to the live data,
+    /// and only works for datastores opened from a non-temporary path by
+    /// this crate. Other handles to the same sled database must be closed
+    /// first. If any step fails, the original directory is put back and
+    /// reopened before the error is returned.
+    pub fn compact(&mut self) -> Result<CompactStats> {
+        if self.holder.is_read_only {
+            return Err(DSError::ReadOnly.into());
+        }
+        let (path, config) = match self.holder.path {
+            Some(ref path) if !self.holder.config.temporary => (path.clone(), self.holder.config.clone()),
+            _ => {
+                return Err(DSError::Unsupported(
+                    "only datastores opened from a non-temporary path can be compacted".to_string(),
+                )
+                .into())
+            }
+        };
+        let scratch = sibling_path(&path, ".compacting");
+        let old = sibling_path(&path, ".old");
+        for leftover in [&scratch, &old] {
+            if leftover.exists() {
+                // Left behind by an interrupted compaction
+                fs::remove_dir_all(leftover).map_err(DSError::from)?;
+            }
+        }
+
+        let bytes_before = self.size_on_disk()?;
+        let copied = SledHolder::new(&scratch, config.clone()).and_then(|target| snapshot::copy(&self.holder, &target));
+        if let Err(err) = copied {
+            let _ = fs::remove_dir_all(&scratch);
+            return Err(err);
+        }
+
+        // Close the original database before its directory is moved
+        let placeholder = SledHolder::open_with_db(map_err(Config::new().temporary(true).open())?)?;
+        drop(mem::replace(&mut self.holder, placeholder));
+        let swapped = swap_directories(&path, &scratch, &old).and_then(|()| SledHolder::new(&path, config.clone()));
+        match swapped {
+            Ok(holder) => {
+                self.holder = holder;
+                // The copy is in place, so failing to delete the original
+                // only leaves it for the next compaction to remove
+                let _ = fs::remove_dir_all(&old);
+            }
+            Err(err) => {
+                if old.exists() {
+                    if path.exists() {
+                        let _ = fs::rename(&path, &scratch);
+                    }
+                    fs::rename(&old, &path).map_err(DSError::from)?;
+                }
+                let _ = fs::remove_dir_all(&scratch);
+                self.holder = SledHolder::new(&path, config)?;
+                return Err(err);
+            }
+        }
+        Ok(CompactStats {
+            bytes_before,
+            bytes_after: self.size_on_disk()?,
+        })
+    }
+
+    /// Flushes the database, so that sled can rewrite and free the segments
+    /// left mostly empty by deleted data, and reports the size of the
+    /// database before and after. Unlike `compact`, this works on a shared
+    /// datastore, but sled frees space gradually in the background, so the
+    /// size may barely drop until later.
+    pub fn reclaim(&self) -> Result<CompactStats> {
+        let bytes_before = self.size_on_disk()?;
+        self.holder.transaction().sync()?;
+        Ok(CompactStats {
+            bytes_before,
+            bytes_after: self.size_on_disk()?,
+        })
+    }
+
+    /// Gets the approximate size of each of the datastore's trees. Every tree
+    /// is scanned, so this takes time proportional to the size of the
+    /// datastore.
+    pub fn tree_sizes(&self) -> Result<Vec<(String, TreeSize)>> {
+        let mut sizes = Vec::new();
+        for (name, tree) in self.holder.named_trees() {
+            sizes.push((name.to_string(), managers::tree_size(tree)?));
+        }
+        Ok(sizes)
+    }
+
+    /// Computes a checksum of every tree, by tree name, to find which tree
+    /// differs between two stores. Every tree is scanned.
+    pub fn tree_checksums(&self) -> Result<BTreeMap<String, u64>> {
+        let mut checksums = BTreeMap::new();
+        for (name, tree) in self.holder.named_trees() {
+            checksums.insert(name.to_string(), managers::tree_checksum(tree)?);
+        }
+        Ok(checksums)
+    }
+
+    /// Computes a checksum of the whole database, combining sled's own
+    /// checksum with those of `tree_checksums`. Stores holding the same data
+    /// have the same checksum. Sled locks every tree while it computes its
+    /// part, so writers are blocked for the duration of a full scan.
+    pub fn checksum(&self) -> Result<u64> {
+        let mut hash = managers::Fnv64::new();
+        hash.write(&map_err(self.holder.db.checksum())?.to_be_bytes());
+        for (name, checksum) in self.tree_checksums()? {
+            hash.write_chunk(name.as_bytes());
+            hash.write(&checksum.to_be_bytes());
+        }
+        Ok(hash.finish())
+    }
+
+    /// Checks that the derived trees agree with the primary trees: see
+    /// `SledTransaction::validate_integrity`. The report counts every
+    /// violation but only keeps a sample of them.
+    pub fn verify_integrity(&self) -> Result<IntegrityReport> {
+        self.transaction().validate_integrity()
+    }
+
+    /// Rebuilds the edge ranges and reversed edge ranges from the edges
+    /// tree, for when they've drifted apart after a crash. Rather than
+    /// clearing the range trees and refilling them, which would leave range
+    /// queries empty while it runs, only the entries that differ are written.
+    /// The returned stats cover both trees. The reversed edge ranges are
+    /// skipped when they aren't maintained.
+    pub fn rebuild_edge_ranges(&self) -> Result<RebuildStats> {
+        if self.holder.is_read_only {
+            return Err(DSError::ReadOnly.into());
+        }
+        let edge_manager = EdgeManager::new(&self.holder);
+        let forward = EdgeRangeManager::new(&self.holder).rebuild(&edge_manager, false)?;
+        let reversed = if self.holder.reversed_edges {
+            EdgeRangeManager::new_reversed(&self.holder).rebuild(&edge_manager, true)?
+        } else {
+            RebuildStats::default()
+        };
+        Ok(RebuildStats {
+            added: forward.added + reversed.added,
+            removed: forward.removed + reversed.removed,
+        })
+    }
+
+    /// Wipes the vertex and edge value indexes and writes them again from
+    /// the property trees, for when they've drifted apart after a crash.
+    /// With `name`, only that property's entries are rebuilt. Every entry is
+    /// rewritten, so the stats count all of them, not only those that were
+    /// wrong. Fails if the value indexes aren't maintained.
+    ///
+    /// # Arguments
+    /// * `name`: The property to rebuild the entries of, or `None` for all.
+    pub fn rebuild_property_indexes(&self, name: Option<Identifier>) -> Result<RebuildStats> {
+        if self.holder.is_read_only {
+            return Err(DSError::ReadOnly.into());
+        }
+        if !self.holder.property_value_index {
+            return Err(DSError::Unsupported(
+                "the property value indexes are disabled by SledConfig::maintain_property_value_index".to_string(),
+            )
+            .into());
+        }
+        let vertex_stats = self.holder.vertex_property_manager().rebuild_value_index(name)?;
+        let edge_stats = self.holder.edge_property_manager().rebuild_value_index(name)?;
+        Ok(RebuildStats {
+            added: vertex_stats.added + edge_stats.added,
+            removed: vertex_stats.removed + edge_stats.removed,
+        })
+    }
+
+    /// Gets per-tree sizes along with the number of indexed properties. Like
+    /// `tree_sizes`, this scans every tree.
+    pub fn stats(&self) -> Result<DatastoreStats> {
+        let meta_data_manager = MetaDataManager::new(&self.holder.metadata)?;
+        Ok(DatastoreStats {
+            trees: self.tree_sizes()?.into_iter().collect(),
+            indexed_properties: meta_data_manager.indexed_properties()?.len() as u64,
+        })
+    }
+
+    /// Returns a snapshot of the latency histograms for sled calls. The
+    /// histograms are process-wide, so they cover every datastore opened in
+    /// this process.
+    #[cfg(feature = "latency-metrics")]
+    pub fn latency_report(&self) -> LatencyReport {
+        latency::report()
+    }
+
+    /// Clears the process-wide latency histograms.
+    #[cfg(feature = "latency-metrics")]
+    pub fn reset_latency_report(&self) {
+        latency::reset()
+    }
+}
+
+/// Checks that `target` doesn't exist yet or is an empty directory, so that a
+/// backup or restore can't mix with existing data.
+fn check_empty_target(target: &Path) -> Result<()> {
+    if target.exists() && fs::read_dir(target).map_err(DSError::from)?.next().is_some() {
+        return Err(DSError::RestoreTargetNotEmpty(target.display().to_string()).into());
+    }
+    Ok(())
+}
+
+/// `path` with `suffix` appended to its last component.
+fn sibling_path(path: &Path, suffix: &str) -> PathBuf {
+    let mut sibling = path.as_os_str().to_owned();
+    sibling.push(suffix);
+    PathBuf::from(sibling)
+}
+
+/// Moves the directory at `path` to `old` and the one at `replacement` to
+/// `path`.
+fn swap_directories(path: &Path, replacement: &Path, old: &Path) -> Result<()> {
+    fs::rename(path, old).map_err(DSError::from)?;
+    fs::rename(replacement, path).map_err(DSError::from)?;
+    Ok(())
+}
+
+fn check_restore_paths(backup: &Path, target: &Path) -> Result<()> {
+    if !backup.is_dir() {
+        return Err(DSError::InvalidBackup(format!("no backup at {}", backup.display())).into());
+    }
+    check_empty_target(target)
+}
+
+// Shows where the datastore lives and how it's configured, but none of its
+// data
+impl fmt::Debug for SledDatastore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let trees: Vec<String> = self
+            .holder
+            .named_trees()
+            .iter()
+            .map(|(_, tree)| String::from_utf8_lossy(&tree.name()).into_owned())
+            .collect();
+        f.debug_struct("SledDatastore")
+            .field("path", &self.holder.path)
+            .field("use_compression", &self.holder.config.use_compression)
+            .field("compression_factor", &self.holder.config.compression_factor)
+            .field("read_only", &self.holder.is_read_only)
+            .field("trees", &trees)
+            .finish()
+    }
+}
+
+impl Drop for SledDatastore {
+    fn drop(&mut self) {
+        if !self.holder.flush_on_drop || self.holder.is_read_only {
+            return;
+        }
+        // Not `SledHolder::transaction`, which unwraps, as panicking in drop
+        // would abort an unwinding thread
+        let flushed = MetaDataManager::new(&self.holder.metadata)
+            .and_then(|manager| manager.sync())
+            .and_then(|()| map_err(timed!(Flush, self.holder.db.flush())).map(|_| ()));
+        if let Err(err) = flushed {
+            if let Some(DropErrorCallback(ref callback)) = self.holder.drop_error_callback {
+                callback(&err);
+            }
+        }
+    }
 }
 
 impl Datastore for SledDatastore {
@@ -111,21 +1491,884 @@ impl Datastore for SledDatastore {
         Self: 'a;
 
     fn transaction(&self) -> Self::Transaction<'_> {
-        SledTransaction {
-            holder: &self.holder,
-            vertex_manager: VertexManager::new(&self.holder),
-            edge_manager: EdgeManager::new(&self.holder),
-            edge_range_manager: EdgeRangeManager::new(&self.holder),
-            edge_range_manager_rev: EdgeRangeManager::new_reversed(&self.holder),
-            edge_property_manager: EdgePropertyManager::new(
-                &self.holder.edge_properties,
-                &self.holder.edge_property_values,
-            ),
-            vertex_property_manager: VertexPropertyManager::new(
-                &self.holder.vertex_properties,
-                &self.holder.vertex_property_values,
-            ),
-            meta_data_manager: MetaDataManager::new(&self.holder.metadata).unwrap(),
+        self.holder.transaction()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indradb::{BulkInsertItem, Datastore, Edge, Identifier, Json, Transaction, Vertex};
+    use serde_json::json;
+    use sled::Config;
+    use tempfile::tempdir;
+
+    use super::{RebuildStats, SledConfig, SledDatastore, Uuid};
+    use crate::integrity::{IntegrityViolation, INTEGRITY_SAMPLE_SIZE};
+    use crate::managers::vertex_property_manager::VertexPropertyManager;
+    use crate::reverse_edge;
+    use crate::transaction::SledTransaction;
+
+    #[test]
+    fn test_path_config_and_debug() {
+        let path = tempdir().unwrap().keep();
+        let ds = SledConfig::default()
+            .compression(Some(7))
+            .graph_name("tenant")
+            .open(&path)
+            .unwrap();
+        let mut txn = ds.transaction();
+        let v = Vertex::new(Identifier::new("person").unwrap());
+        txn.create_vertex(&v).unwrap();
+        txn.set_vertex_properties(
+            vec![v.id],
+            Identifier::new("secret").unwrap(),
+            &Json::new(json!("hunter2")),
+        )
+        .unwrap();
+
+        assert_eq!(ds.path(), Some(path.as_path()));
+        assert!(ds.config().use_compression);
+        assert_eq!(ds.config().compression_factor, Some(7));
+        let debug = format!("{:?}", ds);
+        assert!(debug.contains(&format!("{:?}", path)), "{}", debug);
+        assert!(debug.contains("compression_factor: Some(7)"), "{}", debug);
+        assert!(debug.contains("tenant.vertex_properties"), "{}", debug);
+        assert!(!debug.contains("hunter2"), "{}", debug);
+
+        let ds = SledDatastore::open_graph_with_db(Config::new().temporary(true).open().unwrap(), "other").unwrap();
+        assert_eq!(ds.path(), None);
+        assert_eq!(ds.config().graph_name.as_deref(), Some("other"));
+    }
+
+    #[test]
+    fn test_chained_config() {
+        let config = SledConfig::default().compression(Some(7)).cache_capacity(1 << 20);
+        let sled_config = config.to_sled_config(tempdir().unwrap().keep());
+        assert!(sled_config.use_compression);
+        assert_eq!(sled_config.compression_factor, 7);
+        assert_eq!(sled_config.cache_capacity, 1 << 20);
+
+        let ds = config.open(tempdir().unwrap().keep()).unwrap();
+        let mut txn = ds.transaction();
+        txn.create_vertex(&Vertex::new(Identifier::new("foo").unwrap()))
+            .unwrap();
+        assert_eq!(txn.vertex_count(), 1);
+    }
+
+    #[test]
+    fn test_segment_and_idgen_config() {
+        let path = tempdir().unwrap().keep();
+        let config = SledConfig::default()
+            .segment_size(1 << 20)
+            .idgen_persist_interval(10_000);
+        let sled_config = config.to_sled_config(&path);
+        assert_eq!(sled_config.segment_size, 1 << 20);
+        assert_eq!(sled_config.idgen_persist_interval, 10_000);
+
+        let v = Vertex::new(Identifier::new("foo").unwrap());
+        {
+            let ds = config.clone().open(&path).unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&v).unwrap();
+            txn.sync().unwrap();
+        }
+        let ds = config.open(&path).unwrap();
+        assert_eq!(ds.transaction().specific_vertices(vec![v.id]).unwrap().count(), 1);
+        drop(ds);
+        assert!(SledDatastore::new(&path).is_err());
+    }
+
+    #[test]
+    fn test_from_sled_config() {
+        let config = SledConfig::from_sled_config(Config::new().temporary(true).cache_capacity(1 << 16))
+            .compression(Some(3))
+            .cache_capacity(1 << 20);
+        let sled_config = config.to_sled_config(tempdir().unwrap().keep());
+        assert!(sled_config.temporary);
+        assert!(sled_config.use_compression);
+        assert_eq!(sled_config.compression_factor, 3);
+        assert_eq!(sled_config.cache_capacity, 1 << 16);
+
+        let ds = config.open(tempdir().unwrap().keep()).unwrap();
+        let mut txn = ds.transaction();
+        let v = Vertex::new(Identifier::new("foo").unwrap());
+        assert!(txn.create_vertex(&v).unwrap());
+        assert_eq!(txn.specific_vertices(vec![v.id]).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_flush_every_ms() {
+        let sled_config = SledConfig::default().flush_every_ms(0).to_sled_config("unused");
+        assert_eq!(sled_config.flush_every_ms, None);
+
+        let config = SledConfig::default().flush_every_ms(2_000);
+        assert_eq!(config.to_sled_config("unused").flush_every_ms, Some(2_000));
+
+        let ds = config.open(tempdir().unwrap().keep()).unwrap();
+        let mut txn = ds.transaction();
+        txn.create_vertex(&Vertex::new(Identifier::new("foo").unwrap()))
+            .unwrap();
+        txn.sync().unwrap();
+        assert_eq!(txn.vertex_count(), 1);
+    }
+
+    #[test]
+    fn test_open_with_db() {
+        let db = Config::new().path(tempdir().unwrap().keep()).open().unwrap();
+        let app_tree = db.open_tree("app").unwrap();
+        app_tree.insert(b"key", b"value").unwrap();
+        db.insert(b"default-key", b"value").unwrap();
+
+        let ds = SledDatastore::open_with_db(db.clone()).unwrap();
+        let mut txn = ds.transaction();
+        txn.create_vertex(&Vertex::new(Identifier::new("foo").unwrap()))
+            .unwrap();
+        assert_eq!(txn.vertex_count(), 1);
+        assert_eq!(txn.all_vertices().unwrap().count(), 1);
+
+        assert_eq!(app_tree.len(), 1);
+        assert_eq!(db.len(), 1);
+        assert_eq!(app_tree.get(b"key").unwrap().unwrap(), b"value");
+    }
+
+    #[test]
+    fn test_temporary() {
+        let path = tempdir().unwrap().keep();
+        {
+            let ds = SledConfig::default().temporary().open(&path).unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&Vertex::new(Identifier::new("foo").unwrap()))
+                .unwrap();
+            assert_eq!(txn.vertex_count(), 1);
+        }
+        let ds = SledConfig::default().temporary().open(&path).unwrap();
+        assert_eq!(ds.transaction().vertex_count(), 0);
+
+        let first = SledDatastore::new_temporary().unwrap();
+        let second = SledDatastore::new_temporary().unwrap();
+        first
+            .transaction()
+            .create_vertex(&Vertex::new(Identifier::new("foo").unwrap()))
+            .unwrap();
+        assert_eq!(first.transaction().vertex_count(), 1);
+        assert_eq!(second.transaction().vertex_count(), 0);
+    }
+
+    #[test]
+    fn test_sizes() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("foo").unwrap();
+        let vertices: Vec<Vertex> = (0..2_000).map(|_| Vertex::new(t)).collect();
+        for v in &vertices {
+            txn.create_vertex(v).unwrap();
+        }
+        for pair in vertices.windows(2) {
+            txn.create_edge(&Edge::new(pair[0].id, t, pair[1].id)).unwrap();
+        }
+        txn.sync().unwrap();
+
+        assert!(ds.size_on_disk().unwrap() > 0);
+        let sizes: std::collections::HashMap<_, _> = ds.tree_sizes().unwrap().into_iter().collect();
+        assert_eq!(sizes["vertices"].keys, 2_000);
+        assert_eq!(sizes["edges"].keys, 1_999);
+        assert_eq!(sizes["edge_ranges"], sizes["reversed_edge_ranges"]);
+        assert!(sizes["edge_ranges"].bytes > sizes["vertices"].bytes);
+        assert_eq!(sizes["vertex_properties"], Default::default());
+    }
+
+    #[test]
+    fn test_storage_stats() {
+        let ds = SledDatastore::new(tempdir().unwrap().keep()).unwrap();
+        let empty = ds.storage_stats_exact().unwrap();
+        assert!(empty.exact);
+        assert_eq!(empty.entries.len(), 10);
+        // Only the metadata tree, which holds the format version and settings,
+        // has entries
+        assert!(empty
+            .entries
+            .iter()
+            .all(|(name, &count)| name == "metadata" || count == 0));
+
+        let mut txn = ds.transaction();
+        let t = Identifier::new("foo").unwrap();
+        let vertices: Vec<Vertex> = (0..1_000).map(|_| Vertex::new(t)).collect();
+        for v in &vertices {
+            txn.create_vertex(v).unwrap();
+        }
+        for pair in vertices.windows(2) {
+            txn.create_edge(&Edge::new(pair[0].id, t, pair[1].id)).unwrap();
         }
+        txn.sync().unwrap();
+
+        let exact = ds.storage_stats_exact().unwrap();
+        assert!(exact.size_on_disk > empty.size_on_disk);
+        assert_eq!(exact.entries["vertices"], 1_000);
+        assert_eq!(exact.entries["edges"], 999);
+        assert_eq!(exact.entries["reversed_edge_ranges"], 999);
+
+        let approximate = ds.storage_stats().unwrap();
+        assert!(!approximate.exact);
+        assert!(approximate.size_on_disk > empty.size_on_disk);
+        assert!(!approximate.entries.contains_key("vertices"));
+        for (name, count) in approximate.entries.iter() {
+            assert_eq!(exact.entries[name], *count, "{}", name);
+        }
+    }
+
+    #[test]
+    fn test_graph_isolation() {
+        let db = Config::new().temporary(true).open().unwrap();
+        let first = SledDatastore::open_graph_with_db(db.clone(), "tenant1").unwrap();
+        let second = SledDatastore::open_graph_with_db(db.clone(), "tenant2").unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+
+        let mut txn = first.transaction();
+        txn.index_property(name).unwrap();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!("bob")))
+            .unwrap();
+        txn.set_edge_properties(vec![edge.clone()], name, &Json::new(json!("bob")))
+            .unwrap();
+
+        let txn = second.transaction();
+        assert_eq!(txn.vertex_count(), 0);
+        assert_eq!(txn.edge_count(), 0);
+        assert_eq!(txn.specific_vertices(vec![a.id]).unwrap().count(), 0);
+        assert_eq!(txn.vertex_property(&a, name).unwrap(), None);
+        assert_eq!(txn.edge_property(&edge, name).unwrap(), None);
+        assert!(txn.vertex_ids_with_property(name).unwrap().is_none());
+
+        let txn = first.transaction();
+        assert_eq!(txn.vertex_count(), 2);
+        assert_eq!(txn.edge_count(), 1);
+        assert!(db.tree_names().iter().any(|tree| *tree == "tenant1.vertices"));
+        assert!(db.tree_names().iter().any(|tree| *tree == "tenant2.edge_ranges"));
+        assert!(db.is_empty());
+    }
+
+    #[test]
+    fn test_namespace() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("foo").unwrap();
+        let namespaced = Vertex::new(t);
+        let plain = Vertex::new(t);
+        {
+            let ds = SledConfig::default().namespace("graph").open(&path).unwrap();
+            ds.transaction().create_vertex(&namespaced).unwrap();
+            ds.transaction().sync().unwrap();
+        }
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            let mut txn = ds.transaction();
+            assert_eq!(txn.vertex_count(), 0);
+            txn.create_vertex(&plain).unwrap();
+            txn.sync().unwrap();
+        }
+
+        let ds = SledConfig::default().with_namespace("graph").open(&path).unwrap();
+        let txn = ds.transaction();
+        let ids: Vec<_> = txn.all_vertices().unwrap().map(|v| v.unwrap().id).collect();
+        assert_eq!(ids, vec![namespaced.id]);
+        let tree_names = ds.holder.db.tree_names();
+        assert!(tree_names.iter().any(|tree| *tree == "graph.vertices"));
+        assert!(tree_names.iter().any(|tree| *tree == "graph.metadata"));
+        drop(ds);
+
+        for name in ["", "graph.reversed", "."] {
+            assert!(SledConfig::default().namespace(name).open(&path).is_err(), "{:?}", name);
+        }
+        let db = Config::new().temporary(true).open().unwrap();
+        assert!(SledDatastore::open_graph_with_db(db, "a.b").is_err());
+    }
+
+    #[test]
+    fn test_recovery_report() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("foo").unwrap();
+        {
+            let (ds, report) = SledConfig::default().open_with_report(&path).unwrap();
+            assert!(!ds.was_recovered());
+            assert!(!report.was_recovered);
+            assert_eq!(report.tree_keys["vertices"], 0);
+            let mut txn = ds.transaction();
+            txn.index_property(Identifier::new("name").unwrap()).unwrap();
+            txn.create_vertex(&Vertex::new(t)).unwrap();
+            txn.sync().unwrap();
+        }
+
+        let (ds, report) = SledConfig::default().open_with_report(&path).unwrap();
+        assert!(ds.was_recovered());
+        assert!(report.was_recovered);
+        assert_eq!(report.tree_keys["vertices"], 1);
+        assert_eq!(report.indexed_properties, Ok(1));
+    }
+
+    #[test]
+    fn test_compact() {
+        let path = tempdir().unwrap().keep();
+        let mut ds = SledDatastore::new(&path).unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let vertices: Vec<Vertex> = (0..5000).map(|_| Vertex::new(t)).collect();
+        let mut txn = ds.transaction();
+        txn.index_property(name).unwrap();
+        let mut items: Vec<BulkInsertItem> = vertices.iter().cloned().map(BulkInsertItem::Vertex).collect();
+        items.extend(
+            vertices
+                .iter()
+                .map(|v| BulkInsertItem::VertexProperty(v.id, name, Json::new(json!("x".repeat(1000))))),
+        );
+        txn.bulk_insert(items).unwrap();
+        txn.delete_vertices(vertices[500..].to_vec()).unwrap();
+        txn.sync().unwrap();
+        let before = ds.size_on_disk().unwrap();
+
+        let stats = ds.compact().unwrap();
+        assert_eq!(stats.bytes_before, before);
+        assert_eq!(stats.bytes_after, ds.size_on_disk().unwrap());
+        assert!(stats.bytes_after < before / 2, "{:?}", stats);
+        assert_eq!(stats.reclaimed(), before - stats.bytes_after);
+        let txn = ds.transaction();
+        assert_eq!(txn.vertex_count(), 500);
+        assert_eq!(txn.distinct_vertex_types().unwrap(), vec![t]);
+        let found: Vec<_> = txn
+            .vertex_ids_with_property_value(name, &Json::new(json!("x".repeat(1000))))
+            .unwrap()
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(found.len(), 500);
+        drop(txn);
+
+        // The compacted copy is what's reopened from the path
+        drop(ds);
+        let ds = SledDatastore::new(&path).unwrap();
+        assert_eq!(ds.size_on_disk().unwrap(), stats.bytes_after);
+        assert_eq!(ds.transaction().vertex_count(), 500);
+
+        assert!(SledDatastore::new_temporary().unwrap().compact().is_err());
+    }
+
+    #[test]
+    fn test_reclaim() {
+        let ds = SledDatastore::new(tempdir().unwrap().keep()).unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let vertices: Vec<Vertex> = (0..1_000).map(|_| Vertex::new(t)).collect();
+        let mut txn = ds.transaction();
+        txn.bulk_insert(vertices.iter().cloned().map(BulkInsertItem::Vertex).collect())
+            .unwrap();
+        txn.delete_vertices(vertices[100..].to_vec()).unwrap();
+
+        let stats = ds.reclaim().unwrap();
+        assert!(stats.bytes_before > 0);
+        assert!(stats.bytes_after > 0);
+        assert_eq!(txn.vertex_count(), 100);
+    }
+
+    #[test]
+    fn test_compact_failure_keeps_store_open() {
+        let path = tempdir().unwrap().keep();
+        let mut ds = SledDatastore::new(&path).unwrap();
+        let v = Vertex::new(Identifier::new("foo").unwrap());
+        ds.transaction().create_vertex(&v).unwrap();
+
+        // A file where the scratch directory goes makes the copy fail
+        let mut scratch = path.clone().into_os_string();
+        scratch.push(".compacting");
+        std::fs::write(&scratch, b"in the way").unwrap();
+        assert!(ds.compact().is_err());
+
+        let mut txn = ds.transaction();
+        assert_eq!(txn.specific_vertices(vec![v.id]).unwrap().count(), 1);
+        txn.create_vertex(&Vertex::new(v.t)).unwrap();
+        assert_eq!(txn.vertex_count(), 2);
+        assert!(path.is_dir());
+    }
+
+    #[test]
+    fn test_backup_and_restore() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let mut txn = ds.transaction();
+        txn.index_property(name).unwrap();
+        let vertices: Vec<Vertex> = (0..4).map(|_| Vertex::new(t)).collect();
+        for v in &vertices {
+            txn.create_vertex(v).unwrap();
+        }
+        for pair in vertices.windows(2) {
+            txn.create_edge(&Edge::new(pair[0].id, t, pair[1].id)).unwrap();
+        }
+        txn.set_vertex_properties(vec![vertices[0].id], name, &Json::new(json!("bob")))
+            .unwrap();
+
+        let dest = tempdir().unwrap().keep();
+        ds.backup(&dest).unwrap();
+        assert!(ds.backup(&dest).is_err());
+        {
+            let copy = SledDatastore::new(&dest).unwrap();
+            let txn = copy.transaction();
+            assert_eq!(txn.vertex_count(), 4);
+            assert_eq!(txn.edge_count(), 3);
+        }
+
+        // Only complete backups are restored
+        let not_a_backup = tempdir().unwrap().keep();
+        SledDatastore::new(&not_a_backup).unwrap();
+        assert!(SledDatastore::restore(&not_a_backup, &tempdir().unwrap().keep()).is_err());
+
+        let restored_path = tempdir().unwrap().keep();
+        let restored = SledDatastore::restore(&dest, &restored_path).unwrap();
+        let txn = restored.transaction();
+        assert_eq!(txn.vertex_count(), 4);
+        assert_eq!(txn.edge_count(), 3);
+        let found: Vec<_> = txn
+            .vertex_ids_with_property_value(name, &Json::new(json!("bob")))
+            .unwrap()
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(found, vec![vertices[0].id]);
+    }
+
+    #[test]
+    fn test_checkpoint() {
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edge = Edge::new(a.id, t, b.id);
+        let ds = SledConfig::default()
+            .compression(None)
+            .graph_name("graph")
+            .open(tempdir().unwrap().keep())
+            .unwrap();
+        let mut txn = ds.transaction();
+        txn.index_property(name).unwrap();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!("alice")))
+            .unwrap();
+
+        let dest = tempdir().unwrap().keep();
+        let checkpoint = ds.checkpoint(&dest).unwrap();
+        assert!(ds.checkpoint(&dest).is_err());
+        txn.create_vertex(&Vertex::new(t)).unwrap();
+        let check = |copy: &SledDatastore| {
+            let txn = copy.transaction();
+            assert_eq!(txn.vertex_count(), 2);
+            assert_eq!(
+                txn.all_edges().unwrap().map(|e| e.unwrap()).collect::<Vec<_>>(),
+                vec![edge.clone()]
+            );
+            let found: Vec<Uuid> = txn
+                .vertex_ids_with_property_value(name, &Json::new(json!("alice")))
+                .unwrap()
+                .unwrap()
+                .map(|id| id.unwrap())
+                .collect();
+            assert_eq!(found, vec![a.id]);
+        };
+        check(&checkpoint);
+        drop(checkpoint);
+        check(
+            &SledConfig::default()
+                .compression(None)
+                .graph_name("graph")
+                .open(&dest)
+                .unwrap(),
+        );
+        assert_eq!(ds.transaction().vertex_count(), 3);
+
+        let from_db = SledDatastore::open_with_db(Config::new().temporary(true).open().unwrap()).unwrap();
+        assert!(from_db.checkpoint(tempdir().unwrap().keep()).is_err());
+    }
+
+    #[test]
+    fn test_checksum() {
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let items = || {
+            vec![
+                BulkInsertItem::Vertex(a.clone()),
+                BulkInsertItem::Vertex(b.clone()),
+                BulkInsertItem::Edge(Edge::new(a.id, t, b.id)),
+                BulkInsertItem::VertexProperty(a.id, name, Json::new(json!("bob"))),
+            ]
+        };
+        let first = SledDatastore::new_temporary().unwrap();
+        let second = SledDatastore::new_temporary().unwrap();
+        first.transaction().bulk_insert(items()).unwrap();
+        second.transaction().bulk_insert(items()).unwrap();
+        assert_eq!(first.checksum().unwrap(), second.checksum().unwrap());
+        assert_eq!(first.tree_checksums().unwrap(), second.tree_checksums().unwrap());
+
+        second
+            .transaction()
+            .set_vertex_properties(vec![a.id], name, &Json::new(json!("alice")))
+            .unwrap();
+        assert_ne!(first.checksum().unwrap(), second.checksum().unwrap());
+        let (first_trees, second_trees) = (first.tree_checksums().unwrap(), second.tree_checksums().unwrap());
+        let differing: Vec<&String> = first_trees
+            .keys()
+            .filter(|name| first_trees[*name] != second_trees[*name])
+            .collect();
+        assert_eq!(differing, vec!["vertex_properties", "vertex_property_values"]);
+    }
+
+    #[test]
+    fn test_verify_integrity() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+        let mut txn = ds.transaction();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        txn.set_edge_properties(vec![edge.clone()], name, &Json::new(json!("bob")))
+            .unwrap();
+        assert!(ds.verify_integrity().unwrap().is_consistent());
+
+        ds.holder.edge_property_values.clear().unwrap();
+        for _ in 0..INTEGRITY_SAMPLE_SIZE {
+            let stray = Edge::new(Vertex::new(t).id, t, a.id);
+            txn.edge_range_manager_rev.set(&stray).unwrap();
+        }
+        let report = ds.verify_integrity().unwrap();
+        assert!(!report.is_consistent());
+        assert_eq!(
+            report.counts["orphaned_reversed_edge_range"],
+            INTEGRITY_SAMPLE_SIZE as u64
+        );
+        assert_eq!(report.counts["missing_edge_property_value"], 1);
+        assert_eq!(report.violation_count(), INTEGRITY_SAMPLE_SIZE as u64 + 1);
+        assert_eq!(report.violations.len(), INTEGRITY_SAMPLE_SIZE);
+        assert!(!report
+            .violations
+            .contains(&IntegrityViolation::MissingEdgePropertyValue { edge, name }));
+    }
+
+    #[test]
+    fn test_rebuild_edge_ranges() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let (a, b, c) = (Vertex::new(t), Vertex::new(t), Vertex::new(t));
+        let (ab, bc) = (Edge::new(a.id, t, b.id), Edge::new(b.id, t, c.id));
+        let mut txn = ds.transaction();
+        for v in [&a, &b, &c] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&ab).unwrap();
+        txn.create_edge(&bc).unwrap();
+        let ranges = |txn: &SledTransaction| -> (Vec<Edge>, Vec<Edge>) {
+            let offset = Edge::new(Uuid::default(), t, Uuid::default());
+            let forward = txn.range_edges(offset.clone()).unwrap().map(|e| e.unwrap()).collect();
+            let reversed = txn.range_reversed_edges(offset).unwrap().map(|e| e.unwrap()).collect();
+            (forward, reversed)
+        };
+        let expected = ranges(&txn);
+        assert_eq!(ds.rebuild_edge_ranges().unwrap(), RebuildStats::default());
+
+        txn.edge_range_manager.delete(&ab).unwrap();
+        txn.edge_range_manager_rev.delete(&reverse_edge(&bc)).unwrap();
+        txn.edge_range_manager.set(&Edge::new(c.id, t, a.id)).unwrap();
+        assert_ne!(ranges(&txn), expected);
+
+        let stats = ds.rebuild_edge_ranges().unwrap();
+        assert_eq!(stats, RebuildStats { added: 2, removed: 1 });
+        assert_eq!(ranges(&txn), expected);
+    }
+
+    #[test]
+    fn test_rebuild_property_indexes() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let (name, age) = (Identifier::new("name").unwrap(), Identifier::new("age").unwrap());
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let bob = Json::new(json!("bob"));
+        let mut txn = ds.transaction();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.index_property(name).unwrap();
+        txn.set_vertex_properties(vec![a.id, b.id], name, &bob).unwrap();
+        txn.set_vertex_properties(vec![a.id], age, &Json::new(json!(3)))
+            .unwrap();
+        let with_name = |txn: &SledTransaction| -> Vec<Uuid> {
+            let mut ids: Vec<Uuid> = txn
+                .vertex_ids_with_property_value(name, &bob)
+                .unwrap()
+                .unwrap()
+                .map(|id| id.unwrap())
+                .collect();
+            ids.sort();
+            ids
+        };
+        let expected = with_name(&txn);
+        assert_eq!(expected.len(), 2);
+
+        let stale = VertexPropertyManager::key_value_index(&a.id, &bob, name);
+        ds.holder.vertex_property_values.remove(stale).unwrap();
+        assert_eq!(with_name(&txn), vec![b.id]);
+
+        let stats = ds.rebuild_property_indexes(Some(name)).unwrap();
+        assert_eq!(stats, RebuildStats { added: 2, removed: 1 });
+        assert_eq!(with_name(&txn), expected);
+        let stats = ds.rebuild_property_indexes(None).unwrap();
+        assert_eq!(stats, RebuildStats { added: 3, removed: 3 });
+    }
+
+    #[test]
+    fn test_clear() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+        let mut txn = ds.transaction();
+        txn.index_property(name).unwrap();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!("bob")))
+            .unwrap();
+        txn.set_edge_properties(vec![edge.clone()], name, &Json::new(json!("bob")))
+            .unwrap();
+
+        ds.clear().unwrap();
+
+        let txn = ds.transaction();
+        assert_eq!(txn.vertex_count(), 0);
+        assert_eq!(txn.edge_count(), 0);
+        assert_eq!(txn.all_vertices().unwrap().count(), 0);
+        assert_eq!(txn.all_edges().unwrap().count(), 0);
+        assert_eq!(txn.specific_vertices(vec![a.id]).unwrap().count(), 0);
+        assert_eq!(txn.vertex_property(&a, name).unwrap(), None);
+        assert_eq!(txn.edge_property(&edge, name).unwrap(), None);
+        assert!(txn.vertex_ids_with_property(name).unwrap().is_none());
+        assert!(txn.edges_with_property(name).unwrap().is_none());
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 0);
+        assert_eq!(ds.stats().unwrap().indexed_properties, 0);
+    }
+
+    #[test]
+    fn test_stats() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("foo").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let v = Vertex::new(t);
+        txn.create_vertex(&v).unwrap();
+        txn.index_property(name).unwrap();
+        txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("bob")))
+            .unwrap();
+
+        let stats = ds.stats().unwrap();
+        assert_eq!(stats.indexed_properties, 1);
+        assert_eq!(stats.trees["vertices"].keys, 1);
+        assert_eq!(stats.trees["vertex_properties"].keys, 1);
+        assert_eq!(stats.trees["vertex_properties"].value_bytes, 5);
+        assert_eq!(stats.trees["edges"].keys, 0);
+
+        let logged = serde_json::to_value(&stats).unwrap();
+        assert_eq!(logged["indexed_properties"], json!(1));
+        assert_eq!(logged["trees"]["vertices"]["keys"], json!(1));
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_flush_async() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("foo").unwrap();
+        let vertices: Vec<Vertex> = (0..100).map(|_| Vertex::new(t)).collect();
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            ds.transaction()
+                .bulk_insert(vertices.iter().cloned().map(BulkInsertItem::Vertex).collect())
+                .unwrap();
+            ds.flush_async().await.unwrap();
+        }
+
+        let ds = SledDatastore::new(&path).unwrap();
+        let txn = ds.transaction();
+        assert_eq!(txn.vertex_count(), 100);
+        assert_eq!(txn.specific_vertices(vec![vertices[0].id]).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_maintain_reversed_edges() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("follows").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edge = Edge::new(a.id, t, b.id);
+        let offset = Edge::new(Uuid::default(), t, Uuid::default());
+        {
+            let ds = SledConfig::default()
+                .maintain_reversed_edges(false)
+                .open(&path)
+                .unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&a).unwrap();
+            txn.create_vertex(&b).unwrap();
+            txn.create_edge(&edge).unwrap();
+            txn.bulk_insert(vec![BulkInsertItem::Edge(Edge::new(b.id, t, a.id))])
+                .unwrap();
+            assert!(ds.holder.reversed_edge_ranges.is_empty());
+            assert_eq!(txn.range_edges(offset.clone()).unwrap().count(), 2);
+            assert!(txn.range_reversed_edges(offset.clone()).is_err());
+            assert!(txn.in_degree(b.id).is_err());
+            assert_eq!(txn.out_degree(a.id).unwrap(), 1);
+            assert!(ds.verify_integrity().unwrap().is_consistent());
+            txn.sync().unwrap();
+        }
+
+        // Read-only datastores keep the stored setting
+        {
+            let ds = SledConfig::default().read_only().open(&path).unwrap();
+            assert!(!ds.holder.reversed_edges);
+            assert!(ds.transaction().in_degree(b.id).is_err());
+        }
+
+        // Opening without the setting keeps the stored one, and so does a
+        // restored backup
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            assert!(!ds.holder.reversed_edges);
+            assert!(ds.holder.reversed_edge_ranges.is_empty());
+            assert!(ds.transaction().in_degree(b.id).is_err());
+            let dir = tempdir().unwrap();
+            ds.backup_to(dir.path().join("backup")).unwrap();
+            let restored = SledDatastore::restore_from(dir.path().join("backup"), dir.path().join("restored")).unwrap();
+            assert!(!restored.holder.reversed_edges);
+            assert!(restored.holder.reversed_edge_ranges.is_empty());
+        }
+
+        // Turning it back on rebuilds the reversed edge ranges
+        {
+            let ds = SledConfig::default().maintain_reversed_edges(true).open(&path).unwrap();
+            let txn = ds.transaction();
+            assert_eq!(ds.holder.reversed_edge_ranges.len(), 2);
+            assert_eq!(txn.in_degree(b.id).unwrap(), 1);
+            assert_eq!(txn.range_reversed_edges(offset).unwrap().count(), 2);
+            assert!(ds.verify_integrity().unwrap().is_consistent());
+        }
+
+        let ds = SledConfig::default()
+            .maintain_reversed_edges(false)
+            .open(&path)
+            .unwrap();
+        assert!(ds.holder.reversed_edge_ranges.is_empty());
+        ds.transaction().delete_edges(vec![edge]).unwrap();
+        assert_eq!(ds.transaction().edge_count(), 1);
+        assert!(ds.verify_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_maintain_property_value_index() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edge = Edge::new(a.id, t, b.id);
+        let value = Json::new(json!("alice"));
+        {
+            let ds = SledConfig::default()
+                .maintain_property_value_index(false)
+                .open(&path)
+                .unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&a).unwrap();
+            txn.create_vertex(&b).unwrap();
+            txn.create_edge(&edge).unwrap();
+            txn.set_vertex_properties(vec![a.id, b.id], name, &value).unwrap();
+            txn.set_edge_properties(vec![edge.clone()], name, &value).unwrap();
+            txn.delete_vertex_properties(vec![(b.id, name)]).unwrap();
+            assert!(ds.holder.vertex_property_values.is_empty());
+            assert!(ds.holder.edge_property_values.is_empty());
+            assert!(txn.index_property(name).is_err());
+            assert!(txn.vertex_ids_with_property_value(name, &value).unwrap().is_none());
+            assert!(ds.rebuild_property_indexes(None).is_err());
+            assert!(ds.verify_integrity().unwrap().is_consistent());
+            txn.sync().unwrap();
+        }
+
+        // Opening without the setting keeps the stored one
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            assert!(!ds.holder.property_value_index);
+            assert!(ds.holder.vertex_property_values.is_empty());
+            assert!(ds.transaction().index_property(name).is_err());
+        }
+
+        // Turning it back on rebuilds the value indexes
+        let ds = SledConfig::default()
+            .maintain_property_value_index(true)
+            .open(&path)
+            .unwrap();
+        assert_eq!(ds.holder.vertex_property_values.len(), 1);
+        assert_eq!(ds.holder.edge_property_values.len(), 1);
+        let mut txn = ds.transaction();
+        txn.index_property(name).unwrap();
+        let found: Vec<Uuid> = txn
+            .vertex_ids_with_property_value(name, &value)
+            .unwrap()
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(found, vec![a.id]);
+        assert!(ds.verify_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_flush_on_drop() {
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let v = Vertex::new(t);
+        let path = tempdir().unwrap().keep();
+        {
+            let ds = SledConfig::default()
+                .on_drop_error(|err| panic!("{}", err))
+                .open(&path)
+                .unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&v).unwrap();
+            txn.index_property(name).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("alice")))
+                .unwrap();
+        }
+
+        let ds = SledDatastore::new(&path).unwrap();
+        let txn = ds.transaction();
+        assert_eq!(txn.specific_vertices(vec![v.id]).unwrap().count(), 1);
+        let found = txn
+            .vertex_ids_with_property_value(name, &Json::new(json!("alice")))
+            .unwrap()
+            .unwrap()
+            .count();
+        assert_eq!(found, 1);
+        drop(txn);
+        drop(ds);
+
+        // Without the flush, dropping leaves the database as it was
+        let ds = SledConfig::default()
+            .flush_on_drop(false)
+            .on_drop_error(|err| panic!("{}", err))
+            .open(&path)
+            .unwrap();
+        assert!(!ds.holder.flush_on_drop);
+        drop(ds);
+        assert_eq!(SledDatastore::new(&path).unwrap().transaction().vertex_count(), 1);
     }
 }
@@ -0,0 +1,278 @@
+//! How property values are serialized in the property trees.
+//!
+//! A store without properties takes the encoding it's opened with, which is
+//! recorded in the metadata tree unless it's JSON, so that the store is never
+//! read or written with a different encoding than it was filled with. Stores
+//! without the record use JSON, which is what every store used before the
+//! encoding could be chosen. The value indexes are keyed by the JSON value
+//! regardless of the encoding.
+//!
+//! A `ValueCodec` can further transform the serialized bytes, e.g. to encrypt
+//! them. Unlike the encoding it isn't recorded, so a store written with a
+//! codec has to be opened with the same one.
+
+use std::borrow::Cow;
+use std::fmt::Debug;
+use std::sync::Arc;
+
+use serde::Serialize;
+use serde_json::Value as JsonValue;
+
+use crate::errors::DSError;
+
+/// Transforms serialized property values on their way to and from the
+/// property trees, set with `SledConfig::with_value_codec`. Keys are left
+/// alone, so prefix scans and the value indexes, which are keyed by a hash
+/// of the value, keep working.
+pub trait ValueCodec: Debug + Send + Sync {
+    /// Transforms serialized bytes before they're written.
+    ///
+    /// # Arguments
+    /// * `bytes`: The serialized property value.
+    fn encode(&self, bytes: &[u8]) -> Vec<u8>;
+
+    /// Reverses `encode` on bytes read from a property tree.
+    ///
+    /// # Arguments
+    /// * `bytes`: The stored bytes.
+    fn decode(&self, bytes: &[u8]) -> indradb::Result<Vec<u8>>;
+}
+
+/// How property values are serialized, set with
+/// `SledConfig::property_encoding`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum PropertyEncoding {
+    /// serde_json bytes, readable by any version of this crate.
+    #[default]
+    Json,
+    /// MessagePack, which is more compact and faster to parse for large
+    /// documents.
+    MessagePack,
+}
+
+impl PropertyEncoding {
+    /// Serializes a property value.
+    ///
+    /// # Arguments
+    /// * `value`: The value to serialize.
+    pub(crate) fn encode(self, value: &JsonValue) -> indradb::Result<Vec<u8>> {
+        match self {
+            PropertyEncoding::Json => Ok(serde_json::to_vec(value)?),
+            PropertyEncoding::MessagePack => {
+                rmp_serde::to_vec(value).map_err(|err| DSError::PropertyEncoding(err.to_string()).into())
+            }
+        }
+    }
+
+    /// Deserializes a property value written by `encode`.
+    ///
+    /// # Arguments
+    /// * `bytes`: The stored bytes.
+    pub(crate) fn decode(self, bytes: &[u8]) -> indradb::Result<JsonValue> {
+        match self {
+            PropertyEncoding::Json => Ok(serde_json::from_slice(bytes)?),
+            PropertyEncoding::MessagePack => {
+                rmp_serde::from_slice(bytes).map_err(|err| DSError::PropertyEncoding(err.to_string()).into())
+            }
+        }
+    }
+
+    /// The number the encoding is recorded as in the metadata tree.
+    pub(crate) fn id(self) -> u64 {
+        match self {
+            PropertyEncoding::Json => 0,
+            PropertyEncoding::MessagePack => 1,
+        }
+    }
+
+    pub(crate) fn from_id(id: u64) -> Option<Self> {
+        match id {
+            0 => Some(PropertyEncoding::Json),
+            1 => Some(PropertyEncoding::MessagePack),
+            _ => None,
+        }
+    }
+}
+
+/// The encoding and codec property values are stored with.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct PropertyCodec {
+    pub encoding: PropertyEncoding,
+    pub value_codec: Option<Arc<dyn ValueCodec>>,
+}
+
+impl PropertyCodec {
+    /// Serializes a property value and applies the codec, if there is one.
+    ///
+    /// # Arguments
+    /// * `value`: The value to store.
+    pub(crate) fn encode(&self, value: &JsonValue) -> indradb::Result<Vec<u8>> {
+        let bytes = self.encoding.encode(value)?;
+        Ok(match self.value_codec {
+            Some(ref codec) => codec.encode(&bytes),
+            None => bytes,
+        })
+    }
+
+    /// Reverses the codec on stored bytes, leaving them serialized.
+    ///
+    /// # Arguments
+    /// * `bytes`: The stored bytes.
+    pub(crate) fn serialized<'b>(&self, bytes: &'b [u8]) -> indradb::Result<Cow<'b, [u8]>> {
+        Ok(match self.value_codec {
+            Some(ref codec) => Cow::Owned(codec.decode(bytes)?),
+            None => Cow::Borrowed(bytes),
+        })
+    }
+
+    /// Deserializes a property value written by `encode`.
+    ///
+    /// # Arguments
+    /// * `bytes`: The stored bytes.
+    pub(crate) fn decode(&self, bytes: &[u8]) -> indradb::Result<JsonValue> {
+        self.encoding.decode(&self.serialized(bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::sync::Arc;
+
+    use indradb::{Datastore, Identifier, Json, Transaction, Vertex};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use super::{PropertyEncoding, ValueCodec};
+    use crate::errors::DSError;
+    use crate::{SledConfig, SledDatastore};
+
+    #[derive(Debug)]
+    struct XorCodec(u8);
+
+    impl ValueCodec for XorCodec {
+        fn encode(&self, bytes: &[u8]) -> Vec<u8> {
+            bytes.iter().map(|byte| byte ^ self.0).collect()
+        }
+
+        fn decode(&self, bytes: &[u8]) -> indradb::Result<Vec<u8>> {
+            Ok(self.encode(bytes))
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let document = json!({
+            "name": "alice",
+            "age": 42,
+            "score": -1.5,
+            "tags": ["a", "b", {"nested": [1, 2, null, true]}],
+            "address": {"city": "Berlin", "zip": "10115"},
+        });
+        for encoding in [PropertyEncoding::Json, PropertyEncoding::MessagePack] {
+            let bytes = encoding.encode(&document).unwrap();
+            assert_eq!(encoding.decode(&bytes).unwrap(), document, "{:?}", encoding);
+            assert_eq!(PropertyEncoding::from_id(encoding.id()), Some(encoding));
+
+            let ds = SledConfig::default()
+                .property_encoding(encoding)
+                .open(tempdir().unwrap().keep())
+                .unwrap();
+            let mut txn = ds.transaction();
+            let v = Vertex::new(Identifier::new("person").unwrap());
+            let name = Identifier::new("profile").unwrap();
+            txn.create_vertex(&v).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(document.clone()))
+                .unwrap();
+            txn.index_property(name).unwrap();
+            assert_eq!(
+                txn.vertex_property(&v, name).unwrap(),
+                Some(Json::new(document.clone()))
+            );
+            let found = txn
+                .vertex_ids_with_property_value(name, &Json::new(document.clone()))
+                .unwrap()
+                .unwrap()
+                .count();
+            assert_eq!(found, 1);
+            let stored = ds.holder.vertex_properties.iter().next().unwrap().unwrap().1;
+            assert_eq!(stored, encoding.encode(&document).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_mismatch_fails_to_open() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let v = Vertex::new(t);
+        {
+            let ds = SledConfig::default()
+                .property_encoding(PropertyEncoding::MessagePack)
+                .open(&path)
+                .unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&v).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("alice")))
+                .unwrap();
+            txn.sync().unwrap();
+        }
+
+        let Err(indradb::Error::Datastore(err)) = SledConfig::default()
+            .property_encoding(PropertyEncoding::Json)
+            .open(&path)
+        else {
+            panic!("opened a MessagePack store as JSON");
+        };
+        assert!(matches!(
+            err.downcast_ref::<DSError>(),
+            Some(DSError::PropertyEncodingMismatch {
+                stored: PropertyEncoding::MessagePack,
+                configured: PropertyEncoding::Json,
+            })
+        ));
+
+        // Without an explicit encoding the stored one is used
+        let ds = SledDatastore::new(&path).unwrap();
+        let value = ds.transaction().vertex_property(&v, name).unwrap();
+        assert_eq!(value, Some(Json::new(json!("alice"))));
+    }
+
+    #[test]
+    fn test_value_codec() {
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("email").unwrap();
+        let v = Vertex::new(t);
+        let value = json!("alice@example.com");
+        for encoding in [PropertyEncoding::Json, PropertyEncoding::MessagePack] {
+            let ds = SledConfig::default()
+                .property_encoding(encoding)
+                .with_value_codec(Arc::new(XorCodec(0x5a)))
+                .open(tempdir().unwrap().keep())
+                .unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&v).unwrap();
+            txn.index_property(name).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(value.clone()))
+                .unwrap();
+
+            for tree in [&ds.holder.vertex_properties, &ds.holder.vertex_property_values] {
+                let stored = tree.iter().next().unwrap().unwrap().1;
+                let plaintext = b"alice@example.com";
+                assert!(
+                    !stored.windows(plaintext.len()).any(|w| w == plaintext),
+                    "{:?}",
+                    encoding
+                );
+            }
+            assert_eq!(txn.vertex_property(&v, name).unwrap(), Some(Json::new(value.clone())));
+            let typed: Option<String> = txn.vertex_property_as(v.id, name).unwrap();
+            assert_eq!(typed.as_deref(), value.as_str());
+            let found = txn
+                .vertex_ids_with_property_value(name, &Json::new(value.clone()))
+                .unwrap()
+                .unwrap()
+                .count();
+            assert_eq!(found, 1);
+        }
+    }
+}
@@ -1,16 +1,83 @@
 use std::sync::PoisonError;
 
 use indradb::Error as IndraError;
-use sled::Error as SledError;
+use sled::{Error as SledError, Tree};
+
+use crate::encoding::PropertyEncoding;
 
 pub(crate) fn map_err<T>(result: Result<T, SledError>) -> Result<T, IndraError> {
-    result.map_err(|err| IndraError::Datastore(Box::new(err)))
+    result.map_err(|err| DSError::from(err).into())
+}
+
+/// Like `map_err`, but wraps the error in `DSError::Sled` to record which
+/// call on which tree failed.
+///
+/// # Arguments
+/// * `op`: The name of the sled call, such as "get".
+/// * `tree`: The tree the call was made on.
+/// * `result`: The result of the call.
+pub(crate) fn map_err_ctx<T>(op: &'static str, tree: &Tree, result: Result<T, SledError>) -> Result<T, IndraError> {
+    result.map_err(|source| {
+        DSError::Sled {
+            op,
+            tree: String::from_utf8_lossy(&tree.name()).into_owned(),
+            source,
+        }
+        .into()
+    })
 }
 
 #[derive(Debug, thiserror::Error)]
 pub enum DSError {
     #[error("Error in locking a RwLock: {0}")]
     PoisonError(String),
+    #[error("Counter update would go out of the range of a u64")]
+    CounterOutOfRange,
+    #[error("The datastore was opened read-only")]
+    ReadOnly,
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("Storage corruption: {0}")]
+    Corruption(#[source] SledError),
+    #[error("Tree not found: {}", String::from_utf8_lossy(.0))]
+    CollectionNotFound(Vec<u8>),
+    #[error("Unsupported sled operation: {0}")]
+    Unsupported(String),
+    #[error("Unexpected sled bug: {0}")]
+    ReportableBug(String),
+    #[error("Invalid snapshot: {0}")]
+    InvalidSnapshot(String),
+    #[error("Invalid JSON export: {0}")]
+    InvalidExport(String),
+    #[error("Invalid backup: {0}")]
+    InvalidBackup(String),
+    #[error("Cannot restore into {0}, which is not empty")]
+    RestoreTargetNotEmpty(String),
+    #[error("Invalid graph name {0:?}: graph names can't be empty or contain a '.'")]
+    InvalidGraphName(String),
+    #[error("Range bounds must be numbers, got {0}")]
+    NonNumericBound(String),
+    #[error("Unsupported on-disk format version {found}, this version of the crate reads format version {current}")]
+    UnsupportedFormatVersion { found: u64, current: u64 },
+    #[error("Sled {op} on tree {tree} failed: {source}")]
+    Sled {
+        op: &'static str,
+        tree: String,
+        source: SledError,
+    },
+    #[error("Could not convert property {name} of {owner}: {source}")]
+    PropertyConversion {
+        owner: String,
+        name: String,
+        source: serde_json::Error,
+    },
+    #[error("Could not encode or decode a property value: {0}")]
+    PropertyEncoding(String),
+    #[error("The datastore stores properties as {stored:?}, but was opened with {configured:?}")]
+    PropertyEncodingMismatch {
+        stored: PropertyEncoding,
+        configured: PropertyEncoding,
+    },
 }
 
 impl<T> From<PoisonError<T>> for DSError {
@@ -19,8 +86,77 @@ impl<T> From<PoisonError<T>> for DSError {
     }
 }
 
+impl From<SledError> for DSError {
+    fn from(err: SledError) -> Self {
+        match err {
+            SledError::Io(err) => DSError::Io(err),
+            SledError::CollectionNotFound(name) => DSError::CollectionNotFound(name.to_vec()),
+            SledError::Unsupported(message) => DSError::Unsupported(message),
+            SledError::ReportableBug(message) => DSError::ReportableBug(message),
+            err @ SledError::Corruption { .. } => DSError::Corruption(err),
+        }
+    }
+}
+
 impl From<DSError> for IndraError {
     fn from(err: DSError) -> Self {
         IndraError::Datastore(Box::new(err))
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::fs::File;
+
+    use indradb::{Datastore, Edge, Identifier, Vertex};
+    use sled::Config;
+    use tempfile::tempdir;
+
+    use super::{map_err, DSError};
+    use crate::SledDatastore;
+
+    fn inner(err: indradb::Error) -> DSError {
+        match err {
+            indradb::Error::Datastore(err) => *err.downcast::<DSError>().unwrap(),
+            err => panic!("unexpected error: {}", err),
+        }
+    }
+
+    #[test]
+    fn test_sled_errors_are_typed() {
+        let db = Config::new().temporary(true).open().unwrap();
+        let err = map_err(db.drop_tree(b"__sled__default")).unwrap_err();
+        assert!(matches!(inner(err), DSError::Unsupported(_)));
+
+        let dir = tempdir().unwrap();
+        let path = dir.path().join("not-a-directory");
+        File::create(&path).unwrap();
+        let Err(err) = SledDatastore::new(&path) else {
+            panic!("opened a datastore on top of a file");
+        };
+        assert!(matches!(inner(err), DSError::Io(_)));
+    }
+
+    #[test]
+    fn test_manager_errors_carry_context() {
+        let db = Config::new().temporary(true).open().unwrap();
+        let ds = SledDatastore::open_with_db(db.clone()).unwrap();
+        // Calls on the datastore's handle to a dropped tree fail
+        db.drop_tree("edge_ranges").unwrap();
+
+        let t = Identifier::new("foo").unwrap();
+        let edge = Edge::new(Vertex::new(t).id, t, Vertex::new(t).id);
+        let err = inner(ds.transaction().edge_exists(&edge).unwrap_err());
+        assert!(
+            err.to_string()
+                .starts_with("Sled contains_key on tree edge_ranges failed"),
+            "{}",
+            err
+        );
+        let DSError::Sled { op, tree, source } = err else {
+            panic!("expected a sled error with context");
+        };
+        assert_eq!((op, tree.as_str()), ("contains_key", "edge_ranges"));
+        assert!(matches!(source, sled::Error::CollectionNotFound(_)));
+    }
+}
@@ -0,0 +1,122 @@
+use std::collections::BTreeMap;
+
+use indradb::{Edge, Identifier};
+use serde::Serialize;
+use uuid::Uuid;
+
+use crate::datastore::RebuildStats;
+
+/// A mismatch between a property tree and its value index.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IndexInconsistency {
+    /// A vertex property has no matching value-index entry.
+    MissingVertexIndex { id: Uuid, name: Identifier },
+    /// A vertex value-index entry has no matching property value.
+    DanglingVertexIndex { id: Uuid, name: Identifier },
+    /// An edge property has no matching value-index entry.
+    MissingEdgeIndex { edge: Edge, name: Identifier },
+    /// An edge value-index entry has no matching property value.
+    DanglingEdgeIndex { edge: Edge, name: Identifier },
+}
+
+/// The most violations an `IntegrityReport` keeps as samples. Violations
+/// past this are only counted.
+pub const INTEGRITY_SAMPLE_SIZE: usize = 100;
+
+/// A discrepancy between the trees of a datastore, found by
+/// `SledTransaction::validate_integrity`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IntegrityViolation {
+    /// An edge has no entry in the edge ranges.
+    MissingEdgeRange { edge: Edge },
+    /// An edge range has no matching edge.
+    MissingEdge { edge: Edge },
+    /// An edge range has no matching entry in the reversed edge ranges.
+    MissingReversedEdgeRange { edge: Edge },
+    /// A reversed edge range has no matching entry in the edge ranges. The
+    /// edge is given the right way round.
+    OrphanedReversedEdgeRange { edge: Edge },
+    /// An edge's outbound vertex doesn't exist.
+    MissingOutboundVertex { edge: Edge },
+    /// An edge's inbound vertex doesn't exist.
+    MissingInboundVertex { edge: Edge },
+    /// A vertex property belongs to a vertex that doesn't exist.
+    OrphanedVertexProperty { id: Uuid, name: Identifier },
+    /// A vertex property has no value-index entry.
+    MissingVertexPropertyValue { id: Uuid, name: Identifier },
+    /// A vertex value-index entry doesn't match a stored vertex property.
+    DanglingVertexPropertyValue { id: Uuid, name: Identifier },
+    /// An edge property belongs to an edge that doesn't exist.
+    OrphanedEdgeProperty { edge: Edge, name: Identifier },
+    /// An edge property has no value-index entry.
+    MissingEdgePropertyValue { edge: Edge, name: Identifier },
+    /// An edge value-index entry doesn't match a stored edge property.
+    DanglingEdgePropertyValue { edge: Edge, name: Identifier },
+}
+
+impl IntegrityViolation {
+    /// The name the violation is counted under in an `IntegrityReport`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            IntegrityViolation::MissingEdgeRange { .. } => "missing_edge_range",
+            IntegrityViolation::MissingEdge { .. } => "missing_edge",
+            IntegrityViolation::MissingReversedEdgeRange { .. } => "missing_reversed_edge_range",
+            IntegrityViolation::OrphanedReversedEdgeRange { .. } => "orphaned_reversed_edge_range",
+            IntegrityViolation::MissingOutboundVertex { .. } => "missing_outbound_vertex",
+            IntegrityViolation::MissingInboundVertex { .. } => "missing_inbound_vertex",
+            IntegrityViolation::OrphanedVertexProperty { .. } => "orphaned_vertex_property",
+            IntegrityViolation::MissingVertexPropertyValue { .. } => "missing_vertex_property_value",
+            IntegrityViolation::DanglingVertexPropertyValue { .. } => "dangling_vertex_property_value",
+            IntegrityViolation::OrphanedEdgeProperty { .. } => "orphaned_edge_property",
+            IntegrityViolation::MissingEdgePropertyValue { .. } => "missing_edge_property_value",
+            IntegrityViolation::DanglingEdgePropertyValue { .. } => "dangling_edge_property_value",
+        }
+    }
+}
+
+/// The result of `SledTransaction::validate_integrity`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct IntegrityReport {
+    /// The number of violations found of each kind, keyed by
+    /// `IntegrityViolation::kind`.
+    pub counts: BTreeMap<&'static str, u64>,
+    /// The first `INTEGRITY_SAMPLE_SIZE` violations found, in the order the
+    /// checks ran.
+    pub violations: Vec<IntegrityViolation>,
+}
+
+impl IntegrityReport {
+    /// Whether no discrepancies were found.
+    pub fn is_consistent(&self) -> bool {
+        self.counts.is_empty()
+    }
+
+    /// The total number of violations found, including those that weren't
+    /// kept as samples.
+    pub fn violation_count(&self) -> u64 {
+        self.counts.values().sum()
+    }
+
+    pub(crate) fn record(&mut self, violation: IntegrityViolation) {
+        *self.counts.entry(violation.kind()).or_insert(0) += 1;
+        if self.violations.len() < INTEGRITY_SAMPLE_SIZE {
+            self.violations.push(violation);
+        }
+    }
+}
+
+/// What `SledTransaction::repair_integrity` changed, by tree name. Trees that
+/// weren't touched are left out.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct RepairStats {
+    /// The entries added to and removed from each tree.
+    pub trees: BTreeMap<String, RebuildStats>,
+}
+
+impl RepairStats {
+    pub(crate) fn record(&mut self, tree: &str, stats: RebuildStats) {
+        if stats != RebuildStats::default() {
+            self.trees.insert(tree.to_string(), stats);
+        }
+    }
+}
@@ -0,0 +1,662 @@
+//! JSON export and import of a whole graph.
+//!
+//! The document has the shape
+//! `{"vertices": [{"id", "t", "properties"}], "edges": [{"outbound_id", "t", "inbound_id", "properties"}]}`,
+//! where `properties` maps property names to their JSON values.
+//!
+//! The newline-delimited variant writes one `NdjsonRecord` per line instead,
+//! tagged by a `type` field: all vertices, then edges, then vertex
+//! properties, then edge properties. Its output is canonical, so that it can
+//! be compared and hashed. It backs both `export_ndjson` and
+//! `export_to_writer`, and their import counterparts.
+
+use std::collections::BTreeMap;
+use std::io::{BufRead, BufReader, BufWriter, Read, Write};
+
+use indradb::{BulkInsertItem, Datastore, Edge, Identifier, Json, Transaction, Vertex};
+use serde::{Deserialize, Serialize};
+use serde_json::Value as JsonValue;
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::errors::{map_err, DSError};
+use crate::{SledDatastore, SledTransaction};
+
+#[derive(Serialize, Deserialize)]
+struct JsonVertex {
+    id: Uuid,
+    t: String,
+    #[serde(default)]
+    properties: BTreeMap<String, JsonValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct JsonEdge {
+    outbound_id: Uuid,
+    t: String,
+    inbound_id: Uuid,
+    #[serde(default)]
+    properties: BTreeMap<String, JsonValue>,
+}
+
+#[derive(Deserialize)]
+struct JsonGraph {
+    #[serde(default)]
+    vertices: Vec<JsonVertex>,
+    #[serde(default)]
+    edges: Vec<JsonEdge>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum NdjsonRecord {
+    Vertex {
+        id: Uuid,
+        t: String,
+    },
+    Edge {
+        outbound_id: Uuid,
+        t: String,
+        inbound_id: Uuid,
+    },
+    VertexProperty {
+        id: Uuid,
+        name: String,
+        value: JsonValue,
+    },
+    EdgeProperty {
+        outbound_id: Uuid,
+        t: String,
+        inbound_id: Uuid,
+        name: String,
+        value: JsonValue,
+    },
+}
+
+/// What `SledDatastore::import_from_reader` inserted.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ImportStats {
+    /// The number of vertices inserted.
+    pub vertices: u64,
+    /// The number of edges inserted.
+    pub edges: u64,
+    /// The number of vertex and edge properties inserted.
+    pub properties: u64,
+}
+
+impl NdjsonRecord {
+    fn into_bulk_insert_item(self, stats: &mut ImportStats) -> indradb::Result<BulkInsertItem> {
+        Ok(match self {
+            NdjsonRecord::Vertex { id, t } => {
+                stats.vertices += 1;
+                BulkInsertItem::Vertex(Vertex::with_id(id, Identifier::new(t)?))
+            }
+            NdjsonRecord::Edge {
+                outbound_id,
+                t,
+                inbound_id,
+            } => {
+                stats.edges += 1;
+                BulkInsertItem::Edge(Edge::new(outbound_id, Identifier::new(t)?, inbound_id))
+            }
+            NdjsonRecord::VertexProperty { id, name, value } => {
+                stats.properties += 1;
+                BulkInsertItem::VertexProperty(id, Identifier::new(name)?, Json::new(value))
+            }
+            NdjsonRecord::EdgeProperty {
+                outbound_id,
+                t,
+                inbound_id,
+                name,
+                value,
+            } => {
+                stats.properties += 1;
+                BulkInsertItem::EdgeProperty(
+                    Edge::new(outbound_id, Identifier::new(t)?, inbound_id),
+                    Identifier::new(name)?,
+                    Json::new(value),
+                )
+            }
+        })
+    }
+}
+
+/// The default number of records passed to each `bulk_insert` call by
+/// `import_ndjson`.
+pub(crate) const NDJSON_IMPORT_CHUNK_SIZE: usize = 10_000;
+
+fn write_raw<W: Write>(writer: &mut W, raw: &[u8]) -> indradb::Result<()> {
+    writer.write_all(raw).map_err(DSError::from)?;
+    Ok(())
+}
+
+fn json_vertex(txn: &SledTransaction, vertex: Vertex) -> indradb::Result<JsonVertex> {
+    let mut properties = BTreeMap::new();
+    for property in txn.all_vertex_properties_for_vertex(&vertex)? {
+        let (name, value) = property?;
+        properties.insert(name.to_string(), (*value).clone());
+    }
+    Ok(JsonVertex {
+        id: vertex.id,
+        t: vertex.t.to_string(),
+        properties,
+    })
+}
+
+fn json_edge(txn: &SledTransaction, edge: Edge) -> indradb::Result<JsonEdge> {
+    let mut properties = BTreeMap::new();
+    for property in txn.all_edge_properties_for_edge(&edge)? {
+        let (name, value) = property?;
+        properties.insert(name.to_string(), (*value).clone());
+    }
+    Ok(JsonEdge {
+        outbound_id: edge.outbound_id,
+        t: edge.t.to_string(),
+        inbound_id: edge.inbound_id,
+        properties,
+    })
+}
+
+/// Writes the graph one vertex or edge at a time, so the document is never
+/// held in memory as a whole.
+pub(crate) fn export<W: Write>(datastore: &SledDatastore, writer: W) -> indradb::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    let txn = datastore.transaction();
+
+    write_raw(&mut writer, b"{\"vertices\":[")?;
+    for (i, vertex) in txn.all_vertices()?.enumerate() {
+        if i > 0 {
+            write_raw(&mut writer, b",")?;
+        }
+        serde_json::to_writer(&mut writer, &json_vertex(&txn, vertex?)?)?;
+    }
+
+    write_raw(&mut writer, b"],\"edges\":[")?;
+    for (i, edge) in txn.all_edges()?.enumerate() {
+        if i > 0 {
+            write_raw(&mut writer, b",")?;
+        }
+        serde_json::to_writer(&mut writer, &json_edge(&txn, edge?)?)?;
+    }
+    write_raw(&mut writer, b"]}")?;
+    writer.flush().map_err(DSError::from)?;
+    Ok(())
+}
+
+/// Recreates the graph of a document written by `export`. Unlike the
+/// export, the document is parsed in full before anything is written.
+pub(crate) fn import<R: Read>(datastore: &SledDatastore, reader: R) -> indradb::Result<()> {
+    let graph: JsonGraph = serde_json::from_reader(BufReader::new(reader))?;
+    let mut txn = datastore.transaction();
+
+    for item in graph.vertices {
+        let vertex = Vertex::with_id(item.id, Identifier::new(item.t)?);
+        txn.create_vertex(&vertex)?;
+        for (name, value) in item.properties {
+            txn.set_vertex_properties(vec![vertex.id], Identifier::new(name)?, &Json::new(value))?;
+        }
+    }
+
+    for item in graph.edges {
+        let edge = Edge::new(item.outbound_id, Identifier::new(item.t)?, item.inbound_id);
+        if !txn.create_edge(&edge)? {
+            return Err(DSError::InvalidExport(format!("edge {:?} refers to a missing vertex", edge)).into());
+        }
+        for (name, value) in item.properties {
+            txn.set_edge_properties(vec![edge.clone()], Identifier::new(name)?, &Json::new(value))?;
+        }
+    }
+    Ok(())
+}
+
+fn write_record<W: Write, T: Serialize>(writer: &mut W, record: &T) -> indradb::Result<()> {
+    serde_json::to_writer(&mut *writer, record)?;
+    write_raw(writer, b"\n")
+}
+
+/// Rebuilds `value` with the keys of every object in sorted order, so that
+/// it serializes the same way whatever order the keys were inserted in.
+fn canonical_json(value: JsonValue) -> JsonValue {
+    match value {
+        JsonValue::Object(map) => {
+            let mut entries: Vec<(String, JsonValue)> = map.into_iter().collect();
+            entries.sort_by(|a, b| a.0.cmp(&b.0));
+            JsonValue::Object(
+                entries
+                    .into_iter()
+                    .map(|(key, value)| (key, canonical_json(value)))
+                    .collect(),
+            )
+        }
+        JsonValue::Array(values) => JsonValue::Array(values.into_iter().map(canonical_json).collect()),
+        value => value,
+    }
+}
+
+/// Calls `f` with the edges of each outbound vertex in turn, sorted by type
+/// name and then inbound vertex. The range tree orders types by length
+/// before name, so each group is sorted again.
+fn for_each_edge_group<F>(txn: &SledTransaction, mut f: F) -> indradb::Result<()>
+where
+    F: FnMut(Vec<Edge>) -> indradb::Result<()>,
+{
+    let sorted = |mut group: Vec<Edge>| {
+        group.sort_by(|a, b| (a.t.as_str(), a.inbound_id).cmp(&(b.t.as_str(), b.inbound_id)));
+        group
+    };
+    let mut group: Vec<Edge> = Vec::new();
+    for item in txn.edge_range_manager.iterate_for_all() {
+        let edge = item?;
+        if matches!(group.first(), Some(first) if first.outbound_id != edge.outbound_id) {
+            f(sorted(std::mem::take(&mut group)))?;
+        }
+        group.push(edge);
+    }
+    if !group.is_empty() {
+        f(sorted(group))?;
+    }
+    Ok(())
+}
+
+/// Streams the graph as newline-delimited JSON in a canonical form: vertices
+/// by id, edges by outbound id, type name and inbound id, and properties by
+/// owner and then name, with object keys sorted. Two stores holding the same
+/// graph write the same bytes. Every section is a separate pass over the
+/// manager iterators, so memory use only grows with the edges and
+/// properties of a single vertex.
+pub(crate) fn export_ndjson<W: Write>(datastore: &SledDatastore, writer: W) -> indradb::Result<()> {
+    let mut writer = BufWriter::new(writer);
+    let txn = datastore.transaction();
+
+    for item in txn.vertex_manager.iterate_for_range(Uuid::default()) {
+        let (id, t) = item?;
+        let record = NdjsonRecord::Vertex { id, t: t.to_string() };
+        write_record(&mut writer, &record)?;
+    }
+    for_each_edge_group(&txn, |edges| {
+        for edge in edges {
+            let record = NdjsonRecord::Edge {
+                outbound_id: edge.outbound_id,
+                t: edge.t.to_string(),
+                inbound_id: edge.inbound_id,
+            };
+            write_record(&mut writer, &record)?;
+        }
+        Ok(())
+    })?;
+    for item in txn.vertex_manager.iterate_for_range(Uuid::default()) {
+        let (owner_id, _) = item?;
+        let mut properties = Vec::new();
+        for property in txn.vertex_property_manager.iterate_for_owner(owner_id)? {
+            let ((_, name), value) = property?;
+            properties.push((name, value));
+        }
+        properties.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+        for (name, value) in properties {
+            let record = NdjsonRecord::VertexProperty {
+                id: owner_id,
+                name: name.to_string(),
+                value: canonical_json(value),
+            };
+            write_record(&mut writer, &record)?;
+        }
+    }
+    for_each_edge_group(&txn, |edges| {
+        for edge in edges {
+            let mut properties = Vec::new();
+            for property in txn.edge_property_manager.iterate_for_owner(&edge)? {
+                let ((_, name), value) = property?;
+                properties.push((name, value));
+            }
+            properties.sort_by(|a, b| a.0.as_str().cmp(b.0.as_str()));
+            for (name, value) in properties {
+                let record = NdjsonRecord::EdgeProperty {
+                    outbound_id: edge.outbound_id,
+                    t: edge.t.to_string(),
+                    inbound_id: edge.inbound_id,
+                    name: name.to_string(),
+                    value: canonical_json(value),
+                };
+                write_record(&mut writer, &record)?;
+            }
+        }
+        Ok(())
+    })?;
+    writer.flush().map_err(DSError::from)?;
+    Ok(())
+}
+
+/// Feeds everything written to it into a SHA-256 hasher.
+struct HashingWriter(Sha256);
+
+impl Write for HashingWriter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.update(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Hashes the stream `export_ndjson` writes with SHA-256, without keeping
+/// the stream anywhere.
+pub(crate) fn export_checksum(datastore: &SledDatastore) -> indradb::Result<[u8; 32]> {
+    let mut hasher = HashingWriter(Sha256::new());
+    export_ndjson(datastore, &mut hasher)?;
+    Ok(hasher.0.finalize().into())
+}
+
+/// Replays a stream written by `export_ndjson` through `bulk_insert`, in
+/// chunks of `chunk_size` records. Blank lines are skipped, and the first
+/// malformed line aborts the import; chunks inserted before it are kept. The
+/// database is flushed once the whole stream is in.
+pub(crate) fn import_ndjson<R: Read>(
+    datastore: &SledDatastore,
+    reader: R,
+    chunk_size: usize,
+) -> indradb::Result<ImportStats> {
+    let mut txn = datastore.transaction();
+    let mut stats = ImportStats::default();
+    let mut items = Vec::new();
+    for (i, line) in BufReader::new(reader).lines().enumerate() {
+        let line = line.map_err(DSError::from)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record: NdjsonRecord =
+            serde_json::from_str(&line).map_err(|err| DSError::InvalidExport(format!("line {}: {}", i + 1, err)))?;
+        items.push(record.into_bulk_insert_item(&mut stats)?);
+        if items.len() >= chunk_size {
+            txn.bulk_insert(std::mem::take(&mut items))?;
+        }
+    }
+    if !items.is_empty() {
+        txn.bulk_insert(items)?;
+    }
+    map_err(datastore.holder.db.flush())?;
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod test {
+    use indradb::{Datastore, Edge, Identifier, Json, Transaction, Vertex};
+    use serde_json::json;
+    use sha2::{Digest, Sha256};
+
+    use super::ImportStats;
+    use crate::SledDatastore;
+
+    #[test]
+    fn test_json_round_trip() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("thing").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+        let values = [
+            json!("text"),
+            json!(42),
+            json!(-1.5),
+            json!(true),
+            json!(null),
+            json!([1, "two", [3]]),
+            json!({"nested": {"list": [false]}}),
+        ];
+        let names: Vec<Identifier> = (0..values.len())
+            .map(|i| Identifier::new(format!("p{}", i)).unwrap())
+            .collect();
+        let mut txn = source.transaction();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        for (name, value) in names.iter().zip(values.iter()) {
+            txn.set_vertex_properties(vec![a.id], *name, &Json::new(value.clone()))
+                .unwrap();
+            txn.set_edge_properties(vec![edge.clone()], *name, &Json::new(value.clone()))
+                .unwrap();
+        }
+
+        let mut document = Vec::new();
+        source.export_json(&mut document).unwrap();
+        let parsed: serde_json::Value = serde_json::from_slice(&document).unwrap();
+        assert_eq!(parsed["vertices"].as_array().unwrap().len(), 2);
+        assert_eq!(parsed["edges"].as_array().unwrap().len(), 1);
+
+        let target = SledDatastore::new_temporary().unwrap();
+        target.import_json(document.as_slice()).unwrap();
+        let txn = target.transaction();
+        assert_eq!(txn.vertex_count(), 2);
+        assert_eq!(txn.specific_vertices(vec![b.id]).unwrap().next().unwrap().unwrap(), b);
+        assert_eq!(txn.specific_edges(vec![edge.clone()]).unwrap().count(), 1);
+        for (name, value) in names.iter().zip(values.iter()) {
+            assert_eq!(*txn.vertex_property(&a, *name).unwrap().unwrap(), *value);
+            assert_eq!(*txn.edge_property(&edge, *name).unwrap().unwrap(), *value);
+        }
+        assert_eq!(txn.vertex_property(&b, names[0]).unwrap(), None);
+    }
+
+    #[test]
+    fn test_ndjson_round_trip() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("thing").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+        let mut txn = source.transaction();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!({"first": "ada"})))
+            .unwrap();
+        txn.set_edge_properties(vec![edge.clone()], name, &Json::new(json!([1, 2])))
+            .unwrap();
+
+        let mut stream = Vec::new();
+        source.export_ndjson(&mut stream).unwrap();
+        let types: Vec<String> = String::from_utf8(stream.clone())
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["type"].to_string())
+            .collect();
+        assert_eq!(
+            types,
+            vec![
+                "\"vertex\"",
+                "\"vertex\"",
+                "\"edge\"",
+                "\"vertex_property\"",
+                "\"edge_property\""
+            ]
+        );
+
+        let target = SledDatastore::new_temporary().unwrap();
+        target.import_ndjson(stream.as_slice()).unwrap();
+        let txn = target.transaction();
+        let mut vertices: Vec<Vertex> = txn.all_vertices().unwrap().map(|v| v.unwrap()).collect();
+        vertices.sort_by_key(|v| v.id);
+        let mut expected = vec![a.clone(), b];
+        expected.sort_by_key(|v| v.id);
+        assert_eq!(vertices, expected);
+        assert_eq!(
+            txn.all_edges().unwrap().map(|e| e.unwrap()).collect::<Vec<_>>(),
+            vec![edge.clone()]
+        );
+        assert_eq!(
+            *txn.vertex_property(&a, name).unwrap().unwrap(),
+            json!({"first": "ada"})
+        );
+        assert_eq!(*txn.edge_property(&edge, name).unwrap().unwrap(), json!([1, 2]));
+    }
+
+    #[test]
+    fn test_ndjson_export_is_canonical() {
+        let t = Identifier::new("thing").unwrap();
+        let long_type = Identifier::new("a_long_type").unwrap();
+        let short_type = Identifier::new("b").unwrap();
+        let long_name = Identifier::new("aa").unwrap();
+        let short_name = Identifier::new("b").unwrap();
+        let vertices: Vec<Vertex> = (0..4).map(|_| Vertex::new(t)).collect();
+        let edges: Vec<Edge> = vertices
+            .windows(2)
+            .flat_map(|pair| {
+                [
+                    Edge::new(pair[0].id, short_type, pair[1].id),
+                    Edge::new(pair[0].id, long_type, pair[1].id),
+                ]
+            })
+            .collect();
+
+        let first = SledDatastore::new_temporary().unwrap();
+        let mut txn = first.transaction();
+        for v in &vertices {
+            txn.create_vertex(v).unwrap();
+            txn.set_vertex_properties(vec![v.id], short_name, &Json::new(json!(1)))
+                .unwrap();
+            txn.set_vertex_properties(vec![v.id], long_name, &Json::new(json!({"z": 1, "a": [2]})))
+                .unwrap();
+        }
+        for edge in &edges {
+            txn.create_edge(edge).unwrap();
+            txn.set_edge_properties(vec![edge.clone()], long_name, &Json::new(json!("x")))
+                .unwrap();
+        }
+
+        let second = SledDatastore::new_temporary().unwrap();
+        let mut txn = second.transaction();
+        for v in vertices.iter().rev() {
+            txn.create_vertex(v).unwrap();
+            txn.set_vertex_properties(vec![v.id], long_name, &Json::new(json!({"a": [2], "z": 1})))
+                .unwrap();
+            txn.set_vertex_properties(vec![v.id], short_name, &Json::new(json!(1)))
+                .unwrap();
+        }
+        for edge in edges.iter().rev() {
+            txn.create_edge(edge).unwrap();
+            txn.set_edge_properties(vec![edge.clone()], long_name, &Json::new(json!("x")))
+                .unwrap();
+        }
+
+        let mut stream = Vec::new();
+        first.export_ndjson(&mut stream).unwrap();
+        let mut again = Vec::new();
+        first.export_ndjson(&mut again).unwrap();
+        assert_eq!(stream, again);
+        let mut other = Vec::new();
+        second.export_ndjson(&mut other).unwrap();
+        assert_eq!(stream, other);
+
+        let checksum: [u8; 32] = Sha256::digest(&stream).into();
+        assert_eq!(first.export_checksum().unwrap(), checksum);
+        assert_eq!(second.export_checksum().unwrap(), checksum);
+
+        // Types and property names are ordered by name, not by their
+        // length-prefixed keys
+        let records: Vec<serde_json::Value> = String::from_utf8(stream)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        let first_edge = records.iter().find(|r| r["type"] == json!("edge")).unwrap();
+        assert_eq!(first_edge["t"], json!("a_long_type"));
+        let first_property = records.iter().find(|r| r["type"] == json!("vertex_property")).unwrap();
+        assert_eq!(first_property["name"], json!("aa"));
+
+        first.transaction().create_vertex(&Vertex::new(t)).unwrap();
+        assert_ne!(first.export_checksum().unwrap(), checksum);
+    }
+
+    #[test]
+    fn test_import_from_reader_round_trip() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let person = Identifier::new("person").unwrap();
+        let knows = Identifier::new("knows").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let since = Identifier::new("since").unwrap();
+        let mut txn = source.transaction();
+        let vertices: Vec<Vertex> = (0..10).map(|_| Vertex::new(person)).collect();
+        for (i, v) in vertices.iter().enumerate() {
+            txn.create_vertex(v).unwrap();
+            if i % 2 == 0 {
+                txn.set_vertex_properties(vec![v.id], name, &Json::new(json!(format!("p{}", i))))
+                    .unwrap();
+            }
+        }
+        let edges: Vec<Edge> = vertices
+            .windows(2)
+            .map(|pair| Edge::new(pair[0].id, knows, pair[1].id))
+            .collect();
+        for (i, edge) in edges.iter().enumerate() {
+            txn.create_edge(edge).unwrap();
+            txn.set_edge_properties(vec![edge.clone()], since, &Json::new(json!({"year": 2000 + i})))
+                .unwrap();
+        }
+
+        let mut stream = Vec::new();
+        source.export_to_writer(&mut stream).unwrap();
+        let text = String::from_utf8(stream.clone()).unwrap();
+        assert_eq!(text.lines().count(), vertices.len() + edges.len() + 14);
+        let mut ndjson = Vec::new();
+        source.export_ndjson(&mut ndjson).unwrap();
+        assert_eq!(ndjson, stream);
+        let first: serde_json::Value = serde_json::from_str(text.lines().next().unwrap()).unwrap();
+        assert_eq!(first["type"], json!("vertex"));
+
+        let target = SledDatastore::new_temporary().unwrap();
+        let stats = target.import_from_reader(stream.as_slice()).unwrap();
+        assert_eq!(
+            stats,
+            ImportStats {
+                vertices: 10,
+                edges: 9,
+                properties: 14,
+            }
+        );
+        let mut round_tripped = Vec::new();
+        target.export_to_writer(&mut round_tripped).unwrap();
+        assert_eq!(round_tripped, stream);
+
+        let src = source.transaction();
+        let dst = target.transaction();
+        assert_eq!(dst.vertex_count(), vertices.len() as u64);
+        assert_eq!(dst.edge_count(), edges.len() as u64);
+        for v in &vertices {
+            assert_eq!(
+                dst.vertex_property(v, name).unwrap(),
+                src.vertex_property(v, name).unwrap()
+            );
+        }
+        for edge in &edges {
+            assert_eq!(
+                dst.edge_property(edge, since).unwrap(),
+                src.edge_property(edge, since).unwrap()
+            );
+        }
+
+        let chunked = SledDatastore::new_temporary().unwrap();
+        assert_eq!(
+            chunked.import_from_reader_in_chunks(stream.as_slice(), 3).unwrap(),
+            stats
+        );
+        assert_eq!(chunked.transaction().vertex_count(), vertices.len() as u64);
+        assert_eq!(chunked.transaction().edge_count(), edges.len() as u64);
+    }
+
+    #[test]
+    fn test_import_from_reader_rejects_malformed_line() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let v = Vertex::new(Identifier::new("thing").unwrap());
+        source.transaction().create_vertex(&v).unwrap();
+        let mut stream = Vec::new();
+        source.export_to_writer(&mut stream).unwrap();
+        stream.extend_from_slice(b"{\"type\":\"vertex\"\n");
+
+        let target = SledDatastore::new_temporary().unwrap();
+        let Err(indradb::Error::Datastore(err)) = target.import_from_reader(stream.as_slice()) else {
+            panic!("imported a malformed line");
+        };
+        assert!(err.to_string().contains("line 2"), "{}", err);
+    }
+}
@@ -0,0 +1,261 @@
+//! Latency histograms for the sled calls made by the managers.
+//!
+//! With the `latency-metrics` feature enabled, every sled call made through
+//! `timed!` or `timed_iter` records its duration into a process-wide,
+//! log-bucketed histogram per operation. Without the feature, both expand to
+//! the bare call.
+
+#[cfg(feature = "latency-metrics")]
+use std::sync::atomic::{AtomicU64, Ordering};
+#[cfg(feature = "latency-metrics")]
+use std::time::Duration;
+
+use sled::Iter as DbIterator;
+
+/// Runs a sled call, recording its duration under the given `Operation`
+/// variant.
+#[cfg(feature = "latency-metrics")]
+macro_rules! timed {
+    ($op:ident, $call:expr) => {{
+        let start = std::time::Instant::now();
+        let result = $call;
+        crate::latency::record(crate::latency::Operation::$op, start.elapsed());
+        result
+    }};
+}
+
+/// Runs a sled call, recording its duration under the given `Operation`
+/// variant.
+#[cfg(not(feature = "latency-metrics"))]
+macro_rules! timed {
+    ($op:ident, $call:expr) => {
+        $call
+    };
+}
+
+/// The kinds of sled calls that are timed.
+#[cfg(feature = "latency-metrics")]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Operation {
+    Get,
+    Insert,
+    Remove,
+    ApplyBatch,
+    ScanNext,
+    Flush,
+}
+
+#[cfg(feature = "latency-metrics")]
+impl Operation {
+    /// All operations, in report order.
+    pub const ALL: [Operation; 6] = [
+        Operation::Get,
+        Operation::Insert,
+        Operation::Remove,
+        Operation::ApplyBatch,
+        Operation::ScanNext,
+        Operation::Flush,
+    ];
+}
+
+/// Wraps a sled iterator so that every step records a `ScanNext` latency.
+#[cfg(feature = "latency-metrics")]
+pub(crate) fn timed_iter(iter: DbIterator) -> TimedIter {
+    TimedIter(iter)
+}
+
+/// Wraps a sled iterator so that every step records a `ScanNext` latency.
+#[cfg(not(feature = "latency-metrics"))]
+pub(crate) fn timed_iter(iter: DbIterator) -> DbIterator {
+    iter
+}
+
+#[cfg(feature = "latency-metrics")]
+pub(crate) struct TimedIter(DbIterator);
+
+#[cfg(feature = "latency-metrics")]
+impl Iterator for TimedIter {
+    type Item = sled::Result<(sled::IVec, sled::IVec)>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        timed!(ScanNext, self.0.next())
+    }
+}
+
+#[cfg(feature = "latency-metrics")]
+impl DoubleEndedIterator for TimedIter {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        timed!(ScanNext, self.0.next_back())
+    }
+}
+
+#[cfg(feature = "latency-metrics")]
+const BUCKETS: usize = 64;
+
+/// A fixed-size histogram of durations in nanoseconds. Bucket `i` holds
+/// durations in `[2^(i-1), 2^i - 1]`, and bucket 0 holds zero.
+#[cfg(feature = "latency-metrics")]
+pub(crate) struct Histogram {
+    buckets: [AtomicU64; BUCKETS],
+}
+
+#[cfg(feature = "latency-metrics")]
+impl Histogram {
+    const fn new() -> Self {
+        Histogram {
+            buckets: [const { AtomicU64::new(0) }; BUCKETS],
+        }
+    }
+
+    fn record(&self, duration: Duration) {
+        let nanos = u64::try_from(duration.as_nanos()).unwrap_or(u64::MAX);
+        let bucket = ((u64::BITS - nanos.leading_zeros()) as usize).min(BUCKETS - 1);
+        self.buckets[bucket].fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn snapshot(&self) -> HistogramSnapshot {
+        let mut buckets = [0; BUCKETS];
+        for (count, bucket) in buckets.iter_mut().zip(self.buckets.iter()) {
+            *count = bucket.load(Ordering::Relaxed);
+        }
+        HistogramSnapshot { buckets }
+    }
+
+    fn reset(&self) {
+        for bucket in self.buckets.iter() {
+            bucket.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// A point-in-time copy of a latency histogram.
+#[cfg(feature = "latency-metrics")]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HistogramSnapshot {
+    buckets: [u64; BUCKETS],
+}
+
+#[cfg(feature = "latency-metrics")]
+impl HistogramSnapshot {
+    /// Gets the number of recorded calls.
+    pub fn count(&self) -> u64 {
+        self.buckets.iter().sum()
+    }
+
+    /// Gets an upper bound in nanoseconds for the given percentile, or
+    /// `None` if nothing was recorded. Values are accurate to a factor of two.
+    ///
+    /// # Arguments
+    /// * `percentile`: The percentile, between 0 and 100.
+    pub fn percentile(&self, percentile: f64) -> Option<u64> {
+        let count = self.count();
+        if count == 0 {
+            return None;
+        }
+        let rank = ((percentile.clamp(0.0, 100.0) / 100.0 * count as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (i, bucket) in self.buckets.iter().enumerate() {
+            seen += bucket;
+            if seen >= rank {
+                return Some(if i == 0 { 0 } else { (1u64 << i) - 1 });
+            }
+        }
+        Some(u64::MAX)
+    }
+}
+
+/// Latency histograms per operation, as returned by
+/// `SledDatastore::latency_report`.
+#[cfg(feature = "latency-metrics")]
+#[derive(Clone, Debug)]
+pub struct LatencyReport {
+    pub operations: Vec<(Operation, HistogramSnapshot)>,
+}
+
+#[cfg(feature = "latency-metrics")]
+impl LatencyReport {
+    /// Gets the histogram of an operation.
+    ///
+    /// # Arguments
+    /// * `operation`: The operation to get.
+    pub fn get(&self, operation: Operation) -> &HistogramSnapshot {
+        &self
+            .operations
+            .iter()
+            .find(|(op, _)| *op == operation)
+            .expect("every operation is reported")
+            .1
+    }
+}
+
+#[cfg(feature = "latency-metrics")]
+static RECORDER: [Histogram; Operation::ALL.len()] = [const { Histogram::new() }; Operation::ALL.len()];
+
+#[cfg(feature = "latency-metrics")]
+pub(crate) fn record(operation: Operation, duration: Duration) {
+    RECORDER[operation as usize].record(duration);
+}
+
+#[cfg(feature = "latency-metrics")]
+pub(crate) fn report() -> LatencyReport {
+    LatencyReport {
+        operations: Operation::ALL
+            .iter()
+            .map(|op| (*op, RECORDER[*op as usize].snapshot()))
+            .collect(),
+    }
+}
+
+#[cfg(feature = "latency-metrics")]
+pub(crate) fn reset() {
+    for histogram in RECORDER.iter() {
+        histogram.reset();
+    }
+}
+
+#[cfg(all(test, feature = "latency-metrics"))]
+mod test {
+    use indradb::{BulkInsertItem, Datastore, Identifier, Transaction, Vertex};
+
+    use super::*;
+    use crate::SledDatastore;
+
+    #[test]
+    fn test_percentiles() {
+        let histogram = Histogram::new();
+        assert_eq!(histogram.snapshot().percentile(50.0), None);
+        for _ in 0..90 {
+            histogram.record(Duration::from_nanos(10));
+        }
+        for _ in 0..10 {
+            histogram.record(Duration::from_nanos(1_000));
+        }
+        let snapshot = histogram.snapshot();
+        assert_eq!(snapshot.count(), 100);
+        assert_eq!(snapshot.percentile(50.0), Some(15));
+        assert_eq!(snapshot.percentile(90.0), Some(15));
+        assert_eq!(snapshot.percentile(99.0), Some(1_023));
+        assert_eq!(snapshot.percentile(100.0), Some(1_023));
+
+        histogram.reset();
+        assert_eq!(histogram.snapshot().count(), 0);
+    }
+
+    #[test]
+    fn test_workload_populates_report() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("foo").unwrap();
+        let v = Vertex::new(t);
+        txn.create_vertex(&v).unwrap();
+        txn.delete_vertices(vec![v.clone()]).unwrap();
+        txn.bulk_insert(vec![BulkInsertItem::Vertex(Vertex::new(t))]).unwrap();
+        assert_eq!(txn.all_vertices().unwrap().count(), 1);
+        txn.sync().unwrap();
+
+        let report = ds.latency_report();
+        for op in Operation::ALL {
+            assert!(report.get(op).count() > 0, "{:?} wasn't recorded", op);
+        }
+    }
+}
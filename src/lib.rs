@@ -9,21 +9,55 @@ extern crate ecow;
 extern crate indradb;
 #[cfg(not(any(feature = "bench-suite", feature = "test-suite")))]
 extern crate indradb;
+extern crate serde;
 extern crate serde_json;
 extern crate sled;
 #[cfg(any(feature = "bench-suite", feature = "test-suite"))]
 extern crate tempfile;
+#[cfg(feature = "bench-suite")]
+extern crate test;
 extern crate thiserror;
 extern crate uuid;
 
 use indradb::Edge;
 
-pub use self::datastore::{SledConfig, SledDatastore};
+pub use self::batched::BatchedTransaction;
+pub use self::counters::{CounterBehavior, PersistentCounter, SequenceAllocator};
+pub use self::datastore::{
+    CompactStats, DatastoreStats, RebuildStats, RecoveryReport, SledConfig, SledDatastore, StorageStats, TreeSize,
+};
+pub use self::encoding::{PropertyEncoding, ValueCodec};
+pub use self::integrity::{
+    IndexInconsistency, IntegrityReport, IntegrityViolation, RepairStats, INTEGRITY_SAMPLE_SIZE,
+};
+pub use self::json_export::ImportStats;
+#[cfg(feature = "latency-metrics")]
+pub use self::latency::{HistogramSnapshot, LatencyReport, Operation};
+pub use self::read_only::{ReadOnlyTransaction, SledDatastoreReadOnly};
+#[cfg(feature = "server-adapter")]
+pub use self::server_adapter::OwnedTransactionHolder;
+pub use self::transaction::{CreateEdgeOutcome, NeighborDirection, SledTransaction, VertexWithProperties};
+pub use self::watch::{VertexEvent, VertexWatcher};
 
+// Declared first so that `timed!` is in scope for the modules below.
+#[macro_use]
+mod latency;
+
+mod batched;
+mod counters;
 mod datastore;
+mod encoding;
 mod errors;
+mod integrity;
+mod json_export;
 mod managers;
+mod migrations;
+mod read_only;
+#[cfg(feature = "server-adapter")]
+mod server_adapter;
+mod snapshot;
 mod transaction;
+mod watch;
 
 mod normal_config {
 
@@ -32,7 +66,7 @@ mod normal_config {
         use super::SledDatastore;
         use indradb::Database;
         use tempfile::tempdir;
-        let path = tempdir().unwrap().into_path();
+        let path = tempdir().unwrap().keep();
         Database::new(SledDatastore::new(path).unwrap())
     });
 
@@ -41,7 +75,7 @@ mod normal_config {
         use super::SledDatastore;
         use indradb::Database;
         use tempfile::tempdir;
-        let path = tempdir().unwrap().into_path();
+        let path = tempdir().unwrap().keep();
         Database::new(SledDatastore::new(path).unwrap())
     });
 }
@@ -53,7 +87,7 @@ mod compression_config {
         use super::SledConfig;
         use indradb::Database;
         use tempfile::tempdir;
-        let path = tempdir().unwrap().into_path();
+        let path = tempdir().unwrap().keep();
         Database::new(SledConfig::with_compression(None).open(path).unwrap())
     });
 
@@ -62,7 +96,7 @@ mod compression_config {
         use super::SledConfig;
         use indradb::Database;
         use tempfile::tempdir;
-        let path = tempdir().unwrap().into_path();
+        let path = tempdir().unwrap().keep();
         Database::new(SledConfig::with_compression(None).open(path).unwrap())
     });
 }
@@ -1,11 +1,19 @@
-use indradb::{util, Edge};
+use std::collections::{HashMap, HashSet};
+use std::io::Cursor;
+
+use indradb::{util, Edge, Identifier};
 use sled::{Batch, IVec, Tree};
 
+use crate::counters::{decode_count, encode_count, CounterBehavior, PersistentCounter};
 use crate::datastore::SledHolder;
-use crate::errors::map_err;
-use crate::managers::edge_property_manager::EdgePropertyManager;
+use crate::errors::{map_err, map_err_ctx};
+use crate::latency::timed_iter;
+use crate::managers::count_keys;
 use crate::managers::edge_range_manager::EdgeRangeManager;
+use crate::managers::edge_type_manager::EdgeTypeManager;
+use crate::managers::metadata::{EDGE_TYPE_COUNTS, EDGE_TYPE_COUNTS_READY};
 use crate::reverse_edge;
+use crate::transaction::IndraSledDeleteBatch;
 
 pub struct EdgeManager<'db: 'tree, 'tree> {
     pub holder: &'db SledHolder,
@@ -20,7 +28,7 @@ impl<'db, 'tree> EdgeManager<'db, 'tree> {
         }
     }
 
-    fn key(&self, edge: Edge) -> Vec<u8> {
+    pub(crate) fn key(&self, edge: Edge) -> Vec<u8> {
         util::build(&[
             util::Component::Uuid(edge.outbound_id),
             util::Component::Identifier(edge.t),
@@ -28,8 +36,94 @@ impl<'db, 'tree> EdgeManager<'db, 'tree> {
         ])
     }
 
+    /// Iterates over every edge in the tree. The keys share the layout of
+    /// the edge ranges, so they're decoded the same way.
+    pub fn iterate_for_all(&self) -> impl Iterator<Item = indradb::Result<Edge>> + 'tree {
+        EdgeRangeManager { tree: self.tree }.iterate_for_all()
+    }
+
     pub fn count(&self) -> u64 {
-        self.tree.iter().count() as u64
+        count_keys(self.tree)
+    }
+
+    pub(crate) fn type_count_key(t: Identifier) -> indradb::Result<Vec<u8>> {
+        Ok(util::build(&[
+            util::Component::Identifier(Identifier::new(EDGE_TYPE_COUNTS)?),
+            util::Component::Identifier(t),
+        ]))
+    }
+
+    fn type_counter(&self, t: Identifier) -> indradb::Result<PersistentCounter<'db>> {
+        Ok(PersistentCounter::new(
+            &self.holder.metadata,
+            Self::type_count_key(t)?,
+            CounterBehavior::Saturate,
+        ))
+    }
+
+    pub fn count_for_type(&self, t: Identifier) -> indradb::Result<u64> {
+        self.ensure_type_counts()?;
+        self.type_counter(t)?.get()
+    }
+
+    /// Reads every per-type edge counter. Types whose edges were all deleted
+    /// keep a counter of zero.
+    pub fn type_counts(&self) -> indradb::Result<Vec<(Identifier, u64)>> {
+        self.ensure_type_counts()?;
+        let prefix = util::build(&[util::Component::Identifier(Identifier::new(EDGE_TYPE_COUNTS)?)]);
+        let mut counts = Vec::new();
+        for item in timed_iter(self.holder.metadata.scan_prefix(prefix)) {
+            let (key, value) = map_err(item)?;
+            let mut cursor = Cursor::new(key);
+            let _ = util::read_identifier(&mut cursor);
+            counts.push((util::read_identifier(&mut cursor), decode_count(&value)));
+        }
+        Ok(counts)
+    }
+
+    /// Sums the per-type edge counters, which is the number of edges as long
+    /// as the counters haven't drifted from the edges after a crash.
+    pub fn count_from_type_counters(&self) -> indradb::Result<u64> {
+        Ok(self.type_counts()?.into_iter().map(|(_, count)| count).sum())
+    }
+
+    pub fn update_count_for_type(&self, t: Identifier, delta: i64) -> indradb::Result<()> {
+        self.type_counter(t)?.add(delta)?;
+        Ok(())
+    }
+
+    /// Builds the per-type edge counters from the edge ranges, for stores
+    /// that were written before the counters were maintained.
+    pub(crate) fn ensure_type_counts(&self) -> indradb::Result<()> {
+        let ready_key = util::build(&[util::Component::Identifier(Identifier::new(EDGE_TYPE_COUNTS_READY)?)]);
+        if map_err_ctx(
+            "contains_key",
+            &self.holder.metadata,
+            timed!(Get, self.holder.metadata.contains_key(&ready_key)),
+        )? {
+            return Ok(());
+        }
+
+        let mut counts: HashMap<Identifier, u64> = HashMap::new();
+        for item in EdgeRangeManager::new(self.holder).iterate_for_all() {
+            *counts.entry(item?.t).or_insert(0) += 1;
+        }
+
+        let mut batch = Batch::default();
+        let prefix = util::build(&[util::Component::Identifier(Identifier::new(EDGE_TYPE_COUNTS)?)]);
+        for item in timed_iter(self.holder.metadata.scan_prefix(prefix)) {
+            let (key, _) = map_err(item)?;
+            batch.remove(key);
+        }
+        for (t, count) in counts {
+            batch.insert(Self::type_count_key(t)?, &encode_count(count));
+        }
+        batch.insert(ready_key, IVec::default());
+        map_err_ctx(
+            "apply_batch",
+            &self.holder.metadata,
+            timed!(ApplyBatch, self.holder.metadata.apply_batch(batch)),
+        )
     }
 
     pub fn set_batch(
@@ -38,38 +132,110 @@ impl<'db, 'tree> EdgeManager<'db, 'tree> {
         batch: &mut Batch,
         range_batch: &mut Batch,
         range_rev_batch: &mut Batch,
+        type_batch: &mut Batch,
+        creation_set: &mut HashSet<Edge>,
     ) -> indradb::Result<()> {
         let key = self.key(edge.clone());
+        if !map_err_ctx("contains_key", self.tree, timed!(Get, self.tree.contains_key(&key)))? {
+            creation_set.insert(edge.clone());
+        }
         batch.insert(key, IVec::default());
         let edge_range_manager = EdgeRangeManager::new(self.holder);
         edge_range_manager.set_batch(edge, range_batch)?;
-        let edge_range_manager_rev = EdgeRangeManager::new_reversed(self.holder);
-        edge_range_manager_rev.set_batch(&reverse_edge(edge), range_rev_batch)?;
+        if self.holder.reversed_edges {
+            let edge_range_manager_rev = EdgeRangeManager::new_reversed(self.holder);
+            edge_range_manager_rev.set_batch(&reverse_edge(edge), range_rev_batch)?;
+        }
+        EdgeTypeManager::new(self.holder).set_batch(edge, type_batch)?;
         Ok(())
     }
 
-    pub fn set(&self, edge: &Edge) -> indradb::Result<()> {
+    /// Queues the removal of an edge from every edge tree, the counterpart of
+    /// `set_batch`. An edge that exists is added to `deletion_set`.
+    pub fn delete_batch(
+        &self,
+        edge: &Edge,
+        batch: &mut Batch,
+        range_batch: &mut Batch,
+        range_rev_batch: &mut Batch,
+        type_batch: &mut Batch,
+        deletion_set: &mut HashSet<Edge>,
+    ) -> indradb::Result<()> {
+        let key = self.key(edge.clone());
+        if map_err_ctx("contains_key", self.tree, timed!(Get, self.tree.contains_key(&key)))? {
+            deletion_set.insert(edge.clone());
+        }
+        batch.remove(key);
+        EdgeRangeManager::new(self.holder).delete_batch(edge, range_batch)?;
+        if self.holder.reversed_edges {
+            EdgeRangeManager::new_reversed(self.holder).delete_batch(&reverse_edge(edge), range_rev_batch)?;
+        }
+        EdgeTypeManager::new(self.holder).delete_batch(edge, type_batch)
+    }
+
+    /// Queues the removal of an edge from every edge tree, along with its
+    /// properties. An edge that exists is added to the batch's deletion set,
+    /// so that its type counter is decremented once the batch is applied.
+    pub(crate) fn collect_delete_keys(&self, edge: &Edge, batch: &mut IndraSledDeleteBatch) -> indradb::Result<()> {
+        self.delete_batch(
+            edge,
+            &mut batch.edge_deletion_batch,
+            &mut batch.edge_range_deletion_batch,
+            &mut batch.edge_range_rev_deletion_batch,
+            &mut batch.edge_type_deletion_batch,
+            &mut batch.edge_deletion_set,
+        )?;
+        self.holder.edge_property_manager().delete_for_owner_batch(
+            edge,
+            &mut batch.edge_property_deletion_batch,
+            &mut batch.edge_property_value_deletion_batch,
+        )
+    }
+
+    /// Writes an edge to every edge tree, returning whether it is new rather
+    /// than an overwrite of an existing edge.
+    pub fn set(&self, edge: &Edge) -> indradb::Result<bool> {
         let edge_range_manager = EdgeRangeManager::new(self.holder);
-        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.holder);
 
         let key = self.key(edge.clone());
-        map_err(self.tree.insert(key, IVec::default()))?;
+        let existed = map_err_ctx(
+            "insert",
+            self.tree,
+            timed!(Insert, self.tree.insert(key, IVec::default())),
+        )?
+        .is_some();
         edge_range_manager.set(edge)?;
-        reversed_edge_range_manager.set(&reverse_edge(edge))?;
-        Ok(())
+        if self.holder.reversed_edges {
+            EdgeRangeManager::new_reversed(self.holder).set(&reverse_edge(edge))?;
+        }
+        EdgeTypeManager::new(self.holder).set(edge)?;
+        if !existed {
+            self.update_count_for_type(edge.t, 1)?;
+        }
+        Ok(!existed)
     }
 
     pub fn delete(&self, edge: &Edge) -> indradb::Result<()> {
-        map_err(self.tree.remove(self.key(edge.clone())))?;
+        if map_err_ctx(
+            "remove",
+            self.tree,
+            timed!(Remove, self.tree.remove(self.key(edge.clone()))),
+        )?
+        .is_some()
+        {
+            self.update_count_for_type(edge.t, -1)?;
+        }
 
         let edge_range_manager = EdgeRangeManager::new(self.holder);
         edge_range_manager.delete(edge)?;
 
-        let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.holder);
-        reversed_edge_range_manager.delete(&reverse_edge(edge))?;
+        if self.holder.reversed_edges {
+            let reversed_edge_range_manager = EdgeRangeManager::new_reversed(self.holder);
+            reversed_edge_range_manager.delete(&reverse_edge(edge))?;
+        }
+        EdgeTypeManager::new(self.holder).delete(edge)?;
 
-        let edge_property_manager =
-            EdgePropertyManager::new(&self.holder.edge_properties, &self.holder.edge_property_values);
+        let edge_property_manager = self.holder.edge_property_manager();
 
         for item in edge_property_manager.iterate_for_owner(edge)? {
             let ((edge, id), _) = item?;
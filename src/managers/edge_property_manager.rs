@@ -1,25 +1,57 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Cursor;
 
 use indradb::{util, Edge, Identifier, Json};
 use serde_json::Value as JsonValue;
-use sled::{IVec, Tree};
+use sled::{Batch, IVec, Tree};
 
-use crate::errors::map_err;
+use crate::datastore::RebuildStats;
+use crate::encoding::PropertyCodec;
+use crate::errors::{map_err, map_err_ctx};
+use crate::integrity::{IndexInconsistency, IntegrityReport, IntegrityViolation};
+use crate::latency::timed_iter;
+use crate::managers::BACKFILL_CHUNK_SIZE;
 
 pub type EdgePropertyItem = ((Edge, Identifier), JsonValue);
 
 pub struct EdgePropertyManager<'tree> {
     pub tree: &'tree Tree,
     pub value_index_tree: &'tree Tree,
+    // Whether writes keep `value_index_tree` up to date, see
+    // `SledConfig::maintain_property_value_index`
+    pub maintain_value_index: bool,
+    pub(crate) codec: PropertyCodec,
 }
 
 impl<'tree> EdgePropertyManager<'tree> {
     pub fn new(tree: &'tree Tree, value_index_tree: &'tree Tree) -> Self {
-        EdgePropertyManager { tree, value_index_tree }
+        EdgePropertyManager {
+            tree,
+            value_index_tree,
+            maintain_value_index: true,
+            codec: PropertyCodec::default(),
+        }
+    }
+
+    /// Sets whether writes keep the value index up to date.
+    ///
+    /// # Arguments
+    /// * `maintain`: Whether to maintain the value index.
+    pub fn with_value_index(mut self, maintain: bool) -> Self {
+        self.maintain_value_index = maintain;
+        self
+    }
+
+    /// Sets how property values are serialized and transformed.
+    ///
+    /// # Arguments
+    /// * `codec`: The codec of the property tree.
+    pub(crate) fn with_codec(mut self, codec: PropertyCodec) -> Self {
+        self.codec = codec;
+        self
     }
 
-    fn key(&self, edge: &Edge, name: Identifier) -> Vec<u8> {
+    pub(crate) fn key(&self, edge: &Edge, name: Identifier) -> Vec<u8> {
         util::build(&[
             util::Component::Uuid(edge.outbound_id),
             util::Component::Identifier(edge.t),
@@ -28,7 +60,7 @@ impl<'tree> EdgePropertyManager<'tree> {
         ])
     }
 
-    fn read_key(buf: IVec) -> (Edge, Identifier) {
+    pub(crate) fn read_key(buf: IVec) -> (Edge, Identifier) {
         let mut cursor = Cursor::new(buf.as_ref());
         let edge_property_outbound_id = util::read_uuid(&mut cursor);
         let edge_property_t = util::read_identifier(&mut cursor);
@@ -47,7 +79,7 @@ impl<'tree> EdgePropertyManager<'tree> {
         name: Identifier,
     ) -> indradb::Result<impl Iterator<Item = indradb::Result<Edge>> + '_> {
         let prefix = util::build(&[util::Component::Identifier(name)]);
-        let iterator = self.value_index_tree.scan_prefix(prefix);
+        let iterator = timed_iter(self.value_index_tree.scan_prefix(prefix));
 
         Ok(iterator.map(move |item| -> indradb::Result<Edge> {
             let (k, _v) = map_err(item)?;
@@ -66,7 +98,7 @@ impl<'tree> EdgePropertyManager<'tree> {
             util::Component::Identifier(name),
             util::Component::Json(&Json::new(value)),
         ]);
-        let iterator = self.value_index_tree.scan_prefix(prefix);
+        let iterator = timed_iter(self.value_index_tree.scan_prefix(prefix));
 
         Ok(iterator.map(move |item| -> indradb::Result<Edge> {
             let (k, _) = map_err(item)?;
@@ -85,11 +117,11 @@ impl<'tree> EdgePropertyManager<'tree> {
             util::Component::Uuid(edge.inbound_id),
         ]);
 
-        let iterator = self.tree.scan_prefix(prefix);
+        let iterator = timed_iter(self.tree.scan_prefix(prefix));
         let mapped = iterator.map(move |item| -> indradb::Result<EdgePropertyItem> {
             let (k, v) = map_err(item)?;
             let (edge, p_name) = Self::read_key(k);
-            let value = serde_json::from_slice(&v)?;
+            let value = self.codec.decode(&v)?;
             Ok(((edge, p_name), value))
         });
 
@@ -97,15 +129,19 @@ impl<'tree> EdgePropertyManager<'tree> {
     }
 
     pub fn get(&self, edge: &Edge, name: Identifier) -> indradb::Result<Option<JsonValue>> {
-        let key = self.key(edge, name);
-
-        match map_err(self.tree.get(key))? {
-            Some(ref value_bytes) => Ok(Some(serde_json::from_slice(value_bytes)?)),
+        match self.get_bytes(edge, name)? {
+            Some(ref value_bytes) => Ok(Some(self.codec.decode(value_bytes)?)),
             None => Ok(None),
         }
     }
 
-    fn key_value_index(edge: &Edge, value: &JsonValue, property_name: Identifier) -> Vec<u8> {
+    /// Gets the stored JSON bytes of a property, without parsing them.
+    pub fn get_bytes(&self, edge: &Edge, name: Identifier) -> indradb::Result<Option<IVec>> {
+        let key = self.key(edge, name);
+        map_err_ctx("get", self.tree, timed!(Get, self.tree.get(key)))
+    }
+
+    pub(crate) fn key_value_index(edge: &Edge, value: &JsonValue, property_name: Identifier) -> Vec<u8> {
         util::build(&[
             util::Component::Identifier(property_name),
             util::Component::Json(&Json::new(value.clone())),
@@ -143,11 +179,14 @@ impl<'tree> EdgePropertyManager<'tree> {
         value: &JsonValue,
     ) -> indradb::Result<()> {
         let key = self.key(edge, name);
-        let value_json = serde_json::to_vec(value)?;
+        let value_json = self.codec.encode(value)?;
         batch.insert(key.clone(), value_json);
-        let old_value = map_err(self.tree.get(key.clone()))?;
+        if !self.maintain_value_index {
+            return Ok(());
+        }
+        let old_value = map_err_ctx("get", self.tree, timed!(Get, self.tree.get(key.clone())))?;
         if let Some(old_value) = old_value {
-            let old_value: Json = serde_json::from_slice(&old_value)?;
+            let old_value = self.codec.decode(&old_value)?;
             let value_key = Self::key_value_index(edge, &old_value, name);
             batch_value.remove(value_key.as_slice());
         }
@@ -158,32 +197,295 @@ impl<'tree> EdgePropertyManager<'tree> {
 
     pub fn set(&self, edge: &Edge, name: Identifier, value: &JsonValue) -> indradb::Result<()> {
         let key = self.key(edge, name);
-        let value_json = serde_json::to_vec(value)?;
+        let value_json = self.codec.encode(value)?;
 
-        let old_value = map_err(self.tree.get(key.clone()))?;
+        let old_value = map_err_ctx(
+            "insert",
+            self.tree,
+            timed!(Insert, self.tree.insert(key.as_slice(), value_json.as_slice())),
+        )?;
+        if !self.maintain_value_index {
+            return Ok(());
+        }
         if let Some(old_value) = old_value {
-            let old_value: Json = serde_json::from_slice(&old_value)?;
+            let old_value = self.codec.decode(&old_value)?;
             let value_key = Self::key_value_index(edge, &old_value, name);
-            map_err(self.value_index_tree.remove(value_key.as_slice()))?;
+            map_err_ctx(
+                "remove",
+                self.value_index_tree,
+                timed!(Remove, self.value_index_tree.remove(value_key.as_slice())),
+            )?;
         }
 
-        map_err(self.tree.insert(key.as_slice(), value_json.as_slice()))?;
         let value_key = Self::key_value_index(edge, value, name);
 
-        map_err(
-            self.value_index_tree
-                .insert(value_key.as_slice(), value_json.as_slice()),
+        map_err_ctx(
+            "insert",
+            self.value_index_tree,
+            timed!(
+                Insert,
+                self.value_index_tree
+                    .insert(value_key.as_slice(), value_json.as_slice())
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Writes the value-index entries for every stored property whose name is
+    /// in `names`, using a single scan over the property tree. Entries are
+    /// applied in chunks so large stores don't build one huge batch.
+    pub fn backfill_value_index(&self, names: &HashSet<Identifier>) -> indradb::Result<()> {
+        self.backfill_where(|name| names.contains(&name))?;
+        Ok(())
+    }
+
+    /// Writes the value-index entries for every stored property whose name
+    /// passes `keep`, returning how many were written.
+    fn backfill_where(&self, keep: impl Fn(Identifier) -> bool) -> indradb::Result<u64> {
+        let mut batch = Batch::default();
+        let mut pending = 0;
+        let mut written = 0;
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (edge, name) = Self::read_key(k);
+            if !keep(name) {
+                continue;
+            }
+            let value = self.codec.decode(&v)?;
+            batch.insert(Self::key_value_index(&edge, &value, name), v);
+            written += 1;
+            pending += 1;
+            if pending == BACKFILL_CHUNK_SIZE {
+                map_err_ctx(
+                    "apply_batch",
+                    self.value_index_tree,
+                    timed!(
+                        ApplyBatch,
+                        self.value_index_tree.apply_batch(std::mem::take(&mut batch))
+                    ),
+                )?;
+                pending = 0;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(batch)),
+        )?;
+        Ok(written)
+    }
+
+    /// Removes the value-index entries of the property `name`, or of every
+    /// property if it's `None`, and writes them again from the property
+    /// tree.
+    pub fn rebuild_value_index(&self, name: Option<Identifier>) -> indradb::Result<RebuildStats> {
+        let prefix = name.map_or_else(Vec::new, |name| util::build(&[util::Component::Identifier(name)]));
+        let mut stats = RebuildStats::default();
+        let mut batch = Batch::default();
+        let mut pending = 0;
+        for item in timed_iter(self.value_index_tree.scan_prefix(prefix)) {
+            let (k, _) = map_err(item)?;
+            batch.remove(k);
+            stats.removed += 1;
+            pending += 1;
+            if pending == BACKFILL_CHUNK_SIZE {
+                map_err_ctx(
+                    "apply_batch",
+                    self.value_index_tree,
+                    timed!(
+                        ApplyBatch,
+                        self.value_index_tree.apply_batch(std::mem::take(&mut batch))
+                    ),
+                )?;
+                pending = 0;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(batch)),
+        )?;
+        stats.added = self.backfill_where(|stored| name.is_none_or(|name| name == stored))?;
+        Ok(stats)
+    }
+
+    /// Cross-checks the property tree against the value index for every
+    /// property in `names`.
+    pub fn verify_value_index(&self, names: &HashSet<Identifier>) -> indradb::Result<Vec<IndexInconsistency>> {
+        let mut inconsistencies = Vec::new();
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (edge, name) = Self::read_key(k);
+            if !names.contains(&name) {
+                continue;
+            }
+            let value = self.codec.decode(&v)?;
+            let value_key = Self::key_value_index(&edge, &value, name);
+            if self.maintain_value_index
+                && !map_err_ctx(
+                    "contains_key",
+                    self.value_index_tree,
+                    timed!(Get, self.value_index_tree.contains_key(value_key)),
+                )?
+            {
+                inconsistencies.push(IndexInconsistency::MissingEdgeIndex { edge, name });
+            }
+        }
+        for name in names.iter() {
+            let prefix = util::build(&[util::Component::Identifier(*name)]);
+            for item in timed_iter(self.value_index_tree.scan_prefix(prefix)) {
+                let (k, _) = map_err(item)?;
+                let (_, _, edge) = Self::read_key_value_index(k.clone());
+                let is_consistent = match self.get(&edge, *name)? {
+                    Some(value) => Self::key_value_index(&edge, &value, *name) == *k,
+                    None => false,
+                };
+                if !is_consistent {
+                    inconsistencies.push(IndexInconsistency::DanglingEdgeIndex { edge, name: *name });
+                }
+            }
+        }
+        Ok(inconsistencies)
+    }
+
+    /// Checks that every property belongs to an existing edge and has a
+    /// value-index entry, and that every value-index entry matches a stored
+    /// property.
+    pub fn check_integrity(
+        &self,
+        owner_exists: impl Fn(&Edge) -> indradb::Result<bool>,
+        report: &mut IntegrityReport,
+    ) -> indradb::Result<()> {
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (edge, name) = Self::read_key(k);
+            if !owner_exists(&edge)? {
+                report.record(IntegrityViolation::OrphanedEdgeProperty {
+                    edge: edge.clone(),
+                    name,
+                });
+            }
+            let value = self.codec.decode(&v)?;
+            let value_key = Self::key_value_index(&edge, &value, name);
+            if self.maintain_value_index
+                && !map_err_ctx(
+                    "contains_key",
+                    self.value_index_tree,
+                    timed!(Get, self.value_index_tree.contains_key(value_key)),
+                )?
+            {
+                report.record(IntegrityViolation::MissingEdgePropertyValue { edge, name });
+            }
+        }
+        for item in timed_iter(self.value_index_tree.iter()) {
+            let (k, _) = map_err(item)?;
+            let (name, _, edge) = Self::read_key_value_index(k.clone());
+            let is_consistent = match self.get(&edge, name)? {
+                Some(value) => Self::key_value_index(&edge, &value, name) == *k,
+                None => false,
+            };
+            if !is_consistent {
+                report.record(IntegrityViolation::DanglingEdgePropertyValue { edge, name });
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes properties of edges that don't exist, writes missing
+    /// value-index entries, and then removes value-index entries that don't
+    /// match a stored property. Returns the changes to the property tree and
+    /// the value index, in that order. Each pass is applied as one batch.
+    pub fn repair(
+        &self,
+        owner_exists: impl Fn(&Edge) -> indradb::Result<bool>,
+    ) -> indradb::Result<(RebuildStats, RebuildStats)> {
+        let (mut properties, mut values) = (RebuildStats::default(), RebuildStats::default());
+        let (mut batch, mut value_batch) = (Batch::default(), Batch::default());
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (edge, name) = Self::read_key(k.clone());
+            if !owner_exists(&edge)? {
+                batch.remove(k);
+                properties.removed += 1;
+                continue;
+            }
+            let value = self.codec.decode(&v)?;
+            let value_key = Self::key_value_index(&edge, &value, name);
+            if self.maintain_value_index
+                && !map_err_ctx(
+                    "contains_key",
+                    self.value_index_tree,
+                    timed!(Get, self.value_index_tree.contains_key(&value_key)),
+                )?
+            {
+                value_batch.insert(value_key, v);
+                values.added += 1;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.tree,
+            timed!(ApplyBatch, self.tree.apply_batch(batch)),
+        )?;
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(value_batch)),
+        )?;
+
+        let mut value_batch = Batch::default();
+        for item in timed_iter(self.value_index_tree.iter()) {
+            let (k, _) = map_err(item)?;
+            let (name, _, edge) = Self::read_key_value_index(k.clone());
+            let is_consistent = match self.get(&edge, name)? {
+                Some(value) => Self::key_value_index(&edge, &value, name) == *k,
+                None => false,
+            };
+            if !is_consistent {
+                value_batch.remove(k);
+                values.removed += 1;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(value_batch)),
         )?;
+        Ok((properties, values))
+    }
+
+    /// Queues the removal of every property of an edge, along with their
+    /// value-index entries.
+    pub fn delete_for_owner_batch(
+        &self,
+        edge: &Edge,
+        batch: &mut Batch,
+        value_batch: &mut Batch,
+    ) -> indradb::Result<()> {
+        for item in self.iterate_for_owner(edge)? {
+            let ((edge, name), value) = item?;
+            batch.remove(self.key(&edge, name));
+            if self.maintain_value_index {
+                value_batch.remove(Self::key_value_index(&edge, &value, name));
+            }
+        }
         Ok(())
     }
 
     pub fn delete(&self, edge: &Edge, name: Identifier) -> indradb::Result<()> {
-        let old_value = map_err(self.tree.get(self.key(edge, name)))?;
-        map_err(self.tree.remove(self.key(edge, name)))?;
-        if let Some(old_value) = old_value {
-            let old_value: Json = serde_json::from_slice(&old_value)?;
+        let old_value = map_err_ctx(
+            "remove",
+            self.tree,
+            timed!(Remove, self.tree.remove(self.key(edge, name))),
+        )?;
+        if let Some(old_value) = old_value.filter(|_| self.maintain_value_index) {
+            let old_value = self.codec.decode(&old_value)?;
             let value_key = Self::key_value_index(edge, &old_value, name);
-            map_err(self.value_index_tree.remove(value_key.as_slice()))?;
+            map_err_ctx(
+                "remove",
+                self.value_index_tree,
+                timed!(Remove, self.value_index_tree.remove(value_key.as_slice())),
+            )?;
         }
 
         Ok(())
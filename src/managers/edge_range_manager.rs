@@ -1,11 +1,28 @@
 use std::io::Cursor;
 
-use indradb::{util, Edge};
-use sled::{Batch, Iter as DbIterator, Tree};
+use indradb::{util, Edge, Identifier};
+use sled::{Batch, IVec, Iter as DbIterator, Tree};
 use uuid::Uuid;
 
-use crate::datastore::SledHolder;
-use crate::errors::map_err;
+use crate::datastore::{RebuildStats, SledHolder};
+use crate::errors::{map_err, map_err_ctx};
+use crate::latency::timed_iter;
+use crate::managers::edge_manager::EdgeManager;
+use crate::managers::BACKFILL_CHUNK_SIZE;
+use crate::reverse_edge;
+
+fn read_edge(item: sled::Result<(IVec, IVec)>) -> indradb::Result<Edge> {
+    let (k, _) = map_err(item)?;
+    let mut cursor = Cursor::new(k);
+    let outbound_id = util::read_uuid(&mut cursor);
+    let t = util::read_identifier(&mut cursor);
+    let inbound_id = util::read_uuid(&mut cursor);
+    Ok(Edge {
+        outbound_id,
+        t,
+        inbound_id,
+    })
+}
 
 pub struct EdgeRangeManager<'tree> {
     pub tree: &'tree Tree,
@@ -22,7 +39,7 @@ impl<'tree> EdgeRangeManager<'tree> {
         }
     }
 
-    fn key(&self, edge: &Edge) -> Vec<u8> {
+    pub(crate) fn key(&self, edge: &Edge) -> Vec<u8> {
         util::build(&[
             util::Component::Uuid(edge.outbound_id),
             util::Component::Identifier(edge.t),
@@ -32,22 +49,11 @@ impl<'tree> EdgeRangeManager<'tree> {
 
     pub(crate) fn contains(&self, edge: &Edge) -> indradb::Result<bool> {
         let key = self.key(edge);
-        map_err(self.tree.contains_key(key))
+        map_err_ctx("contains_key", self.tree, timed!(Get, self.tree.contains_key(key)))
     }
 
     fn sled_to_edge(iter: DbIterator) -> impl Iterator<Item = indradb::Result<Edge>> {
-        iter.map(move |item| {
-            let (k, _) = map_err(item)?;
-            let mut cursor = Cursor::new(k);
-            let outbound_id = util::read_uuid(&mut cursor);
-            let t = util::read_identifier(&mut cursor);
-            let inbound_id = util::read_uuid(&mut cursor);
-            Ok(Edge {
-                outbound_id,
-                t,
-                inbound_id,
-            })
-        })
+        timed_iter(iter).map(read_edge)
     }
 
     pub fn iterate_for_range<'iter, 'trans: 'iter>(
@@ -59,6 +65,14 @@ impl<'tree> EdgeRangeManager<'tree> {
         Self::sled_to_edge(iterator)
     }
 
+    /// Iterates over the edges that sort at or before `edge`, in descending
+    /// order.
+    pub fn iterate_for_range_rev(&self, edge: &Edge) -> impl Iterator<Item = indradb::Result<Edge>> {
+        let offset = self.key(edge);
+        let iterator = self.tree.range(..=offset);
+        timed_iter(iterator).rev().map(read_edge)
+    }
+
     pub fn iterate_for_all(&self) -> impl Iterator<Item = indradb::Result<Edge>> {
         let iterator = self.tree.iter();
         Self::sled_to_edge(iterator)
@@ -73,9 +87,43 @@ impl<'tree> EdgeRangeManager<'tree> {
         Self::sled_to_edge(iterator)
     }
 
+    /// Iterates over the edges of `id` with type `t`, using a prefix scan
+    /// over the `id | t` part of the key.
+    pub fn iterate_for_type<'iter, 'trans: 'iter>(
+        &'trans self,
+        id: Uuid,
+        t: Identifier,
+    ) -> impl Iterator<Item = indradb::Result<Edge>> + 'iter {
+        let prefix: Vec<u8> = util::build(&[util::Component::Uuid(id), util::Component::Identifier(t)]);
+        let iterator = self.tree.scan_prefix(prefix);
+        Self::sled_to_edge(iterator)
+    }
+
+    fn count_prefix(&self, prefix: Vec<u8>) -> indradb::Result<u64> {
+        let mut count = 0;
+        for item in timed_iter(self.tree.scan_prefix(prefix)) {
+            map_err(item)?;
+            count += 1;
+        }
+        Ok(count)
+    }
+
+    /// Counts the edges of `id` without decoding them.
+    pub fn count_for_owner(&self, id: Uuid) -> indradb::Result<u64> {
+        self.count_prefix(util::build(&[util::Component::Uuid(id)]))
+    }
+
+    /// Counts the edges of `id` with type `t` without decoding them.
+    pub fn count_for_type(&self, id: Uuid, t: Identifier) -> indradb::Result<u64> {
+        self.count_prefix(util::build(&[
+            util::Component::Uuid(id),
+            util::Component::Identifier(t),
+        ]))
+    }
+
     pub fn set(&self, edge: &Edge) -> indradb::Result<()> {
         let key = self.key(edge);
-        map_err(self.tree.insert(key, &[]))?;
+        map_err_ctx("insert", self.tree, timed!(Insert, self.tree.insert(key, &[])))?;
         Ok(())
     }
 
@@ -85,8 +133,66 @@ impl<'tree> EdgeRangeManager<'tree> {
         Ok(())
     }
 
+    pub fn delete_batch(&self, edge: &Edge, batch: &mut Batch) -> indradb::Result<()> {
+        batch.remove(self.key(edge));
+        Ok(())
+    }
+
+    /// Brings the range tree in line with the edges tree: adds the entries of
+    /// edges that are missing one and removes entries without an edge.
+    /// `reversed` says whether this manages the reversed ranges. Both trees
+    /// are streamed and writes are applied in chunks, so memory use doesn't
+    /// depend on the number of edges.
+    pub fn rebuild(&self, edge_manager: &EdgeManager, reversed: bool) -> indradb::Result<RebuildStats> {
+        let orient = |edge: Edge| if reversed { reverse_edge(&edge) } else { edge };
+        let mut stats = RebuildStats::default();
+        let mut batch = Batch::default();
+        let mut pending = 0;
+        for item in edge_manager.iterate_for_all() {
+            let key = self.key(&orient(item?));
+            if !map_err_ctx("contains_key", self.tree, timed!(Get, self.tree.contains_key(&key)))? {
+                batch.insert(key, &[]);
+                stats.added += 1;
+                self.apply_chunk(&mut batch, &mut pending)?;
+            }
+        }
+        for item in Self::sled_to_edge(self.tree.iter()) {
+            let edge = orient(item?);
+            let edge_key = edge_manager.key(edge.clone());
+            if !map_err_ctx(
+                "contains_key",
+                edge_manager.tree,
+                timed!(Get, edge_manager.tree.contains_key(edge_key)),
+            )? {
+                batch.remove(self.key(&orient(edge)));
+                stats.removed += 1;
+                self.apply_chunk(&mut batch, &mut pending)?;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.tree,
+            timed!(ApplyBatch, self.tree.apply_batch(batch)),
+        )?;
+        Ok(stats)
+    }
+
+    /// Counts a queued write, applying the batch once it holds a chunk.
+    fn apply_chunk(&self, batch: &mut Batch, pending: &mut usize) -> indradb::Result<()> {
+        *pending += 1;
+        if *pending == BACKFILL_CHUNK_SIZE {
+            map_err_ctx(
+                "apply_batch",
+                self.tree,
+                timed!(ApplyBatch, self.tree.apply_batch(std::mem::take(batch))),
+            )?;
+            *pending = 0;
+        }
+        Ok(())
+    }
+
     pub fn delete(&self, edge: &Edge) -> indradb::Result<()> {
-        map_err(self.tree.remove(self.key(edge)))?;
+        map_err_ctx("remove", self.tree, timed!(Remove, self.tree.remove(self.key(edge))))?;
         Ok(())
     }
 }
@@ -0,0 +1,68 @@
+use std::io::Cursor;
+
+use indradb::{util, Edge, Identifier};
+use sled::{Batch, Tree};
+
+use crate::datastore::SledHolder;
+use crate::errors::{map_err, map_err_ctx};
+use crate::latency::timed_iter;
+
+/// Maintains the `t | outbound_id | inbound_id` index of edges, so that the
+/// edges of a type can be found with a prefix scan.
+pub struct EdgeTypeManager<'tree> {
+    pub tree: &'tree Tree,
+}
+
+impl<'tree> EdgeTypeManager<'tree> {
+    pub fn new<'db: 'tree>(ds: &'db SledHolder) -> Self {
+        EdgeTypeManager { tree: &ds.edge_types }
+    }
+
+    pub(crate) fn key(&self, edge: &Edge) -> Vec<u8> {
+        util::build(&[
+            util::Component::Identifier(edge.t),
+            util::Component::Uuid(edge.outbound_id),
+            util::Component::Uuid(edge.inbound_id),
+        ])
+    }
+
+    pub fn iterate_for_type(&self, t: Identifier) -> impl Iterator<Item = indradb::Result<Edge>> + 'tree {
+        let prefix = util::build(&[util::Component::Identifier(t)]);
+        timed_iter(self.tree.scan_prefix(prefix)).map(move |item| {
+            let (k, _) = map_err(item)?;
+            let mut cursor = Cursor::new(k);
+            let t = util::read_identifier(&mut cursor);
+            let outbound_id = util::read_uuid(&mut cursor);
+            let inbound_id = util::read_uuid(&mut cursor);
+            Ok(Edge {
+                outbound_id,
+                t,
+                inbound_id,
+            })
+        })
+    }
+
+    pub fn set(&self, edge: &Edge) -> indradb::Result<()> {
+        map_err_ctx(
+            "insert",
+            self.tree,
+            timed!(Insert, self.tree.insert(self.key(edge), &[])),
+        )?;
+        Ok(())
+    }
+
+    pub fn set_batch(&self, edge: &Edge, batch: &mut Batch) -> indradb::Result<()> {
+        batch.insert(self.key(edge), &[]);
+        Ok(())
+    }
+
+    pub fn delete_batch(&self, edge: &Edge, batch: &mut Batch) -> indradb::Result<()> {
+        batch.remove(self.key(edge));
+        Ok(())
+    }
+
+    pub fn delete(&self, edge: &Edge) -> indradb::Result<()> {
+        map_err_ctx("remove", self.tree, timed!(Remove, self.tree.remove(self.key(edge))))?;
+        Ok(())
+    }
+}
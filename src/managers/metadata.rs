@@ -1,13 +1,34 @@
 use std::collections::HashSet;
 use std::io::Cursor;
-use std::sync::{Arc, RwLock};
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard, RwLockWriteGuard};
 
 use indradb::{util, Identifier};
 use sled::Tree;
 
-use crate::errors::{map_err, DSError};
+use crate::errors::{map_err, map_err_ctx};
+use crate::latency::timed_iter;
 
 const INDEXED_PROPERTIES: &str = "IndexedProperties";
+// Prefix of the per-type edge counters, keyed by `EDGE_TYPE_COUNTS | t`
+pub(crate) const EDGE_TYPE_COUNTS: &str = "EdgeTypeCounts";
+// Marks that the per-type edge counters have been built for this store
+pub(crate) const EDGE_TYPE_COUNTS_READY: &str = "EdgeTypeCountsReady";
+// Prefix of the per-type vertex counters, keyed by `VERTEX_TYPE_COUNTS | t`
+pub(crate) const VERTEX_TYPE_COUNTS: &str = "VertexTypeCounts";
+// Marks that the per-type vertex counters have been built for this store
+pub(crate) const VERTEX_TYPE_COUNTS_READY: &str = "VertexTypeCountsReady";
+// The on-disk format version of this store, see `crate::migrations`
+pub(crate) const FORMAT_VERSION_KEY: &str = "FormatVersion";
+// Whether the reversed edge ranges are maintained, absent for stores that
+// predate `SledConfig::maintain_reversed_edges`
+pub(crate) const REVERSED_EDGES_KEY: &str = "ReversedEdges";
+// Whether the property value indexes are maintained, absent for stores that
+// predate `SledConfig::maintain_property_value_index`
+pub(crate) const PROPERTY_VALUE_INDEX_KEY: &str = "PropertyValueIndex";
+// How property values are serialized, absent for JSON, see `crate::encoding`
+pub(crate) const PROPERTY_ENCODING_KEY: &str = "PropertyEncoding";
+// The number of other entries in a backup, written once the backup is complete
+pub(crate) const BACKUP_ENTRIES_KEY: &str = "BackupEntries";
 
 pub struct MetaDataManager<'tree> {
     pub tree: &'tree Tree,
@@ -26,16 +47,35 @@ impl<'tree> MetaDataManager<'tree> {
         Ok(manager)
     }
 
+    // A panic while the lock is held poisons it, but every change to the set
+    // is a single insert or remove, so the set is still whole and the guard
+    // is recovered rather than failing every later index lookup
+    fn read(&self) -> RwLockReadGuard<'_, HashSet<String>> {
+        self.indexed_properties.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    fn write(&self) -> RwLockWriteGuard<'_, HashSet<String>> {
+        self.indexed_properties.write().unwrap_or_else(PoisonError::into_inner)
+    }
+
     pub fn is_indexed(&self, prop: &Identifier) -> indradb::Result<bool> {
-        let indexed_properties = self.indexed_properties.read().map_err(DSError::from)?;
+        let indexed_properties = self.read();
 
         let is_indexed = indexed_properties.contains(prop.as_str());
         Ok(is_indexed)
     }
 
+    pub fn indexed_properties(&self) -> indradb::Result<HashSet<Identifier>> {
+        let indexed_properties = self.read();
+        indexed_properties
+            .iter()
+            .map(|prop| Ok(Identifier::new(prop)?))
+            .collect()
+    }
+
     pub fn add_index(&self, prop: &Identifier) -> indradb::Result<()> {
         {
-            let mut indexed_properties = self.indexed_properties.write().map_err(DSError::from)?;
+            let mut indexed_properties = self.write();
             if indexed_properties.contains(prop.as_str()) {
                 return Ok(());
             }
@@ -48,7 +88,7 @@ impl<'tree> MetaDataManager<'tree> {
     #[allow(dead_code)]
     pub fn remove_index(&self, prop: &Identifier) -> indradb::Result<()> {
         {
-            let mut indexed_properties = self.indexed_properties.write().map_err(DSError::from)?;
+            let mut indexed_properties = self.write();
             if !indexed_properties.contains(prop.as_str()) {
                 return Ok(());
             }
@@ -59,10 +99,16 @@ impl<'tree> MetaDataManager<'tree> {
         Ok(())
     }
 
+    /// Forgets the indexed properties, for after the metadata tree has been
+    /// cleared, so that a later `sync` doesn't write them back.
+    pub(crate) fn forget(&self) {
+        self.write().clear();
+    }
+
     fn load(&self) -> indradb::Result<()> {
-        let mut indexed_properties = self.indexed_properties.write().map_err(DSError::from)?;
+        let mut indexed_properties = self.write();
         let all_indexed_prefix = util::build(&[util::Component::Identifier(self.index_key)]);
-        for index in self.tree.scan_prefix(all_indexed_prefix) {
+        for index in timed_iter(self.tree.scan_prefix(all_indexed_prefix)) {
             let (k, _) = map_err(index)?;
             let mut cursor = Cursor::new(k);
             let _ = util::read_identifier(&mut cursor);
@@ -73,19 +119,95 @@ impl<'tree> MetaDataManager<'tree> {
         Ok(())
     }
 
+    /// Brings the indexed-property keys on disk in line with the in-memory
+    /// set, writing only the keys that differ.
     pub(crate) fn sync(&self) -> indradb::Result<()> {
         let all_indexed_prefix = util::build(&[util::Component::Identifier(self.index_key)]);
-        for index in self.tree.scan_prefix(all_indexed_prefix) {
+        let mut stored = HashSet::new();
+        for index in timed_iter(self.tree.scan_prefix(all_indexed_prefix)) {
             let (key, _) = map_err(index)?;
-            map_err(self.tree.remove(key))?;
+            stored.insert(key.to_vec());
         }
-        for index in self.indexed_properties.read().map_err(DSError::from)?.iter() {
-            let key = util::build(&[
+        let mut wanted = HashSet::new();
+        for index in self.read().iter() {
+            wanted.insert(util::build(&[
                 util::Component::Identifier(self.index_key),
                 util::Component::Identifier(Identifier::new(index)?),
-            ]);
-            map_err(self.tree.insert(key, &[]))?;
+            ]));
+        }
+        for key in stored.difference(&wanted) {
+            map_err_ctx("remove", self.tree, timed!(Remove, self.tree.remove(key)))?;
+        }
+        for key in wanted.difference(&stored) {
+            map_err_ctx("insert", self.tree, timed!(Insert, self.tree.insert(key, &[])))?;
         }
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::thread;
+    use std::time::Duration;
+
+    use indradb::{util, Identifier};
+    use sled::{Config, Subscriber};
+
+    use super::{MetaDataManager, INDEXED_PROPERTIES};
+
+    /// Counts the events the subscriber has seen since the last call.
+    fn writes(subscriber: &mut Subscriber) -> usize {
+        let mut count = 0;
+        while subscriber.next_timeout(Duration::from_millis(50)).is_ok() {
+            count += 1;
+        }
+        count
+    }
+
+    #[test]
+    fn test_sync_only_writes_changes() {
+        let db = Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("metadata").unwrap();
+        let manager = MetaDataManager::new(&tree).unwrap();
+        let prefix = util::build(&[util::Component::Identifier(
+            Identifier::new(INDEXED_PROPERTIES).unwrap(),
+        )]);
+        let mut subscriber = tree.watch_prefix(prefix);
+
+        let names = ["a", "b", "c"].map(|name| Identifier::new(name).unwrap());
+        for name in &names {
+            manager.add_index(name).unwrap();
+            assert_eq!(writes(&mut subscriber), 1);
+        }
+        manager.add_index(&names[0]).unwrap();
+        manager.sync().unwrap();
+        assert_eq!(writes(&mut subscriber), 0);
+        manager.remove_index(&names[1]).unwrap();
+        assert_eq!(writes(&mut subscriber), 1);
+
+        let reloaded = MetaDataManager::new(&tree).unwrap();
+        assert_eq!(reloaded.indexed_properties().unwrap(), [names[0], names[2]].into());
+    }
+
+    #[test]
+    fn test_poisoned_lock_is_recovered() {
+        let db = Config::new().temporary(true).open().unwrap();
+        let tree = db.open_tree("metadata").unwrap();
+        let manager = MetaDataManager::new(&tree).unwrap();
+        let name = Identifier::new("name").unwrap();
+
+        let lock = manager.indexed_properties.clone();
+        let result = thread::spawn(move || {
+            let _guard = lock.write().unwrap();
+            panic!("poisoning the indexed properties");
+        })
+        .join();
+        assert!(result.is_err());
+        assert!(manager.indexed_properties.is_poisoned());
+
+        assert!(!manager.is_indexed(&name).unwrap());
+        manager.add_index(&name).unwrap();
+        assert!(manager.is_indexed(&name).unwrap());
+        assert_eq!(manager.indexed_properties().unwrap(), [name].into());
+    }
+}
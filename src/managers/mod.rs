@@ -1,6 +1,73 @@
+use sled::Tree;
+
+use crate::datastore::TreeSize;
+use crate::errors::map_err;
+use crate::latency::timed_iter;
+
 pub(crate) mod edge_manager;
 pub(crate) mod edge_property_manager;
 pub(crate) mod edge_range_manager;
+pub(crate) mod edge_type_manager;
 pub(crate) mod metadata;
 pub(crate) mod vertex_manager;
 pub(crate) mod vertex_property_manager;
+
+/// The number of value-index entries written per batch when backfilling an
+/// index over existing properties.
+pub(crate) const BACKFILL_CHUNK_SIZE: usize = 10_000;
+
+/// Counts the keys of a tree. Errors while scanning are counted as keys.
+pub(crate) fn count_keys(tree: &Tree) -> u64 {
+    timed_iter(tree.iter()).count() as u64
+}
+
+/// A 64-bit FNV-1a hash, used for checksums that have to be stable across
+/// processes and Rust versions.
+pub(crate) struct Fnv64(u64);
+
+impl Fnv64 {
+    pub(crate) fn new() -> Self {
+        Fnv64(0xcbf2_9ce4_8422_2325)
+    }
+
+    pub(crate) fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 ^= u64::from(*byte);
+            self.0 = self.0.wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    /// Writes `bytes` prefixed with their length, so that adjacent chunks
+    /// can't run into each other.
+    pub(crate) fn write_chunk(&mut self, bytes: &[u8]) {
+        self.write(&(bytes.len() as u64).to_be_bytes());
+        self.write(bytes);
+    }
+
+    pub(crate) fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Computes a checksum over the keys and values of a tree, in key order.
+pub(crate) fn tree_checksum(tree: &Tree) -> indradb::Result<u64> {
+    let mut hash = Fnv64::new();
+    for item in timed_iter(tree.iter()) {
+        let (key, value) = map_err(item)?;
+        hash.write_chunk(&key);
+        hash.write_chunk(&value);
+    }
+    Ok(hash.finish())
+}
+
+/// Computes the key count and approximate byte sizes of a tree.
+pub(crate) fn tree_size(tree: &Tree) -> indradb::Result<TreeSize> {
+    let mut size = TreeSize::default();
+    for item in timed_iter(tree.iter()) {
+        let (key, value) = map_err(item)?;
+        size.keys += 1;
+        size.bytes += (key.len() + value.len()) as u64;
+        size.value_bytes += value.len() as u64;
+    }
+    Ok(size)
+}
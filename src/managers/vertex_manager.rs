@@ -1,45 +1,142 @@
+use std::collections::HashMap;
 use std::io::Cursor;
 use std::ops::Deref;
 
 use indradb::{util, Identifier, Vertex};
-use sled::{Batch, Iter as DbIterator, Tree};
+use sled::{Batch, IVec, Tree};
 use uuid::Uuid;
 
+use crate::counters::{decode_count, encode_count, CounterBehavior, PersistentCounter};
 use crate::datastore::SledHolder;
-use crate::errors::map_err;
-use crate::managers::edge_manager::EdgeManager;
-use crate::managers::edge_range_manager::EdgeRangeManager;
+use crate::errors::{map_err, map_err_ctx};
+use crate::latency::timed_iter;
+use crate::managers::count_keys;
+use crate::managers::metadata::{VERTEX_TYPE_COUNTS, VERTEX_TYPE_COUNTS_READY};
 use crate::managers::vertex_property_manager::VertexPropertyManager;
 
 pub type VertexItem = (Uuid, Identifier);
 
-pub struct VertexManager<'db: 'tree, 'tree> {
-    pub holder: &'db SledHolder,
+pub struct VertexManager<'tree> {
     pub tree: &'tree Tree,
+    pub metadata: &'tree Tree,
 }
 
-impl<'db: 'tree, 'tree> VertexManager<'db, 'tree> {
-    pub fn new(ds: &'db SledHolder) -> Self {
+impl<'tree> VertexManager<'tree> {
+    pub fn new<'db: 'tree>(ds: &'db SledHolder) -> Self {
         VertexManager {
-            holder: ds,
-            tree: ds.db.deref(),
+            tree: &ds.vertices,
+            metadata: &ds.metadata,
         }
     }
 
     pub fn count(&self) -> u64 {
-        self.tree.iter().count() as u64
+        count_keys(self.tree)
     }
 
-    fn key(&self, id: Uuid) -> Vec<u8> {
+    pub(crate) fn type_count_key(t: Identifier) -> indradb::Result<Vec<u8>> {
+        Ok(util::build(&[
+            util::Component::Identifier(Identifier::new(VERTEX_TYPE_COUNTS)?),
+            util::Component::Identifier(t),
+        ]))
+    }
+
+    fn type_counter(&self, t: Identifier) -> indradb::Result<PersistentCounter<'tree>> {
+        Ok(PersistentCounter::new(
+            self.metadata,
+            Self::type_count_key(t)?,
+            CounterBehavior::Saturate,
+        ))
+    }
+
+    /// Reads every per-type vertex counter. Types whose vertices were all
+    /// deleted keep a counter of zero.
+    pub fn type_counts(&self) -> indradb::Result<Vec<(Identifier, u64)>> {
+        self.ensure_type_counts()?;
+        let prefix = util::build(&[util::Component::Identifier(Identifier::new(VERTEX_TYPE_COUNTS)?)]);
+        let mut counts = Vec::new();
+        for item in timed_iter(self.metadata.scan_prefix(prefix)) {
+            let (key, value) = map_err(item)?;
+            let mut cursor = Cursor::new(key);
+            let _ = util::read_identifier(&mut cursor);
+            counts.push((util::read_identifier(&mut cursor), decode_count(&value)));
+        }
+        Ok(counts)
+    }
+
+    pub fn update_count_for_type(&self, t: Identifier, delta: i64) -> indradb::Result<()> {
+        self.type_counter(t)?.add(delta)?;
+        Ok(())
+    }
+
+    /// Applies the type changes of vertices written in a batch, given as the
+    /// type each vertex had before the batch and the type it has after it.
+    pub(crate) fn update_counts_for_changes<I>(&self, changes: I) -> indradb::Result<()>
+    where
+        I: IntoIterator<Item = (Option<Identifier>, Option<Identifier>)>,
+    {
+        let mut type_counts: HashMap<Identifier, i64> = HashMap::new();
+        for (before, after) in changes {
+            if before == after {
+                continue;
+            }
+            if let Some(t) = before {
+                *type_counts.entry(t).or_insert(0) -= 1;
+            }
+            if let Some(t) = after {
+                *type_counts.entry(t).or_insert(0) += 1;
+            }
+        }
+        for (t, count) in type_counts {
+            self.update_count_for_type(t, count)?;
+        }
+        Ok(())
+    }
+
+    /// Builds the per-type vertex counters from the vertices, for stores
+    /// that were written before the counters were maintained.
+    pub(crate) fn ensure_type_counts(&self) -> indradb::Result<()> {
+        let ready_key = util::build(&[util::Component::Identifier(Identifier::new(VERTEX_TYPE_COUNTS_READY)?)]);
+        if map_err_ctx(
+            "contains_key",
+            self.metadata,
+            timed!(Get, self.metadata.contains_key(&ready_key)),
+        )? {
+            return Ok(());
+        }
+
+        let mut counts: HashMap<Identifier, u64> = HashMap::new();
+        for item in self.iterate_for_range(Uuid::default()) {
+            let (_, t) = item?;
+            *counts.entry(t).or_insert(0) += 1;
+        }
+
+        let mut batch = Batch::default();
+        let prefix = util::build(&[util::Component::Identifier(Identifier::new(VERTEX_TYPE_COUNTS)?)]);
+        for item in timed_iter(self.metadata.scan_prefix(prefix)) {
+            let (key, _) = map_err(item)?;
+            batch.remove(key);
+        }
+        for (t, count) in counts {
+            batch.insert(Self::type_count_key(t)?, &encode_count(count));
+        }
+        batch.insert(ready_key, IVec::default());
+        map_err_ctx(
+            "apply_batch",
+            self.metadata,
+            timed!(ApplyBatch, self.metadata.apply_batch(batch)),
+        )
+    }
+
+    pub(crate) fn key(&self, id: Uuid) -> Vec<u8> {
         util::build(&[util::Component::Uuid(id)])
     }
 
     pub fn exists(&self, id: Uuid) -> indradb::Result<bool> {
-        Ok(map_err(self.tree.get(self.key(id)))?.is_some())
+        Ok(map_err_ctx("get", self.tree, timed!(Get, self.tree.get(self.key(id))))?.is_some())
     }
 
     pub fn get(&self, id: Uuid) -> indradb::Result<Option<Identifier>> {
-        match map_err(self.tree.get(self.key(id)))? {
+        match map_err_ctx("get", self.tree, timed!(Get, self.tree.get(self.key(id))))? {
             Some(value_bytes) => {
                 let mut cursor = Cursor::new(value_bytes.deref());
                 Ok(Some(util::read_identifier(&mut cursor)))
@@ -48,7 +145,10 @@ impl<'db: 'tree, 'tree> VertexManager<'db, 'tree> {
         }
     }
 
-    fn iterate(&self, iterator: DbIterator) -> impl Iterator<Item = indradb::Result<VertexItem>> + '_ {
+    fn iterate<'a, I>(&'a self, iterator: I) -> impl Iterator<Item = indradb::Result<VertexItem>> + 'a
+    where
+        I: Iterator<Item = sled::Result<(IVec, IVec)>> + 'a,
+    {
         iterator.map(move |item| -> indradb::Result<VertexItem> {
             let (k, v) = map_err(item)?;
 
@@ -68,48 +168,125 @@ impl<'db: 'tree, 'tree> VertexManager<'db, 'tree> {
         let low_key = util::build(&[util::Component::Uuid(id)]);
         let low_key_bytes: &[u8] = low_key.as_ref();
         let iter = self.tree.range(low_key_bytes..);
-        self.iterate(iter)
+        self.iterate(timed_iter(iter))
+    }
+
+    /// Iterates over the vertices with an id of at most `id`, in descending
+    /// id order.
+    pub fn iterate_for_range_rev(&self, id: Uuid) -> impl Iterator<Item = indradb::Result<VertexItem>> + '_ {
+        let high_key = util::build(&[util::Component::Uuid(id)]);
+        let high_key_bytes: &[u8] = high_key.as_ref();
+        let iter = self.tree.range(..=high_key_bytes);
+        self.iterate(timed_iter(iter).rev())
     }
 
     pub fn create(&self, vertex: &Vertex) -> indradb::Result<bool> {
         let key = self.key(vertex.id);
-        if map_err(self.tree.contains_key(&key))? {
+        if map_err_ctx("contains_key", self.tree, timed!(Get, self.tree.contains_key(&key)))? {
             return Ok(false);
         }
-        map_err(
-            self.tree
-                .insert(&key, util::build(&[util::Component::Identifier(vertex.t)])),
+        map_err_ctx(
+            "insert",
+            self.tree,
+            timed!(
+                Insert,
+                self.tree
+                    .insert(&key, util::build(&[util::Component::Identifier(vertex.t)]))
+            ),
         )?;
+        self.update_count_for_type(vertex.t, 1)?;
         Ok(true)
     }
 
-    pub fn create_batch(&self, vertex: &Vertex, batch: &mut Batch) -> indradb::Result<()> {
-        let key = self.key(vertex.id);
-        batch.insert(key.clone(), util::build(&[util::Component::Identifier(vertex.t)]));
-        Ok(())
-    }
-
-    pub fn delete(&self, id: Uuid) -> indradb::Result<()> {
-        map_err(self.tree.remove(self.key(id)))?;
-
-        let vertex_property_manager =
-            VertexPropertyManager::new(&self.holder.vertex_properties, &self.holder.vertex_property_values);
-        for item in vertex_property_manager.iterate_for_owner(id)? {
-            let ((vertex_property_owner_id, vertex_property_name), _) = item?;
-            vertex_property_manager.delete(vertex_property_owner_id, vertex_property_name)?;
+    /// Overwrites the type of an existing vertex, leaving its properties and
+    /// edges alone. Returns whether the vertex exists.
+    pub fn set_type(&self, id: Uuid, t: Identifier) -> indradb::Result<bool> {
+        let Some(current) = self.get(id)? else {
+            return Ok(false);
+        };
+        map_err_ctx(
+            "insert",
+            self.tree,
+            timed!(
+                Insert,
+                self.tree
+                    .insert(self.key(id), util::build(&[util::Component::Identifier(t)]))
+            ),
+        )?;
+        if current != t {
+            self.update_count_for_type(current, -1)?;
+            self.update_count_for_type(t, 1)?;
         }
+        Ok(true)
+    }
 
-        let edge_manager = EdgeManager::new(self.holder);
-
-        {
-            let edge_range_manager = EdgeRangeManager::new(self.holder);
-            for item in edge_range_manager.iterate_for_owner(id) {
-                let edge = item?;
-                debug_assert_eq!(edge.outbound_id, id);
-                edge_manager.delete(&edge)?;
+    /// Inserts the vertex unless its id is taken, in a single
+    /// compare-and-swap. Returns whether it was inserted, along with the type
+    /// stored under the id.
+    pub fn get_or_create(&self, vertex: &Vertex) -> indradb::Result<(bool, Identifier)> {
+        let value = util::build(&[util::Component::Identifier(vertex.t)]);
+        loop {
+            let swapped = map_err_ctx(
+                "compare_and_swap",
+                self.tree,
+                timed!(
+                    Insert,
+                    self.tree
+                        .compare_and_swap(self.key(vertex.id), None as Option<&[u8]>, Some(value.as_slice()))
+                ),
+            )?;
+            match swapped {
+                Ok(()) => {
+                    self.update_count_for_type(vertex.t, 1)?;
+                    return Ok((true, vertex.t));
+                }
+                Err(err) => {
+                    if let Some(current) = err.current {
+                        let mut cursor = Cursor::new(current.deref());
+                        return Ok((false, util::read_identifier(&mut cursor)));
+                    }
+                    // A swap against an absent value only fails if one is present, so
+                    // this is unreachable, but retrying is safer than panicking
+                }
             }
         }
+    }
 
+    /// Queues the write of a vertex, overwriting any vertex with the same id.
+    /// The type stored before the batch and the one written are recorded in
+    /// `creation_set`, so that the type counters can be updated once the
+    /// batch is applied.
+    pub fn create_batch(
+        &self,
+        vertex: &Vertex,
+        batch: &mut Batch,
+        creation_set: &mut HashMap<Uuid, (Option<Identifier>, Identifier)>,
+    ) -> indradb::Result<()> {
+        let key = self.key(vertex.id);
+        let before = self.get(vertex.id)?;
+        creation_set.insert(vertex.id, (before, vertex.t));
+        batch.insert(key, util::build(&[util::Component::Identifier(vertex.t)]));
         Ok(())
     }
+
+    /// Appends the removal of a vertex, its properties and their value-index
+    /// entries to caller-owned batches. Removing the vertex's edges is left to
+    /// the caller. A vertex that exists is added to `deletion_set` with its
+    /// type, so that its type counter can be decremented once the batches
+    /// are applied.
+    pub fn collect_delete_keys(
+        &self,
+        id: Uuid,
+        property_manager: &VertexPropertyManager,
+        batch: &mut Batch,
+        deletion_set: &mut HashMap<Uuid, Identifier>,
+        property_batch: &mut Batch,
+        property_value_batch: &mut Batch,
+    ) -> indradb::Result<()> {
+        if let Some(t) = self.get(id)? {
+            deletion_set.insert(id, t);
+        }
+        batch.remove(self.key(id));
+        property_manager.delete_for_owner_batch(id, property_batch, property_value_batch)
+    }
 }
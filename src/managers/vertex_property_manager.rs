@@ -1,30 +1,69 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::io::Cursor;
 
 use indradb::{util, Identifier, Json};
 use serde_json::Value as JsonValue;
-use sled::{IVec, Tree};
+use sled::{Batch, IVec, Tree};
 use uuid::Uuid;
 
-use crate::errors::map_err;
+use crate::datastore::RebuildStats;
+use crate::encoding::PropertyCodec;
+use crate::errors::{map_err, map_err_ctx};
+use crate::integrity::{IndexInconsistency, IntegrityReport, IntegrityViolation};
+use crate::latency::timed_iter;
+use crate::managers::BACKFILL_CHUNK_SIZE;
 
 pub type OwnedPropertyItem = ((Uuid, Identifier), JsonValue);
 
 pub struct VertexPropertyManager<'tree> {
     pub tree: &'tree Tree,
     pub value_index_tree: &'tree Tree,
+    // Whether writes keep `value_index_tree` up to date, see
+    // `SledConfig::maintain_property_value_index`
+    pub maintain_value_index: bool,
+    pub(crate) codec: PropertyCodec,
 }
 
 impl<'tree> VertexPropertyManager<'tree> {
     pub fn new(tree: &'tree Tree, value_index_tree: &'tree Tree) -> Self {
-        VertexPropertyManager { tree, value_index_tree }
+        VertexPropertyManager {
+            tree,
+            value_index_tree,
+            maintain_value_index: true,
+            codec: PropertyCodec::default(),
+        }
+    }
+
+    /// Sets whether writes keep the value index up to date.
+    ///
+    /// # Arguments
+    /// * `maintain`: Whether to maintain the value index.
+    pub fn with_value_index(mut self, maintain: bool) -> Self {
+        self.maintain_value_index = maintain;
+        self
+    }
+
+    /// Sets how property values are serialized and transformed.
+    ///
+    /// # Arguments
+    /// * `codec`: The codec of the property tree.
+    pub(crate) fn with_codec(mut self, codec: PropertyCodec) -> Self {
+        self.codec = codec;
+        self
     }
 
-    fn key(&self, vertex_id: Uuid, name: Identifier) -> Vec<u8> {
+    pub(crate) fn key(&self, vertex_id: Uuid, name: Identifier) -> Vec<u8> {
         util::build(&[util::Component::Uuid(vertex_id), util::Component::Identifier(name)])
     }
 
-    fn key_value_index(vertex_id: &Uuid, value: &JsonValue, property_name: Identifier) -> Vec<u8> {
+    pub(crate) fn read_key(buf: IVec) -> (Uuid, Identifier) {
+        let mut cursor = Cursor::new(buf.as_ref());
+        let owner_id = util::read_uuid(&mut cursor);
+        let name = util::read_identifier(&mut cursor);
+        (owner_id, name)
+    }
+
+    pub(crate) fn key_value_index(vertex_id: &Uuid, value: &JsonValue, property_name: Identifier) -> Vec<u8> {
         util::build(&[
             util::Component::Identifier(property_name),
             util::Component::Json(&Json::new(value.clone())),
@@ -41,7 +80,7 @@ impl<'tree> VertexPropertyManager<'tree> {
     }
 
     fn value_iterate_uuids(&self, iterator: sled::Iter) -> impl Iterator<Item = indradb::Result<Uuid>> + '_ {
-        iterator.map(move |item| -> indradb::Result<Uuid> {
+        timed_iter(iterator).map(move |item| -> indradb::Result<Uuid> {
             let (k, _) = map_err(item)?;
             let (_, _, vid) = Self::read_key_value_index(k);
             Ok(vid)
@@ -71,12 +110,62 @@ impl<'tree> VertexPropertyManager<'tree> {
         Ok(self.value_iterate_uuids(iterator))
     }
 
+    /// Iterates over the vertices whose `name` property is a number between
+    /// `min` and `max`, inclusive. This is a scan within the property's
+    /// namespace of the value index, not an ordered index lookup: the keys
+    /// hold a hash of the value rather than the value itself, so every
+    /// indexed value of the property is read back and compared as an `f64`.
+    pub fn vertex_ids_with_property_value_range(
+        &self,
+        name: Identifier,
+        min: f64,
+        max: f64,
+    ) -> indradb::Result<impl Iterator<Item = indradb::Result<Uuid>> + '_> {
+        let iter = self.iterate_for_property_name(name)?.filter_map(move |item| {
+            let in_range = |id: Uuid| -> indradb::Result<bool> {
+                let value = self.get(id, name)?.as_ref().and_then(JsonValue::as_f64);
+                Ok(value.is_some_and(|value| min <= value && value <= max))
+            };
+            match item.and_then(|id| Ok((id, in_range(id)?))) {
+                Ok((id, true)) => Some(Ok(id)),
+                Ok((_, false)) => None,
+                Err(err) => Some(Err(err)),
+            }
+        });
+        Ok(iter)
+    }
+
+    /// Iterates over the vertices whose `name` property is a string starting
+    /// with `prefix`. Like `vertex_ids_with_property_value_range`, this reads
+    /// back every indexed value of the property.
+    pub fn vertex_ids_with_property_value_prefix<'a>(
+        &'a self,
+        name: Identifier,
+        prefix: &'a str,
+    ) -> indradb::Result<impl Iterator<Item = indradb::Result<Uuid>> + 'a> {
+        let iter = self.iterate_for_property_name(name)?.filter_map(move |item| {
+            let matches = |id: Uuid| -> indradb::Result<bool> {
+                let value = self.get(id, name)?;
+                Ok(value
+                    .as_ref()
+                    .and_then(JsonValue::as_str)
+                    .is_some_and(|value| value.starts_with(prefix)))
+            };
+            match item.and_then(|id| Ok((id, matches(id)?))) {
+                Ok((id, true)) => Some(Ok(id)),
+                Ok((_, false)) => None,
+                Err(err) => Some(Err(err)),
+            }
+        });
+        Ok(iter)
+    }
+
     pub fn iterate_for_owner(
         &self,
         vertex_id: Uuid,
     ) -> indradb::Result<impl Iterator<Item = indradb::Result<OwnedPropertyItem>> + '_> {
         let prefix = util::build(&[util::Component::Uuid(vertex_id)]);
-        let iterator = self.tree.scan_prefix(prefix);
+        let iterator = timed_iter(self.tree.scan_prefix(prefix));
 
         Ok(iterator.map(move |item| -> indradb::Result<OwnedPropertyItem> {
             let (k, v) = map_err(item)?;
@@ -84,20 +173,24 @@ impl<'tree> VertexPropertyManager<'tree> {
             let owner_id = util::read_uuid(&mut cursor);
             debug_assert_eq!(vertex_id, owner_id);
             let name = util::read_identifier(&mut cursor);
-            let value = serde_json::from_slice(&v)?;
+            let value = self.codec.decode(&v)?;
             Ok(((owner_id, name), value))
         }))
     }
 
     pub fn get(&self, vertex_id: Uuid, name: Identifier) -> indradb::Result<Option<JsonValue>> {
-        let key = self.key(vertex_id, name);
-
-        match map_err(self.tree.get(key))? {
-            Some(value_bytes) => Ok(Some(serde_json::from_slice(&value_bytes)?)),
+        match self.get_bytes(vertex_id, name)? {
+            Some(value_bytes) => Ok(Some(self.codec.decode(&value_bytes)?)),
             None => Ok(None),
         }
     }
 
+    /// Gets the stored JSON bytes of a property, without parsing them.
+    pub fn get_bytes(&self, vertex_id: Uuid, name: Identifier) -> indradb::Result<Option<IVec>> {
+        let key = self.key(vertex_id, name);
+        map_err_ctx("get", self.tree, timed!(Get, self.tree.get(key)))
+    }
+
     pub fn set_batch(
         &self,
         vertex_id: Uuid,
@@ -108,11 +201,14 @@ impl<'tree> VertexPropertyManager<'tree> {
         value: &JsonValue,
     ) -> indradb::Result<()> {
         let key = self.key(vertex_id, name);
-        let value_json = serde_json::to_vec(value)?;
+        let value_json = self.codec.encode(value)?;
         batch.insert(key.clone(), value_json);
-        let old_value = map_err(self.tree.get(key.clone()))?;
+        if !self.maintain_value_index {
+            return Ok(());
+        }
+        let old_value = map_err_ctx("get", self.tree, timed!(Get, self.tree.get(key.clone())))?;
         if let Some(old_value) = old_value {
-            let old_value: Json = serde_json::from_slice(&old_value)?;
+            let old_value = self.codec.decode(&old_value)?;
             let value_key = Self::key_value_index(&vertex_id, &old_value, name);
             batch_value.remove(value_key.as_slice());
         }
@@ -123,27 +219,294 @@ impl<'tree> VertexPropertyManager<'tree> {
 
     pub fn set(&self, vertex_id: Uuid, name: Identifier, value: &JsonValue) -> indradb::Result<()> {
         let key = self.key(vertex_id, name);
-        let value_json = serde_json::to_vec(value)?;
+        let value_json = self.codec.encode(value)?;
 
-        if let Some(old) = map_err(self.tree.get(key.clone()))? {
-            let old_value = serde_json::from_slice(&old)?;
+        let old = map_err_ctx(
+            "insert",
+            self.tree,
+            timed!(Insert, self.tree.insert(key.as_slice(), value_json.as_slice())),
+        )?;
+        if !self.maintain_value_index {
+            return Ok(());
+        }
+        if let Some(old) = old {
+            let old_value = self.codec.decode(&old)?;
             let value_index_key = Self::key_value_index(&vertex_id, &old_value, name);
-            map_err(self.value_index_tree.remove(value_index_key))?;
+            map_err_ctx(
+                "remove",
+                self.value_index_tree,
+                timed!(Remove, self.value_index_tree.remove(value_index_key)),
+            )?;
         }
 
-        map_err(self.tree.insert(key.as_slice(), value_json.as_slice()))?;
         let value_index_key = Self::key_value_index(&vertex_id, value, name);
-        map_err(self.value_index_tree.insert(value_index_key, value_json.as_slice()))?;
+        map_err_ctx(
+            "insert",
+            self.value_index_tree,
+            timed!(
+                Insert,
+                self.value_index_tree.insert(value_index_key, value_json.as_slice())
+            ),
+        )?;
+        Ok(())
+    }
+
+    /// Writes the value-index entries for every stored property whose name is
+    /// in `names`, using a single scan over the property tree. Entries are
+    /// applied in chunks so large stores don't build one huge batch.
+    pub fn backfill_value_index(&self, names: &HashSet<Identifier>) -> indradb::Result<()> {
+        self.backfill_where(|name| names.contains(&name))?;
+        Ok(())
+    }
+
+    /// Writes the value-index entries for every stored property whose name
+    /// passes `keep`, returning how many were written.
+    fn backfill_where(&self, keep: impl Fn(Identifier) -> bool) -> indradb::Result<u64> {
+        let mut batch = Batch::default();
+        let mut pending = 0;
+        let mut written = 0;
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (vertex_id, name) = Self::read_key(k);
+            if !keep(name) {
+                continue;
+            }
+            let value = self.codec.decode(&v)?;
+            batch.insert(Self::key_value_index(&vertex_id, &value, name), v);
+            written += 1;
+            pending += 1;
+            if pending == BACKFILL_CHUNK_SIZE {
+                map_err_ctx(
+                    "apply_batch",
+                    self.value_index_tree,
+                    timed!(
+                        ApplyBatch,
+                        self.value_index_tree.apply_batch(std::mem::take(&mut batch))
+                    ),
+                )?;
+                pending = 0;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(batch)),
+        )?;
+        Ok(written)
+    }
+
+    /// Removes the value-index entries of the property `name`, or of every
+    /// property if it's `None`, and writes them again from the property
+    /// tree.
+    pub fn rebuild_value_index(&self, name: Option<Identifier>) -> indradb::Result<RebuildStats> {
+        let prefix = name.map_or_else(Vec::new, |name| util::build(&[util::Component::Identifier(name)]));
+        let mut stats = RebuildStats::default();
+        let mut batch = Batch::default();
+        let mut pending = 0;
+        for item in timed_iter(self.value_index_tree.scan_prefix(prefix)) {
+            let (k, _) = map_err(item)?;
+            batch.remove(k);
+            stats.removed += 1;
+            pending += 1;
+            if pending == BACKFILL_CHUNK_SIZE {
+                map_err_ctx(
+                    "apply_batch",
+                    self.value_index_tree,
+                    timed!(
+                        ApplyBatch,
+                        self.value_index_tree.apply_batch(std::mem::take(&mut batch))
+                    ),
+                )?;
+                pending = 0;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(batch)),
+        )?;
+        stats.added = self.backfill_where(|stored| name.is_none_or(|name| name == stored))?;
+        Ok(stats)
+    }
+
+    /// Cross-checks the property tree against the value index for every
+    /// property in `names`.
+    pub fn verify_value_index(&self, names: &HashSet<Identifier>) -> indradb::Result<Vec<IndexInconsistency>> {
+        let mut inconsistencies = Vec::new();
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (vertex_id, name) = Self::read_key(k);
+            if !names.contains(&name) {
+                continue;
+            }
+            let value = self.codec.decode(&v)?;
+            let value_key = Self::key_value_index(&vertex_id, &value, name);
+            if self.maintain_value_index
+                && !map_err_ctx(
+                    "contains_key",
+                    self.value_index_tree,
+                    timed!(Get, self.value_index_tree.contains_key(value_key)),
+                )?
+            {
+                inconsistencies.push(IndexInconsistency::MissingVertexIndex { id: vertex_id, name });
+            }
+        }
+        for name in names.iter() {
+            let prefix = util::build(&[util::Component::Identifier(*name)]);
+            for item in timed_iter(self.value_index_tree.scan_prefix(prefix)) {
+                let (k, _) = map_err(item)?;
+                let (_, _, vertex_id) = Self::read_key_value_index(k.clone());
+                let is_consistent = match self.get(vertex_id, *name)? {
+                    Some(value) => Self::key_value_index(&vertex_id, &value, *name) == *k,
+                    None => false,
+                };
+                if !is_consistent {
+                    inconsistencies.push(IndexInconsistency::DanglingVertexIndex {
+                        id: vertex_id,
+                        name: *name,
+                    });
+                }
+            }
+        }
+        Ok(inconsistencies)
+    }
+
+    /// Checks that every property belongs to an existing vertex and has a
+    /// value-index entry, and that every value-index entry matches a stored
+    /// property. The value index is maintained for all properties, not only
+    /// indexed ones, unless it's turned off altogether.
+    pub fn check_integrity(
+        &self,
+        owner_exists: impl Fn(Uuid) -> indradb::Result<bool>,
+        report: &mut IntegrityReport,
+    ) -> indradb::Result<()> {
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (id, name) = Self::read_key(k);
+            if !owner_exists(id)? {
+                report.record(IntegrityViolation::OrphanedVertexProperty { id, name });
+            }
+            let value = self.codec.decode(&v)?;
+            let value_key = Self::key_value_index(&id, &value, name);
+            if self.maintain_value_index
+                && !map_err_ctx(
+                    "contains_key",
+                    self.value_index_tree,
+                    timed!(Get, self.value_index_tree.contains_key(value_key)),
+                )?
+            {
+                report.record(IntegrityViolation::MissingVertexPropertyValue { id, name });
+            }
+        }
+        for item in timed_iter(self.value_index_tree.iter()) {
+            let (k, _) = map_err(item)?;
+            let (name, _, id) = Self::read_key_value_index(k.clone());
+            let is_consistent = match self.get(id, name)? {
+                Some(value) => Self::key_value_index(&id, &value, name) == *k,
+                None => false,
+            };
+            if !is_consistent {
+                report.record(IntegrityViolation::DanglingVertexPropertyValue { id, name });
+            }
+        }
+        Ok(())
+    }
+
+    /// Removes properties of vertices that don't exist, writes missing
+    /// value-index entries, and then removes value-index entries that don't
+    /// match a stored property. Returns the changes to the property tree and
+    /// the value index, in that order. Each pass is applied as one batch.
+    pub fn repair(
+        &self,
+        owner_exists: impl Fn(Uuid) -> indradb::Result<bool>,
+    ) -> indradb::Result<(RebuildStats, RebuildStats)> {
+        let (mut properties, mut values) = (RebuildStats::default(), RebuildStats::default());
+        let (mut batch, mut value_batch) = (Batch::default(), Batch::default());
+        for item in timed_iter(self.tree.iter()) {
+            let (k, v) = map_err(item)?;
+            let (id, name) = Self::read_key(k.clone());
+            if !owner_exists(id)? {
+                batch.remove(k);
+                properties.removed += 1;
+                continue;
+            }
+            let value = self.codec.decode(&v)?;
+            let value_key = Self::key_value_index(&id, &value, name);
+            if self.maintain_value_index
+                && !map_err_ctx(
+                    "contains_key",
+                    self.value_index_tree,
+                    timed!(Get, self.value_index_tree.contains_key(&value_key)),
+                )?
+            {
+                value_batch.insert(value_key, v);
+                values.added += 1;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.tree,
+            timed!(ApplyBatch, self.tree.apply_batch(batch)),
+        )?;
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(value_batch)),
+        )?;
+
+        let mut value_batch = Batch::default();
+        for item in timed_iter(self.value_index_tree.iter()) {
+            let (k, _) = map_err(item)?;
+            let (name, _, id) = Self::read_key_value_index(k.clone());
+            let is_consistent = match self.get(id, name)? {
+                Some(value) => Self::key_value_index(&id, &value, name) == *k,
+                None => false,
+            };
+            if !is_consistent {
+                value_batch.remove(k);
+                values.removed += 1;
+            }
+        }
+        map_err_ctx(
+            "apply_batch",
+            self.value_index_tree,
+            timed!(ApplyBatch, self.value_index_tree.apply_batch(value_batch)),
+        )?;
+        Ok((properties, values))
+    }
+
+    /// Queues the removal of every property of a vertex, along with their
+    /// value-index entries.
+    pub fn delete_for_owner_batch(
+        &self,
+        vertex_id: Uuid,
+        batch: &mut Batch,
+        value_batch: &mut Batch,
+    ) -> indradb::Result<()> {
+        for item in self.iterate_for_owner(vertex_id)? {
+            let ((id, name), value) = item?;
+            batch.remove(self.key(id, name));
+            if self.maintain_value_index {
+                value_batch.remove(Self::key_value_index(&id, &value, name));
+            }
+        }
         Ok(())
     }
 
     pub fn delete(&self, vertex_id: Uuid, name: Identifier) -> indradb::Result<()> {
-        let old_value = map_err(self.tree.get(self.key(vertex_id, name)))?;
-        map_err(self.tree.remove(self.key(vertex_id, name)))?;
-        if let Some(old_value) = old_value {
-            let old_value = serde_json::from_slice(&old_value)?;
+        let old_value = map_err_ctx(
+            "remove",
+            self.tree,
+            timed!(Remove, self.tree.remove(self.key(vertex_id, name))),
+        )?;
+        if let Some(old_value) = old_value.filter(|_| self.maintain_value_index) {
+            let old_value = self.codec.decode(&old_value)?;
             let value_index_key = Self::key_value_index(&vertex_id, &old_value, name);
-            map_err(self.value_index_tree.remove(value_index_key))?;
+            map_err_ctx(
+                "remove",
+                self.value_index_tree,
+                timed!(Remove, self.value_index_tree.remove(value_index_key)),
+            )?;
         }
 
         Ok(())
@@ -152,13 +515,13 @@ impl<'tree> VertexPropertyManager<'tree> {
 #[cfg(test)]
 mod test {
     use serde_json::json;
-    use uuid::{Context, Timestamp};
+    use uuid::{ContextV1, Timestamp};
 
     use super::*;
 
     #[test]
     fn test_index_key_and_reco() {
-        let context = Context::new(24);
+        let context = ContextV1::new(24);
         let uuid = Uuid::new_v1(Timestamp::now(context), &[1, 2, 3, 4, 5, 6]);
         let name = Identifier::new("_changesetID").unwrap();
         let value = json! {"Changesets/25dfc1e7-fdd1-4027-9e98-48a8429a9c70"};
@@ -0,0 +1,218 @@
+//! On-disk format versioning.
+//!
+//! The format version is stored in the metadata tree. Fresh stores are
+//! stamped with `FORMAT_VERSION` when opened, and stores written before
+//! versioning existed are taken to be at version 1, whose layout they share.
+//! When the key layout changes, bump `FORMAT_VERSION` and register a
+//! `Migration` in `MIGRATIONS` that rewrites the trees of the previous
+//! version in place.
+//!
+//! Version 2 added the `edge_types` tree, an index of edges by type. Stores
+//! at version 1 get it built from the edge ranges when they're first opened
+//! by a newer crate, which takes one scan over all edges. Read-only stores
+//! have to be opened for writing once to be migrated.
+
+use indradb::{util, Identifier};
+use sled::Batch;
+
+use crate::counters::{decode_count, encode_count};
+use crate::datastore::SledHolder;
+use crate::errors::{map_err, DSError};
+use crate::managers::edge_range_manager::EdgeRangeManager;
+use crate::managers::edge_type_manager::EdgeTypeManager;
+use crate::managers::metadata::FORMAT_VERSION_KEY;
+use crate::managers::BACKFILL_CHUNK_SIZE;
+
+/// The format version written by this version of the crate.
+pub(crate) const FORMAT_VERSION: u64 = 2;
+
+/// An upgrade from one format version to the next.
+pub(crate) struct Migration {
+    /// The version this migration upgrades from, to `from + 1`.
+    pub from: u64,
+    /// Rewrites the trees of `holder` in place.
+    pub run: fn(&SledHolder) -> indradb::Result<()>,
+}
+
+/// The registered migrations.
+pub(crate) const MIGRATIONS: &[Migration] = &[Migration {
+    from: 1,
+    run: build_edge_type_index,
+}];
+
+/// Builds the `edge_types` index from the edge ranges.
+fn build_edge_type_index(holder: &SledHolder) -> indradb::Result<()> {
+    let edge_type_manager = EdgeTypeManager::new(holder);
+    let mut batch = Batch::default();
+    let mut pending = 0;
+    for item in EdgeRangeManager::new(holder).iterate_for_all() {
+        edge_type_manager.set_batch(&item?, &mut batch)?;
+        pending += 1;
+        if pending == BACKFILL_CHUNK_SIZE {
+            map_err(timed!(
+                ApplyBatch,
+                holder.edge_types.apply_batch(std::mem::take(&mut batch))
+            ))?;
+            pending = 0;
+        }
+    }
+    map_err(timed!(ApplyBatch, holder.edge_types.apply_batch(batch)))
+}
+
+fn version_key() -> indradb::Result<Vec<u8>> {
+    Ok(util::build(&[util::Component::Identifier(Identifier::new(
+        FORMAT_VERSION_KEY,
+    )?)]))
+}
+
+fn is_empty(holder: &SledHolder) -> bool {
+    holder.named_trees().iter().all(|(_, tree)| tree.is_empty())
+}
+
+/// Checks the format version of `holder`, stamping fresh stores and running
+/// migrations for older ones.
+pub(crate) fn prepare(holder: &SledHolder) -> indradb::Result<()> {
+    migrate(holder, FORMAT_VERSION, MIGRATIONS)
+}
+
+fn migrate(holder: &SledHolder, current: u64, migrations: &[Migration]) -> indradb::Result<()> {
+    let key = version_key()?;
+    let stored = map_err(holder.metadata.get(&key))?.map(|value| decode_count(&value));
+    let mut version = match stored {
+        Some(version) => version,
+        None if is_empty(holder) => current,
+        None => 1,
+    };
+    if version > current {
+        return Err(DSError::UnsupportedFormatVersion {
+            found: version,
+            current,
+        }
+        .into());
+    }
+    if stored == Some(current) {
+        return Ok(());
+    }
+    if holder.is_read_only && version < current {
+        return Err(DSError::ReadOnly.into());
+    }
+    while version < current {
+        let migration =
+            migrations
+                .iter()
+                .find(|migration| migration.from == version)
+                .ok_or(DSError::UnsupportedFormatVersion {
+                    found: version,
+                    current,
+                })?;
+        (migration.run)(holder)?;
+        version += 1;
+        map_err(holder.metadata.insert(&key, &encode_count(version)))?;
+    }
+    if !holder.is_read_only && stored.is_none() {
+        map_err(holder.metadata.insert(&key, &encode_count(version)))?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use indradb::{Datastore, Edge, Identifier, Transaction, Vertex};
+    use sled::Config;
+    use tempfile::tempdir;
+
+    use super::{migrate, version_key, Migration, FORMAT_VERSION};
+    use crate::counters::{decode_count, encode_count};
+    use crate::datastore::SledHolder;
+    use crate::errors::DSError;
+    use crate::{SledConfig, SledDatastore};
+
+    #[test]
+    fn test_fresh_store_is_stamped() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let stored = ds.holder.metadata.get(version_key().unwrap()).unwrap().unwrap();
+        assert_eq!(decode_count(&stored), FORMAT_VERSION);
+    }
+
+    #[test]
+    fn test_unknown_version_fails_to_open() {
+        let path = tempdir().unwrap().keep();
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            ds.transaction()
+                .create_vertex(&Vertex::new(Identifier::new("foo").unwrap()))
+                .unwrap();
+            ds.holder
+                .metadata
+                .insert(version_key().unwrap(), &encode_count(FORMAT_VERSION + 1))
+                .unwrap();
+            ds.holder.db.flush().unwrap();
+        }
+        match SledDatastore::new(&path) {
+            Err(indradb::Error::Datastore(err)) => {
+                assert!(err.to_string().contains("format version"), "{}", err);
+            }
+            Err(err) => panic!("unexpected error: {}", err),
+            Ok(_) => panic!("opened a store with an unknown format version"),
+        }
+        assert!(SledConfig::default().read_only().open(&path).is_err());
+    }
+
+    #[test]
+    fn test_edge_type_index_is_built_from_version_1() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("foo").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&a).unwrap();
+            txn.create_vertex(&b).unwrap();
+            txn.create_edge(&edge).unwrap();
+            // Roll the store back to the version 1 layout
+            ds.holder.edge_types.clear().unwrap();
+            ds.holder
+                .metadata
+                .insert(version_key().unwrap(), &encode_count(1))
+                .unwrap();
+            ds.holder.db.flush().unwrap();
+        }
+
+        let ds = SledDatastore::new(&path).unwrap();
+        let stored = ds.holder.metadata.get(version_key().unwrap()).unwrap().unwrap();
+        assert_eq!(decode_count(&stored), FORMAT_VERSION);
+        let found: Vec<Edge> = ds
+            .transaction()
+            .edges_with_type(t)
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(found, vec![edge]);
+    }
+
+    #[test]
+    fn test_migrations_run_in_order() {
+        fn mark(holder: &SledHolder) -> indradb::Result<()> {
+            holder.metadata.insert("migrated", &[]).unwrap();
+            Ok(())
+        }
+        let db = Config::new().temporary(true).open().unwrap();
+        let holder = SledHolder::open_with_db(db).unwrap();
+        holder.vertices.insert("legacy", &[]).unwrap();
+        holder.metadata.remove(version_key().unwrap()).unwrap();
+
+        let migrations = [Migration { from: 1, run: mark }];
+        migrate(&holder, 2, &migrations).unwrap();
+        assert!(holder.metadata.contains_key("migrated").unwrap());
+        let stored = holder.metadata.get(version_key().unwrap()).unwrap().unwrap();
+        assert_eq!(decode_count(&stored), 2);
+
+        holder.metadata.remove(version_key().unwrap()).unwrap();
+        let Err(indradb::Error::Datastore(err)) = migrate(&holder, 3, &migrations) else {
+            panic!("expected a missing migration to fail");
+        };
+        assert!(err.downcast_ref::<DSError>().is_some());
+    }
+}
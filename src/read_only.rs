@@ -0,0 +1,191 @@
+//! A datastore whose transactions can only read.
+//!
+//! `ReadOnlyTransaction` forwards every read to a `SledTransaction` over a
+//! holder opened with `SledConfig::read_only`, and fails every write with
+//! `Error::Unsupported` before it reaches the trees.
+
+use indradb::{BulkInsertItem, Datastore, DynIter, Edge, Error, Identifier, Json, Transaction, Vertex};
+use uuid::Uuid;
+
+use crate::datastore::SledHolder;
+use crate::transaction::SledTransaction;
+
+/// A sled datastore opened with `SledConfig::open_read_only`.
+pub struct SledDatastoreReadOnly {
+    pub(crate) holder: SledHolder,
+}
+
+impl Datastore for SledDatastoreReadOnly {
+    type Transaction<'a> = ReadOnlyTransaction<'a>
+    where
+        Self: 'a;
+
+    fn transaction(&self) -> Self::Transaction<'_> {
+        ReadOnlyTransaction {
+            inner: self.holder.transaction(),
+        }
+    }
+}
+
+/// A transaction of a `SledDatastoreReadOnly`.
+pub struct ReadOnlyTransaction<'a> {
+    inner: SledTransaction<'a>,
+}
+
+impl<'a> Transaction<'a> for ReadOnlyTransaction<'a> {
+    fn vertex_count(&self) -> u64 {
+        self.inner.vertex_count()
+    }
+
+    fn all_vertices(&'a self) -> indradb::Result<DynIter<'a, Vertex>> {
+        self.inner.all_vertices()
+    }
+
+    fn range_vertices(&'a self, offset: Uuid) -> indradb::Result<DynIter<'a, Vertex>> {
+        self.inner.range_vertices(offset)
+    }
+
+    fn specific_vertices(&'a self, ids: Vec<Uuid>) -> indradb::Result<DynIter<'a, Vertex>> {
+        self.inner.specific_vertices(ids)
+    }
+
+    fn vertex_ids_with_property(&'a self, name: Identifier) -> indradb::Result<Option<DynIter<'a, Uuid>>> {
+        self.inner.vertex_ids_with_property(name)
+    }
+
+    fn vertex_ids_with_property_value(
+        &'a self,
+        name: Identifier,
+        value: &Json,
+    ) -> indradb::Result<Option<DynIter<'a, Uuid>>> {
+        self.inner.vertex_ids_with_property_value(name, value)
+    }
+
+    fn edge_count(&self) -> u64 {
+        self.inner.edge_count()
+    }
+
+    fn all_edges(&'a self) -> indradb::Result<DynIter<'a, Edge>> {
+        self.inner.all_edges()
+    }
+
+    fn range_edges(&'a self, offset: Edge) -> indradb::Result<DynIter<'a, Edge>> {
+        self.inner.range_edges(offset)
+    }
+
+    fn range_reversed_edges(&'a self, offset: Edge) -> indradb::Result<DynIter<'a, Edge>> {
+        self.inner.range_reversed_edges(offset)
+    }
+
+    fn specific_edges(&'a self, edges: Vec<Edge>) -> indradb::Result<DynIter<'a, Edge>> {
+        self.inner.specific_edges(edges)
+    }
+
+    fn edges_with_property(&'a self, name: Identifier) -> indradb::Result<Option<DynIter<'a, Edge>>> {
+        self.inner.edges_with_property(name)
+    }
+
+    fn edges_with_property_value(
+        &'a self,
+        name: Identifier,
+        value: &Json,
+    ) -> indradb::Result<Option<DynIter<'a, Edge>>> {
+        self.inner.edges_with_property_value(name, value)
+    }
+
+    fn vertex_property(&self, vertex: &Vertex, name: Identifier) -> indradb::Result<Option<Json>> {
+        self.inner.vertex_property(vertex, name)
+    }
+
+    fn all_vertex_properties_for_vertex(&'a self, vertex: &Vertex) -> indradb::Result<DynIter<'a, (Identifier, Json)>> {
+        self.inner.all_vertex_properties_for_vertex(vertex)
+    }
+
+    fn edge_property(&self, edge: &Edge, name: Identifier) -> indradb::Result<Option<Json>> {
+        self.inner.edge_property(edge, name)
+    }
+
+    fn all_edge_properties_for_edge(&'a self, edge: &Edge) -> indradb::Result<DynIter<'a, (Identifier, Json)>> {
+        self.inner.all_edge_properties_for_edge(edge)
+    }
+
+    fn delete_vertices(&mut self, _vertices: Vec<Vertex>) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    fn delete_edges(&mut self, _edges: Vec<Edge>) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    fn delete_vertex_properties(&mut self, _props: Vec<(Uuid, Identifier)>) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    fn delete_edge_properties(&mut self, _props: Vec<(Edge, Identifier)>) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    // Nothing can have been written, so there's nothing to flush
+    fn sync(&self) -> indradb::Result<()> {
+        Ok(())
+    }
+
+    fn create_vertex(&mut self, _vertex: &Vertex) -> indradb::Result<bool> {
+        Err(Error::Unsupported)
+    }
+
+    fn create_edge(&mut self, _edge: &Edge) -> indradb::Result<bool> {
+        Err(Error::Unsupported)
+    }
+
+    fn bulk_insert(&mut self, _items: Vec<BulkInsertItem>) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    fn index_property(&mut self, _name: Identifier) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    fn set_vertex_properties(&mut self, _vertices: Vec<Uuid>, _name: Identifier, _value: &Json) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+
+    fn set_edge_properties(&mut self, _edges: Vec<Edge>, _name: Identifier, _value: &Json) -> indradb::Result<()> {
+        Err(Error::Unsupported)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use indradb::{Datastore, Error, Identifier, Json, Transaction, Vertex};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use crate::{SledConfig, SledDatastore};
+
+    #[test]
+    fn test_read_only_transaction() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let v = Vertex::new(t);
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&v).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("alice")))
+                .unwrap();
+            txn.sync().unwrap();
+        }
+
+        let ds = SledConfig::default().open_read_only(&path).unwrap();
+        let mut txn = ds.transaction();
+        assert!(matches!(txn.create_vertex(&Vertex::new(t)), Err(Error::Unsupported)));
+        assert!(matches!(txn.delete_vertices(vec![v.clone()]), Err(Error::Unsupported)));
+        assert!(matches!(txn.index_property(name), Err(Error::Unsupported)));
+        assert_eq!(txn.vertex_count(), 1);
+        assert_eq!(txn.all_vertices().unwrap().next().unwrap().unwrap(), v);
+        assert_eq!(txn.vertex_property(&v, name).unwrap(), Some(Json::new(json!("alice"))));
+        txn.sync().unwrap();
+    }
+}
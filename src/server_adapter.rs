@@ -0,0 +1,71 @@
+//! Glue for serving a sled datastore from multi-threaded or async services.
+//!
+//! `SledTransaction` borrows from its `SledDatastore`, so it can't be held
+//! across await points or handed to another thread. Service code should
+//! instead hold an `OwnedTransactionHolder`, which is `Send + 'static`, and
+//! open a short-lived transaction per request with `with_transaction`.
+//!
+//! ```
+//! use std::thread;
+//!
+//! use indradb::{Identifier, Transaction, Vertex};
+//! use indradb_sled::{OwnedTransactionHolder, SledDatastore};
+//!
+//! let path = tempfile::tempdir().unwrap().keep();
+//! let holder = OwnedTransactionHolder::new(SledDatastore::new(path).unwrap());
+//! let t = Identifier::new("request").unwrap();
+//!
+//! let workers: Vec<_> = (0..4)
+//!     .map(|_| {
+//!         let holder = holder.clone();
+//!         thread::spawn(move || holder.with_transaction(|txn| txn.create_vertex(&Vertex::new(t))))
+//!     })
+//!     .collect();
+//! for worker in workers {
+//!     assert!(worker.join().unwrap().unwrap());
+//! }
+//!
+//! assert_eq!(holder.with_transaction(|txn| txn.vertex_count()), 4);
+//! ```
+
+use std::sync::Arc;
+
+use crate::datastore::SledDatastore;
+use crate::transaction::SledTransaction;
+
+/// A cheaply clonable, `Send + 'static` handle to a shared sled datastore.
+#[derive(Clone)]
+pub struct OwnedTransactionHolder {
+    datastore: Arc<SledDatastore>,
+}
+
+impl OwnedTransactionHolder {
+    /// Creates a new holder that owns the datastore.
+    ///
+    /// # Arguments
+    /// * `datastore`: The datastore to share.
+    pub fn new(datastore: SledDatastore) -> Self {
+        Self::from_arc(Arc::new(datastore))
+    }
+
+    /// Creates a new holder around an already shared datastore.
+    ///
+    /// # Arguments
+    /// * `datastore`: The datastore to share.
+    pub fn from_arc(datastore: Arc<SledDatastore>) -> Self {
+        OwnedTransactionHolder { datastore }
+    }
+
+    /// Gets the shared datastore.
+    pub fn datastore(&self) -> &Arc<SledDatastore> {
+        &self.datastore
+    }
+
+    /// Runs `f` against a fresh transaction on the shared datastore.
+    ///
+    /// # Arguments
+    /// * `f`: The function to run.
+    pub fn with_transaction<R>(&self, f: impl FnOnce(&mut SledTransaction<'_>) -> R) -> R {
+        self.datastore.with_transaction(f)
+    }
+}
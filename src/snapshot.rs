@@ -0,0 +1,351 @@
+//! Single-file snapshots of a datastore.
+//!
+//! A snapshot starts with `MAGIC` and a format version, followed by one
+//! record per key/value pair. Each record is the name of its tree, the key
+//! and the value, each prefixed with its length as a big-endian u32.
+//!
+//! Backups skip the file format and copy each tree straight into the trees
+//! of another database. Once everything is copied, the number of entries is
+//! written to the backup's metadata, so that restores can tell a complete
+//! backup from one that was cut short.
+
+use std::collections::HashMap;
+use std::io::{BufReader, BufWriter, ErrorKind, Read, Write};
+
+use indradb::{util, Identifier};
+use sled::Tree;
+
+use crate::counters::{decode_count, encode_count};
+use crate::datastore::SledHolder;
+use crate::errors::{map_err, DSError};
+use crate::latency::timed_iter;
+use crate::managers::count_keys;
+use crate::managers::metadata::BACKUP_ENTRIES_KEY;
+use crate::migrations;
+
+const MAGIC: &[u8; 8] = b"IDBSLEDS";
+const VERSION: u8 = 1;
+
+fn write_chunk<W: Write>(writer: &mut W, chunk: &[u8]) -> Result<(), DSError> {
+    let len = u32::try_from(chunk.len())
+        .map_err(|_| DSError::InvalidSnapshot(format!("chunk of {} bytes is too large", chunk.len())))?;
+    writer.write_all(&len.to_be_bytes())?;
+    writer.write_all(chunk)?;
+    Ok(())
+}
+
+fn read_chunk<R: Read>(reader: &mut R) -> Result<Vec<u8>, DSError> {
+    let mut len = [0; 4];
+    reader.read_exact(&mut len)?;
+    let mut chunk = vec![0; u32::from_be_bytes(len) as usize];
+    reader.read_exact(&mut chunk)?;
+    Ok(chunk)
+}
+
+/// Reads the tree name of the next record, or `None` at the end of the
+/// snapshot.
+fn read_tree_name<R: Read>(reader: &mut R) -> Result<Option<Vec<u8>>, DSError> {
+    let mut len = [0; 4];
+    match reader.read_exact(&mut len[..1]) {
+        Ok(()) => {}
+        Err(err) if err.kind() == ErrorKind::UnexpectedEof => return Ok(None),
+        Err(err) => return Err(err.into()),
+    }
+    reader.read_exact(&mut len[1..])?;
+    let mut name = vec![0; u32::from_be_bytes(len) as usize];
+    reader.read_exact(&mut name)?;
+    Ok(Some(name))
+}
+
+pub(crate) fn export<W: Write>(holder: &SledHolder, writer: W) -> indradb::Result<()> {
+    map_err(holder.db.flush())?;
+    let mut writer = BufWriter::new(writer);
+    writer.write_all(MAGIC).map_err(DSError::from)?;
+    writer.write_all(&[VERSION]).map_err(DSError::from)?;
+    for (name, tree) in holder.named_trees() {
+        for item in timed_iter(tree.iter()) {
+            let (key, value) = map_err(item)?;
+            write_chunk(&mut writer, name.as_bytes())?;
+            write_chunk(&mut writer, &key)?;
+            write_chunk(&mut writer, &value)?;
+        }
+    }
+    writer.flush().map_err(DSError::from)?;
+    Ok(())
+}
+
+pub(crate) fn import<R: Read>(holder: &SledHolder, reader: R) -> indradb::Result<()> {
+    if holder.is_read_only {
+        return Err(DSError::ReadOnly.into());
+    }
+    let mut reader = BufReader::new(reader);
+    let mut header = [0; 9];
+    reader.read_exact(&mut header).map_err(DSError::from)?;
+    if &header[..8] != MAGIC {
+        return Err(DSError::InvalidSnapshot("missing snapshot header".to_string()).into());
+    }
+    if header[8] != VERSION {
+        return Err(DSError::InvalidSnapshot(format!("unsupported version {}", header[8])).into());
+    }
+
+    let trees: HashMap<&[u8], &Tree> = holder
+        .named_trees()
+        .into_iter()
+        .map(|(name, tree)| (name.as_bytes(), tree))
+        .collect();
+    for tree in trees.values() {
+        map_err(tree.clear())?;
+    }
+    while let Some(name) = read_tree_name(&mut reader)? {
+        let tree = trees
+            .get(name.as_slice())
+            .ok_or_else(|| DSError::InvalidSnapshot(format!("unknown tree {:?}", String::from_utf8_lossy(&name))))?;
+        let key = read_chunk(&mut reader)?;
+        let value = read_chunk(&mut reader)?;
+        map_err(timed!(Insert, tree.insert(key, value)))?;
+    }
+    // Snapshots written by older versions of the crate need migrating
+    migrations::prepare(holder)?;
+    map_err(holder.db.flush())?;
+    Ok(())
+}
+
+/// Copies every tree of `source` into the matching tree of `target`, whose
+/// existing contents are cleared first.
+pub(crate) fn copy(source: &SledHolder, target: &SledHolder) -> indradb::Result<()> {
+    if target.is_read_only {
+        return Err(DSError::ReadOnly.into());
+    }
+    map_err(source.db.flush())?;
+    for ((_, from), (_, to)) in source.named_trees().into_iter().zip(target.named_trees()) {
+        map_err(to.clear())?;
+        for item in timed_iter(from.iter()) {
+            let (key, value) = map_err(item)?;
+            map_err(timed!(Insert, to.insert(key, value)))?;
+        }
+    }
+    map_err(target.db.flush())?;
+    Ok(())
+}
+
+fn backup_entries_key() -> indradb::Result<Vec<u8>> {
+    Ok(util::build(&[util::Component::Identifier(Identifier::new(
+        BACKUP_ENTRIES_KEY,
+    )?)]))
+}
+
+fn entry_count(holder: &SledHolder) -> u64 {
+    holder.named_trees().iter().map(|(_, tree)| count_keys(tree)).sum()
+}
+
+/// Copies `source` into `target` and marks the copy as a complete backup.
+pub(crate) fn backup(source: &SledHolder, target: &SledHolder) -> indradb::Result<()> {
+    copy(source, target)?;
+    let key = backup_entries_key()?;
+    map_err(target.metadata.remove(&key))?;
+    let entries = entry_count(target);
+    map_err(target.metadata.insert(&key, &encode_count(entries)))?;
+    map_err(target.db.flush())?;
+    Ok(())
+}
+
+/// Checks that a backup written by `backup` was completed and hasn't lost
+/// any entries since.
+pub(crate) fn verify_backup(backup: &SledHolder) -> indradb::Result<()> {
+    let expected = match map_err(backup.metadata.get(backup_entries_key()?))? {
+        Some(value) => decode_count(&value),
+        None => return Err(DSError::InvalidBackup("the backup was not completed".to_string()).into()),
+    };
+    let found = entry_count(backup) - 1;
+    if found != expected {
+        return Err(DSError::InvalidBackup(format!("expected {} entries, found {}", expected, found)).into());
+    }
+    Ok(())
+}
+
+/// Copies a backup checked by `verify_backup` into `target`.
+pub(crate) fn restore(backup: &SledHolder, target: &SledHolder) -> indradb::Result<()> {
+    copy(backup, target)?;
+    map_err(target.metadata.remove(backup_entries_key()?))?;
+    map_err(target.db.flush())?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::BTreeSet;
+
+    use indradb::{Datastore, Edge, Identifier, Json, Transaction, Vertex};
+    use serde_json::json;
+    use tempfile::tempdir;
+
+    use crate::SledDatastore;
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let since = Identifier::new("since").unwrap();
+        let mut txn = source.transaction();
+        txn.index_property(name).unwrap();
+        let vertices: Vec<Vertex> = (0..20).map(|_| Vertex::new(t)).collect();
+        for (i, v) in vertices.iter().enumerate() {
+            txn.create_vertex(v).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(json!(format!("v{}", i))))
+                .unwrap();
+        }
+        for pair in vertices.windows(2) {
+            let edge = Edge::new(pair[0].id, follows, pair[1].id);
+            txn.create_edge(&edge).unwrap();
+            txn.set_edge_properties(vec![edge], since, &Json::new(json!(2024)))
+                .unwrap();
+        }
+
+        let mut snapshot = Vec::new();
+        source.export_snapshot(&mut snapshot).unwrap();
+
+        let target = SledDatastore::new_temporary().unwrap();
+        target
+            .transaction()
+            .create_vertex(&Vertex::new(Identifier::new("stale").unwrap()))
+            .unwrap();
+        target.import_snapshot(snapshot.as_slice()).unwrap();
+
+        let src = source.transaction();
+        let dst = target.transaction();
+        let src_vertices: BTreeSet<_> = src.all_vertices().unwrap().map(|v| v.unwrap().id).collect();
+        let dst_vertices: BTreeSet<_> = dst.all_vertices().unwrap().map(|v| v.unwrap().id).collect();
+        assert_eq!(src_vertices, dst_vertices);
+        let src_edges: BTreeSet<_> = src.all_edges().unwrap().map(|e| e.unwrap()).collect();
+        let dst_edges: BTreeSet<_> = dst.all_edges().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(src_edges, dst_edges);
+        assert_eq!(dst.in_degree(vertices[1].id).unwrap(), 1);
+
+        for v in &vertices {
+            assert_eq!(
+                dst.vertex_property(v, name).unwrap(),
+                src.vertex_property(v, name).unwrap()
+            );
+        }
+        for edge in &src_edges {
+            assert_eq!(
+                dst.edge_property(edge, since).unwrap(),
+                src.edge_property(edge, since).unwrap()
+            );
+        }
+        let found: Vec<_> = dst
+            .vertex_ids_with_property_value(name, &Json::new(json!("v3")))
+            .unwrap()
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(found, vec![vertices[3].id]);
+    }
+
+    #[test]
+    fn test_import_rejects_garbage() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        assert!(ds.import_snapshot(&b"not a snapshot"[..]).is_err());
+    }
+
+    #[test]
+    fn test_backup_to() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let mut txn = source.transaction();
+        txn.index_property(name).unwrap();
+        let vertices: Vec<Vertex> = (0..5).map(|_| Vertex::new(t)).collect();
+        for (i, v) in vertices.iter().enumerate() {
+            txn.create_vertex(v).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(json!(format!("v{}", i))))
+                .unwrap();
+        }
+        for pair in vertices.windows(2) {
+            txn.create_edge(&Edge::new(pair[0].id, t, pair[1].id)).unwrap();
+        }
+
+        let path = tempdir().unwrap().keep();
+        source.backup_to(&path).unwrap();
+        // The source stays writable after the backup
+        txn.create_vertex(&Vertex::new(t)).unwrap();
+
+        let copy = SledDatastore::new(&path).unwrap();
+        let dst = copy.transaction();
+        assert_eq!(dst.vertex_count(), 5);
+        assert_eq!(dst.edge_count(), 4);
+        let found: Vec<_> = dst
+            .vertex_ids_with_property_value(name, &Json::new(json!("v2")))
+            .unwrap()
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(found, vec![vertices[2].id]);
+    }
+
+    #[test]
+    fn test_restore_from() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let since = Identifier::new("since").unwrap();
+        let mut txn = source.transaction();
+        txn.index_property(name).unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!("ada")))
+            .unwrap();
+        txn.set_edge_properties(vec![edge.clone()], since, &Json::new(json!(1843)))
+            .unwrap();
+
+        let backup = tempdir().unwrap().keep();
+        source.backup_to(&backup).unwrap();
+
+        let target = tempdir().unwrap().keep();
+        let restored = SledDatastore::restore_from(&backup, &target).unwrap();
+        let dst = restored.transaction();
+        assert_eq!(dst.vertex_count(), 2);
+        assert_eq!(dst.edge_count(), 1);
+        assert_eq!(*dst.edge_property(&edge, since).unwrap().unwrap(), json!(1843));
+        let found: Vec<_> = dst
+            .vertex_ids_with_property_value(name, &Json::new(json!("ada")))
+            .unwrap()
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(found, vec![a.id]);
+        assert!(dst.verify_indexes().unwrap().is_empty());
+
+        // The target is no longer empty
+        assert!(SledDatastore::restore_from(&backup, &target).is_err());
+        let missing = tempdir().unwrap().path().join("missing");
+        assert!(SledDatastore::restore_from(&missing, tempdir().unwrap().keep()).is_err());
+    }
+
+    #[test]
+    fn test_restore_rejects_partial_backup() {
+        let source = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("person").unwrap();
+        for _ in 0..3 {
+            source.transaction().create_vertex(&Vertex::new(t)).unwrap();
+        }
+        let backup = tempdir().unwrap().keep();
+        source.backup_to(&backup).unwrap();
+        {
+            // Lose a vertex, as if the copy had been cut short
+            let partial = SledDatastore::new(&backup).unwrap();
+            let (key, _) = partial.holder.vertices.first().unwrap().unwrap();
+            partial.holder.vertices.remove(key).unwrap();
+            partial.holder.db.flush().unwrap();
+        }
+        let target = tempdir().unwrap().path().join("restored");
+        assert!(SledDatastore::restore_from(&backup, &target).is_err());
+        assert!(!target.exists());
+    }
+}
@@ -1,25 +1,93 @@
-use std::collections::HashMap;
-use std::ops::Deref;
+use std::collections::{HashMap, HashSet};
 
 use indradb::{BulkInsertItem, DynIter, Edge, Error, Identifier, Json, Transaction, Vertex};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
 use sled::{Batch, IVec};
 use uuid::Uuid;
 
 use crate::datastore::SledHolder;
-use crate::errors::map_err;
+use crate::encoding::PropertyEncoding;
+use crate::errors::{map_err, DSError};
+use crate::integrity::{IndexInconsistency, IntegrityReport, IntegrityViolation, RepairStats};
 use crate::managers::edge_manager::EdgeManager;
 use crate::managers::edge_property_manager::EdgePropertyManager;
 use crate::managers::edge_range_manager::EdgeRangeManager;
+use crate::managers::edge_type_manager::EdgeTypeManager;
 use crate::managers::metadata::MetaDataManager;
 use crate::managers::vertex_manager::VertexManager;
 use crate::managers::vertex_property_manager::VertexPropertyManager;
+use crate::reverse_edge;
+
+/// The smallest number of items `bulk_insert` stages on one thread, below
+/// which the overhead of splitting outweighs the gain.
+#[cfg(feature = "rayon")]
+const MIN_BULK_INSERT_PARTITION: usize = 1_000;
+
+/// A vertex along with all of its properties, as returned by
+/// `SledTransaction::vertices_with_all_properties`.
+pub type VertexWithProperties = (Vertex, Vec<(Identifier, Json)>);
+
+/// The result of `SledTransaction::create_edge_checked`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CreateEdgeOutcome {
+    /// The edge didn't exist and was created.
+    Created,
+    /// The edge already existed and was written again.
+    Overwritten,
+    /// The outbound vertex doesn't exist, so nothing was written.
+    MissingOutbound,
+    /// The inbound vertex doesn't exist, so nothing was written.
+    MissingInbound,
+}
+
+/// Which edges of a vertex `SledTransaction::neighbors` follows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum NeighborDirection {
+    /// The outbound edges, to the vertices they go into.
+    Outbound,
+    /// The inbound edges, from the vertices they come out of. These are read
+    /// from the reversed edge ranges.
+    Inbound,
+    /// The outbound edges followed by the inbound edges.
+    Both,
+}
+
+/// The weight used by `sample_neighbor` for edges without a numeric weight.
+const DEFAULT_NEIGHBOR_WEIGHT: f64 = 1.0;
+
+/// Advances a splitmix64 state and returns a float in `[0, 1)`.
+fn next_unit_f64(state: &mut u64) -> f64 {
+    *state = state.wrapping_add(0x9e37_79b9_7f4a_7c15);
+    let mut z = *state;
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+    z ^= z >> 31;
+    (z >> 11) as f64 / (1u64 << 53) as f64
+}
+
+/// Wraps a serde error with the owner and name of the property it concerns.
+fn property_conversion_error(owner: String, name: Identifier) -> impl FnOnce(serde_json::Error) -> Error {
+    move |source| {
+        DSError::PropertyConversion {
+            owner,
+            name: name.to_string(),
+            source,
+        }
+        .into()
+    }
+}
 
 #[derive(Default)]
 struct IndraSledBatch {
     pub(crate) vertex_creation_batch: Batch,
+    pub(crate) vertex_creation_set: HashMap<Uuid, (Option<Identifier>, Identifier)>,
     pub(crate) edge_creation_batch: Batch,
+    pub(crate) edge_creation_set: HashSet<Edge>,
+    pub(crate) edge_removal_set: HashSet<Edge>,
     pub(crate) edge_range_creation_batch: Batch,
     pub(crate) edge_range_rev_creation_batch: Batch,
+    pub(crate) edge_type_creation_batch: Batch,
     pub(crate) vertex_property_creation_batch: Batch,
     pub(crate) vertex_property_value_creation_batch: Batch,
     pub(crate) vertex_property_creation_set: HashMap<(Uuid, Identifier), Vec<u8>>,
@@ -29,38 +97,235 @@ struct IndraSledBatch {
 }
 
 impl IndraSledBatch {
-    fn apply(mut self, holder: &SledHolder) -> indradb::Result<()> {
-        map_err(holder.db.deref().apply_batch(self.vertex_creation_batch))?;
-        map_err(holder.edges.apply_batch(self.edge_creation_batch))?;
-        map_err(holder.edge_ranges.apply_batch(self.edge_range_creation_batch))?;
-        map_err(
-            holder
-                .reversed_edge_ranges
-                .apply_batch(self.edge_range_rev_creation_batch),
-        )?;
-        map_err(holder.edge_properties.apply_batch(self.edge_property_creation_batch))?;
-        map_err(
-            holder
-                .vertex_properties
-                .apply_batch(self.vertex_property_creation_batch),
+    /// Queues the writes for `items`. Nothing is written until the batch is
+    /// applied, so the previous values read here are those stored before any
+    /// of the items.
+    fn stage(holder: &SledHolder, items: &[BulkInsertItem]) -> indradb::Result<Self> {
+        let vertex_manager = VertexManager::new(holder);
+        let edge_manager = EdgeManager::new(holder);
+        let vertex_property_manager = holder.vertex_property_manager();
+        let edge_property_manager = holder.edge_property_manager();
+        let mut batch = IndraSledBatch::default();
+
+        for item in items {
+            match item {
+                BulkInsertItem::Vertex(v) => {
+                    vertex_manager.create_batch(v, &mut batch.vertex_creation_batch, &mut batch.vertex_creation_set)?;
+                }
+                BulkInsertItem::Edge(e) => {
+                    edge_manager.set_batch(
+                        e,
+                        &mut batch.edge_creation_batch,
+                        &mut batch.edge_range_creation_batch,
+                        &mut batch.edge_range_rev_creation_batch,
+                        &mut batch.edge_type_creation_batch,
+                        &mut batch.edge_creation_set,
+                    )?;
+                }
+                BulkInsertItem::VertexProperty(id, p, v) => {
+                    vertex_property_manager.set_batch(
+                        *id,
+                        &mut batch.vertex_property_creation_batch,
+                        &mut batch.vertex_property_value_creation_batch,
+                        &mut batch.vertex_property_creation_set,
+                        *p,
+                        v,
+                    )?;
+                }
+                BulkInsertItem::EdgeProperty(e, p, v) => {
+                    edge_property_manager.set_batch(
+                        e,
+                        &mut batch.edge_property_creation_batch,
+                        &mut batch.edge_property_value_creation_batch,
+                        &mut batch.edge_property_creation_set,
+                        *p,
+                        v,
+                    )?;
+                }
+            }
+        }
+        Ok(batch)
+    }
+
+    /// Queues the removal of an edge and its properties alongside the staged
+    /// writes, so that both land with the same batch per tree. The removed
+    /// keys include the edge's type, so they can't clash with a staged edge
+    /// of another type.
+    fn stage_edge_removal(&mut self, holder: &SledHolder, edge: &Edge) -> indradb::Result<()> {
+        EdgeManager::new(holder).delete_batch(
+            edge,
+            &mut self.edge_creation_batch,
+            &mut self.edge_range_creation_batch,
+            &mut self.edge_range_rev_creation_batch,
+            &mut self.edge_type_creation_batch,
+            &mut self.edge_removal_set,
         )?;
+        holder.edge_property_manager().delete_for_owner_batch(
+            edge,
+            &mut self.edge_property_creation_batch,
+            &mut self.edge_property_value_creation_batch,
+        )
+    }
+
+    fn apply(self, holder: &SledHolder) -> indradb::Result<()> {
+        Self::apply_in_order(vec![self], holder)
+    }
 
-        for (_, key) in self.edge_property_creation_set {
-            self.edge_property_value_creation_batch.insert(key, IVec::default());
+    /// Applies batches staged from consecutive partitions of the same items,
+    /// in partition order, so that later items win just as they would within
+    /// a single batch. In particular, only the last value set for a property
+    /// gets a value-index entry.
+    fn apply_in_order(partitions: Vec<Self>, holder: &SledHolder) -> indradb::Result<()> {
+        let mut vertex_creation_set = HashMap::new();
+        let mut edge_creation_set = HashSet::new();
+        let mut edge_removal_set = HashSet::new();
+        let mut vertex_property_creation_set = HashMap::new();
+        let mut edge_property_creation_set = HashMap::new();
+        let mut value_batches = Vec::with_capacity(partitions.len());
+        for partition in partitions {
+            map_err(timed!(
+                ApplyBatch,
+                holder.vertices.apply_batch(partition.vertex_creation_batch)
+            ))?;
+            map_err(timed!(
+                ApplyBatch,
+                holder.edges.apply_batch(partition.edge_creation_batch)
+            ))?;
+            map_err(timed!(
+                ApplyBatch,
+                holder.edge_ranges.apply_batch(partition.edge_range_creation_batch)
+            ))?;
+            map_err(timed!(
+                ApplyBatch,
+                holder
+                    .reversed_edge_ranges
+                    .apply_batch(partition.edge_range_rev_creation_batch)
+            ))?;
+            map_err(timed!(
+                ApplyBatch,
+                holder.edge_types.apply_batch(partition.edge_type_creation_batch)
+            ))?;
+            map_err(timed!(
+                ApplyBatch,
+                holder
+                    .edge_properties
+                    .apply_batch(partition.edge_property_creation_batch)
+            ))?;
+            map_err(timed!(
+                ApplyBatch,
+                holder
+                    .vertex_properties
+                    .apply_batch(partition.vertex_property_creation_batch)
+            ))?;
+            vertex_creation_set.extend(partition.vertex_creation_set);
+            edge_creation_set.extend(partition.edge_creation_set);
+            edge_removal_set.extend(partition.edge_removal_set);
+            vertex_property_creation_set.extend(partition.vertex_property_creation_set);
+            edge_property_creation_set.extend(partition.edge_property_creation_set);
+            value_batches.push((
+                partition.vertex_property_value_creation_batch,
+                partition.edge_property_value_creation_batch,
+            ));
         }
-        for (_, key) in self.vertex_property_creation_set {
-            self.vertex_property_value_creation_batch.insert(key, IVec::default());
+
+        VertexManager::new(holder)
+            .update_counts_for_changes(vertex_creation_set.into_values().map(|(before, t)| (before, Some(t))))?;
+
+        let mut type_counts: HashMap<Identifier, i64> = HashMap::new();
+        for edge in edge_creation_set {
+            *type_counts.entry(edge.t).or_insert(0) += 1;
         }
-        map_err(
-            holder
-                .vertex_property_values
-                .apply_batch(self.vertex_property_value_creation_batch),
-        )?;
-        map_err(
-            holder
-                .edge_property_values
-                .apply_batch(self.edge_property_value_creation_batch),
+        for edge in edge_removal_set {
+            *type_counts.entry(edge.t).or_insert(0) -= 1;
+        }
+        let edge_manager = EdgeManager::new(holder);
+        for (t, count) in type_counts {
+            edge_manager.update_count_for_type(t, count)?;
+        }
+
+        // The value-index entries go in after every partition's removals of
+        // the entries for previous values
+        if let Some((vertex_values, edge_values)) = value_batches.last_mut() {
+            for (_, key) in edge_property_creation_set {
+                edge_values.insert(key, IVec::default());
+            }
+            for (_, key) in vertex_property_creation_set {
+                vertex_values.insert(key, IVec::default());
+            }
+        }
+        for (vertex_values, edge_values) in value_batches {
+            map_err(timed!(
+                ApplyBatch,
+                holder.vertex_property_values.apply_batch(vertex_values)
+            ))?;
+            map_err(timed!(ApplyBatch, holder.edge_property_values.apply_batch(edge_values)))?;
+        }
+        Ok(())
+    }
+}
+
+/// Removals collected across trees, so that a cascading delete is applied
+/// with one batch per tree. The deletion counterpart of `IndraSledBatch`.
+#[derive(Default)]
+pub(crate) struct IndraSledDeleteBatch {
+    pub(crate) vertex_deletion_batch: Batch,
+    pub(crate) vertex_deletion_set: HashMap<Uuid, Identifier>,
+    pub(crate) edge_deletion_batch: Batch,
+    pub(crate) edge_deletion_set: HashSet<Edge>,
+    pub(crate) edge_range_deletion_batch: Batch,
+    pub(crate) edge_range_rev_deletion_batch: Batch,
+    pub(crate) edge_type_deletion_batch: Batch,
+    pub(crate) vertex_property_deletion_batch: Batch,
+    pub(crate) vertex_property_value_deletion_batch: Batch,
+    pub(crate) edge_property_deletion_batch: Batch,
+    pub(crate) edge_property_value_deletion_batch: Batch,
+}
+
+impl IndraSledDeleteBatch {
+    /// Queues the removal of a vertex, its properties and its outbound edges.
+    fn delete_vertex(&mut self, txn: &SledTransaction, id: Uuid) -> indradb::Result<()> {
+        txn.vertex_manager.collect_delete_keys(
+            id,
+            &txn.vertex_property_manager,
+            &mut self.vertex_deletion_batch,
+            &mut self.vertex_deletion_set,
+            &mut self.vertex_property_deletion_batch,
+            &mut self.vertex_property_value_deletion_batch,
         )?;
+        for item in txn.edge_range_manager.iterate_for_owner(id) {
+            txn.edge_manager.collect_delete_keys(&item?, self)?;
+        }
+        Ok(())
+    }
+
+    fn apply(self, holder: &SledHolder) -> indradb::Result<()> {
+        let batches = [
+            (&holder.vertices, self.vertex_deletion_batch),
+            (&holder.vertex_properties, self.vertex_property_deletion_batch),
+            (
+                &holder.vertex_property_values,
+                self.vertex_property_value_deletion_batch,
+            ),
+            (&holder.edges, self.edge_deletion_batch),
+            (&holder.edge_ranges, self.edge_range_deletion_batch),
+            (&holder.reversed_edge_ranges, self.edge_range_rev_deletion_batch),
+            (&holder.edge_types, self.edge_type_deletion_batch),
+            (&holder.edge_properties, self.edge_property_deletion_batch),
+            (&holder.edge_property_values, self.edge_property_value_deletion_batch),
+        ];
+        for (tree, batch) in batches {
+            map_err(timed!(ApplyBatch, tree.apply_batch(batch)))?;
+        }
+        VertexManager::new(holder)
+            .update_counts_for_changes(self.vertex_deletion_set.into_values().map(|t| (Some(t), None)))?;
+        let mut type_counts: HashMap<Identifier, i64> = HashMap::new();
+        for edge in self.edge_deletion_set {
+            *type_counts.entry(edge.t).or_insert(0) -= 1;
+        }
+        let edge_manager = EdgeManager::new(holder);
+        for (t, count) in type_counts {
+            edge_manager.update_count_for_type(t, count)?;
+        }
         Ok(())
     }
 }
@@ -68,7 +333,7 @@ impl IndraSledBatch {
 /// A transaction that is backed by Sled.
 pub struct SledTransaction<'a> {
     pub(crate) holder: &'a SledHolder,
-    pub(crate) vertex_manager: VertexManager<'a, 'a>,
+    pub(crate) vertex_manager: VertexManager<'a>,
     pub(crate) edge_manager: EdgeManager<'a, 'a>,
     pub(crate) edge_property_manager: EdgePropertyManager<'a>,
     pub(crate) vertex_property_manager: VertexPropertyManager<'a>,
@@ -110,7 +375,7 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
     }
 
     fn vertex_ids_with_property(&'a self, name: Identifier) -> indradb::Result<Option<DynIter<'a, Uuid>>> {
-        if !self.meta_data_manager.is_indexed(&name)? {
+        if !self.is_indexed(&name)? {
             return Ok(None);
         }
         let iter = self.vertex_property_manager.iterate_for_property_name(name)?;
@@ -122,7 +387,7 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
         name: Identifier,
         value: &Json,
     ) -> indradb::Result<Option<DynIter<'a, Uuid>>> {
-        if !self.meta_data_manager.is_indexed(&name)? {
+        if !self.is_indexed(&name)? {
             return Ok(None);
         }
         let iter = self
@@ -149,29 +414,31 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
     }
 
     fn range_reversed_edges(&'a self, offset: Edge) -> indradb::Result<DynIter<'a, Edge>> {
+        self.check_reversed_edges()?;
         let iter = self.edge_range_manager_rev.iterate_for_range(&offset);
 
         Ok(Box::new(iter))
     }
 
     fn specific_edges(&'a self, edges: Vec<Edge>) -> indradb::Result<DynIter<'a, Edge>> {
-        let iter: Vec<_> = edges
-            .into_iter()
-            .filter(|e| {
-                let r = self.edge_range_manager.contains(e);
-                if let Ok(r) = r {
-                    r
-                } else {
-                    false
+        let mut found = Vec::with_capacity(edges.len());
+        for edge in edges {
+            match self.edge_range_manager.contains(&edge) {
+                Ok(true) => found.push(Ok(edge)),
+                Ok(false) => {}
+                Err(err) => {
+                    // A failed lookup ends the results rather than passing
+                    // the edge off as missing
+                    found.push(Err(err));
+                    break;
                 }
-            })
-            .map(Ok)
-            .collect();
-        Ok(Box::new(iter.into_iter()))
+            }
+        }
+        Ok(Box::new(found.into_iter()))
     }
 
     fn edges_with_property(&'a self, name: Identifier) -> indradb::Result<Option<DynIter<'a, Edge>>> {
-        if !self.meta_data_manager.is_indexed(&name)? {
+        if !self.is_indexed(&name)? {
             return Ok(None);
         }
         let iter = self.edge_property_manager.iterate_for_property_name(name)?;
@@ -183,7 +450,7 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
         name: Identifier,
         value: &Json,
     ) -> indradb::Result<Option<DynIter<'a, Edge>>> {
-        if !self.meta_data_manager.is_indexed(&name)? {
+        if !self.is_indexed(&name)? {
             return Ok(None);
         }
         let iter = self
@@ -215,13 +482,16 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
     }
 
     fn delete_vertices(&mut self, vertices: Vec<Vertex>) -> indradb::Result<()> {
+        self.check_writable()?;
+        let mut batch = IndraSledDeleteBatch::default();
         for v in vertices {
-            self.vertex_manager.delete(v.id)?
+            batch.delete_vertex(self, v.id)?;
         }
-        Ok(())
+        batch.apply(self.holder)
     }
 
     fn delete_edges(&mut self, edges: Vec<Edge>) -> indradb::Result<()> {
+        self.check_writable()?;
         for item in edges.iter() {
             if self.vertex_manager.get(item.outbound_id)?.is_some() {
                 self.edge_manager.delete(item)?;
@@ -232,6 +502,7 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
     }
 
     fn delete_vertex_properties(&mut self, props: Vec<(Uuid, Identifier)>) -> indradb::Result<()> {
+        self.check_writable()?;
         for (id, prop) in props {
             self.vertex_property_manager.delete(id, prop)?
         }
@@ -239,6 +510,7 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
     }
 
     fn delete_edge_properties(&mut self, props: Vec<(Edge, Identifier)>) -> indradb::Result<()> {
+        self.check_writable()?;
         for (edge, prop) in props {
             self.edge_property_manager.delete(&edge, prop)?;
         }
@@ -247,86 +519,2920 @@ impl<'a> Transaction<'a> for SledTransaction<'a> {
 
     fn sync(&self) -> indradb::Result<()> {
         self.meta_data_manager.sync()?;
-        let _ = map_err(self.holder.db.flush())?;
+        let _ = map_err(timed!(Flush, self.holder.db.flush()))?;
         Ok(())
     }
 
     fn create_vertex(&mut self, vertex: &Vertex) -> indradb::Result<bool> {
+        self.check_writable()?;
         self.vertex_manager.create(vertex)
     }
 
     fn create_edge(&mut self, edge: &Edge) -> indradb::Result<bool> {
-        let outbound_exists = self.vertex_manager.exists(edge.outbound_id)?;
-        let inbound_exists = self.vertex_manager.exists(edge.inbound_id)?;
-
-        if !outbound_exists || !inbound_exists {
-            Ok(false)
-        } else {
-            self.edge_manager.set(edge)?;
-            Ok(true)
-        }
+        let outcome = self.create_edge_checked(edge)?;
+        Ok(matches!(
+            outcome,
+            CreateEdgeOutcome::Created | CreateEdgeOutcome::Overwritten
+        ))
     }
 
     fn bulk_insert(&mut self, items: Vec<BulkInsertItem>) -> indradb::Result<()> {
-        let mut batch = IndraSledBatch::default();
-
-        for item in items {
-            match item {
-                BulkInsertItem::Vertex(v) => {
-                    self.vertex_manager.create_batch(&v, &mut batch.vertex_creation_batch)?;
-                }
-                BulkInsertItem::Edge(e) => {
-                    self.edge_manager.set_batch(
-                        &e,
-                        &mut batch.edge_creation_batch,
-                        &mut batch.edge_range_creation_batch,
-                        &mut batch.edge_range_rev_creation_batch,
-                    )?;
-                }
-                BulkInsertItem::VertexProperty(id, p, v) => {
-                    self.vertex_property_manager.set_batch(
-                        id,
-                        &mut batch.vertex_property_creation_batch,
-                        &mut batch.vertex_property_value_creation_batch,
-                        &mut batch.vertex_property_creation_set,
-                        p,
-                        &v,
-                    )?;
-                }
-                BulkInsertItem::EdgeProperty(e, p, v) => {
-                    self.edge_property_manager.set_batch(
-                        &e,
-                        &mut batch.edge_property_creation_batch,
-                        &mut batch.edge_property_value_creation_batch,
-                        &mut batch.edge_property_creation_set,
-                        p,
-                        &v,
-                    )?;
-                }
-            }
-        }
-        batch.apply(self.holder)?;
+        self.check_writable()?;
+        #[cfg(feature = "rayon")]
+        self.bulk_insert_parallel(&items)?;
+        #[cfg(not(feature = "rayon"))]
+        IndraSledBatch::stage(self.holder, &items)?.apply(self.holder)?;
 
         self.sync()?;
         Ok(())
     }
 
     fn index_property(&mut self, name: Identifier) -> indradb::Result<()> {
-        self.meta_data_manager.add_index(&name)?;
-        Ok(())
+        self.index_properties_with_backfill(vec![name])
     }
 
     fn set_vertex_properties(&mut self, vertices: Vec<Uuid>, name: Identifier, value: &Json) -> indradb::Result<()> {
+        self.check_writable()?;
+        let mut batch = IndraSledBatch::default();
         for v in vertices {
-            self.vertex_property_manager.set(v, name, value)?;
+            self.vertex_property_manager.set_batch(
+                v,
+                &mut batch.vertex_property_creation_batch,
+                &mut batch.vertex_property_value_creation_batch,
+                &mut batch.vertex_property_creation_set,
+                name,
+                value,
+            )?;
         }
-        Ok(())
+        batch.apply(self.holder)
     }
 
     fn set_edge_properties(&mut self, edges: Vec<Edge>, name: Identifier, value: &Json) -> indradb::Result<()> {
+        self.check_writable()?;
         for edge in edges {
             self.edge_property_manager.set(&edge, name, value)?;
         }
         Ok(())
     }
 }
+
+impl<'a> SledTransaction<'a> {
+    /// Like `bulk_insert`, but also reports whether each item was new:
+    /// `true` if the vertex, edge or property didn't exist before and didn't
+    /// appear earlier in `items`. Re-importing the same batch therefore
+    /// yields all `false`.
+    ///
+    /// # Arguments
+    /// * `items`: The items to insert.
+    pub fn bulk_insert_checked(&mut self, items: Vec<BulkInsertItem>) -> indradb::Result<Vec<bool>> {
+        self.check_writable()?;
+        let mut vertices = HashSet::new();
+        let mut edges = HashSet::new();
+        let mut vertex_properties = HashSet::new();
+        let mut edge_properties = HashSet::new();
+        let mut created = Vec::with_capacity(items.len());
+        for item in &items {
+            let is_new = match item {
+                BulkInsertItem::Vertex(v) => !self.vertex_manager.exists(v.id)? && vertices.insert(v.id),
+                BulkInsertItem::Edge(e) => !self.edge_range_manager.contains(e)? && edges.insert(e.clone()),
+                BulkInsertItem::VertexProperty(id, name, _) => {
+                    self.vertex_property_manager.get_bytes(*id, *name)?.is_none()
+                        && vertex_properties.insert((*id, *name))
+                }
+                BulkInsertItem::EdgeProperty(e, name, _) => {
+                    self.edge_property_manager.get_bytes(e, *name)?.is_none()
+                        && edge_properties.insert((e.clone(), *name))
+                }
+            };
+            created.push(is_new);
+        }
+        self.bulk_insert(items)?;
+        Ok(created)
+    }
+
+    /// Like `sync`, but waits for sled's flush on a background thread pool
+    /// instead of blocking. Writes are only durable once the returned future
+    /// has completed successfully.
+    #[cfg(feature = "async")]
+    pub async fn sync_async(&self) -> indradb::Result<()> {
+        self.meta_data_manager.sync()?;
+        map_err(self.holder.db.flush_async().await)?;
+        Ok(())
+    }
+
+    /// Asks the datastore's flush thread to flush and returns immediately.
+    /// Requests made while a flush is already pending are folded into it.
+    /// There is no way to learn when, or whether, the flush finished, so this
+    /// gives no durability guarantee: writes can still be lost on a crash.
+    /// Use `sync` or `sync_async` when that matters.
+    pub fn request_flush(&self) -> indradb::Result<()> {
+        self.meta_data_manager.sync()?;
+        self.holder.request_flush();
+        Ok(())
+    }
+
+    /// Stages the writes for partitions of `items` on the rayon thread pool,
+    /// then applies them in order. Used by `bulk_insert` when the `rayon`
+    /// feature is enabled.
+    #[cfg(feature = "rayon")]
+    fn bulk_insert_parallel(&self, items: &[BulkInsertItem]) -> indradb::Result<()> {
+        use rayon::prelude::*;
+
+        let partition_size = items
+            .len()
+            .div_ceil(rayon::current_num_threads())
+            .max(MIN_BULK_INSERT_PARTITION);
+        let holder = self.holder;
+        let partitions = items
+            .par_chunks(partition_size)
+            .map(|partition| IndraSledBatch::stage(holder, partition))
+            .collect::<indradb::Result<Vec<_>>>()?;
+        IndraSledBatch::apply_in_order(partitions, holder)
+    }
+
+    pub(crate) fn check_writable(&self) -> indradb::Result<()> {
+        if self.holder.is_read_only {
+            return Err(DSError::ReadOnly.into());
+        }
+        Ok(())
+    }
+
+    /// Fails queries over inbound edges when the reversed edge ranges aren't
+    /// maintained, rather than letting them find nothing.
+    fn check_reversed_edges(&self) -> indradb::Result<()> {
+        if !self.holder.reversed_edges {
+            return Err(DSError::Unsupported(
+                "the reversed edge ranges are disabled by SledConfig::maintain_reversed_edges".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Fails indexing a property when the value indexes aren't maintained,
+    /// since the index would never be filled.
+    fn check_property_value_index(&self) -> indradb::Result<()> {
+        if !self.holder.property_value_index {
+            return Err(DSError::Unsupported(
+                "the property value indexes are disabled by SledConfig::maintain_property_value_index".to_string(),
+            )
+            .into());
+        }
+        Ok(())
+    }
+
+    /// Whether queries by the property `name` can use the value index. A
+    /// property marked as indexed doesn't count while the value indexes
+    /// aren't maintained.
+    fn is_indexed(&self, name: &Identifier) -> indradb::Result<bool> {
+        Ok(self.holder.property_value_index && self.meta_data_manager.is_indexed(name)?)
+    }
+
+    /// Marks all the given properties as indexed and backfills their value
+    /// indexes with a single scan over each property tree.
+    ///
+    /// # Arguments
+    /// * `names`: The property names to index.
+    pub fn index_properties_with_backfill(&mut self, names: Vec<Identifier>) -> indradb::Result<()> {
+        self.check_writable()?;
+        self.check_property_value_index()?;
+        for name in names.iter() {
+            self.meta_data_manager.add_index(name)?;
+        }
+        let names: HashSet<Identifier> = names.into_iter().collect();
+        self.vertex_property_manager.backfill_value_index(&names)?;
+        self.edge_property_manager.backfill_value_index(&names)?;
+        Ok(())
+    }
+
+    /// Cross-checks the property trees against their value indexes for every
+    /// indexed property, reporting entries that exist on only one side.
+    pub fn verify_indexes(&self) -> indradb::Result<Vec<IndexInconsistency>> {
+        let names = self.meta_data_manager.indexed_properties()?;
+        let mut inconsistencies = self.vertex_property_manager.verify_value_index(&names)?;
+        inconsistencies.extend(self.edge_property_manager.verify_value_index(&names)?);
+        Ok(inconsistencies)
+    }
+
+    /// Cross-checks the edge trees against each other and the vertex tree,
+    /// and the property trees against their owners and value indexes. These
+    /// can drift apart if the process crashes between the writes of a single
+    /// operation. Every entry is visited, so this takes time linear in the
+    /// size of the datastore.
+    pub fn validate_integrity(&self) -> indradb::Result<IntegrityReport> {
+        let mut report = IntegrityReport::default();
+        for item in self.edge_manager.iterate_for_all() {
+            let edge = item?;
+            if !self.edge_range_manager.contains(&edge)? {
+                report.record(IntegrityViolation::MissingEdgeRange { edge });
+            }
+        }
+        for item in self.edge_range_manager.iterate_for_all() {
+            let edge = item?;
+            if !map_err(timed!(
+                Get,
+                self.holder.edges.contains_key(self.edge_manager.key(edge.clone()))
+            ))? {
+                report.record(IntegrityViolation::MissingEdge { edge: edge.clone() });
+            }
+            if self.holder.reversed_edges && !self.edge_range_manager_rev.contains(&reverse_edge(&edge))? {
+                report.record(IntegrityViolation::MissingReversedEdgeRange { edge: edge.clone() });
+            }
+            if !self.vertex_manager.exists(edge.outbound_id)? {
+                report.record(IntegrityViolation::MissingOutboundVertex { edge: edge.clone() });
+            }
+            if !self.vertex_manager.exists(edge.inbound_id)? {
+                report.record(IntegrityViolation::MissingInboundVertex { edge });
+            }
+        }
+        // The reversed edge ranges are empty when they aren't maintained
+        for item in self.edge_range_manager_rev.iterate_for_all() {
+            let edge = reverse_edge(&item?);
+            if !self.edge_range_manager.contains(&edge)? {
+                report.record(IntegrityViolation::OrphanedReversedEdgeRange { edge });
+            }
+        }
+        self.vertex_property_manager
+            .check_integrity(|id| self.vertex_manager.exists(id), &mut report)?;
+        self.edge_property_manager
+            .check_integrity(|edge| self.edge_range_manager.contains(edge), &mut report)?;
+        Ok(report)
+    }
+
+    /// Like `delete_edges`, but collects the removals from every edge tree
+    /// and the edge properties into one batch per tree, which is much faster
+    /// for many edges.
+    ///
+    /// # Arguments
+    /// * `edges`: The edges to delete.
+    pub fn delete_edges_batch(&mut self, edges: Vec<Edge>) -> indradb::Result<()> {
+        self.check_writable()?;
+        let mut batch = IndraSledDeleteBatch::default();
+        for edge in edges.iter() {
+            if self.vertex_manager.exists(edge.outbound_id)? {
+                self.edge_manager.collect_delete_keys(edge, &mut batch)?;
+            }
+        }
+        batch.apply(self.holder)
+    }
+
+    /// Removes all vertices, edges, properties and indexes by clearing every
+    /// tree, which is much faster than deleting the vertices. The metadata
+    /// is cleared too, so the per-type counters start over and property
+    /// indexes have to be recreated. Like `SledDatastore::clear`, this
+    /// flushes.
+    pub fn clear(&mut self) -> indradb::Result<()> {
+        self.check_writable()?;
+        self.holder.clear()?;
+        self.meta_data_manager.forget();
+        Ok(())
+    }
+
+    /// Copies every property of one vertex onto another, overwriting the
+    /// properties of the same name that it already has, and keeping the
+    /// rest. The source vertex is left as it is. Returns how many properties
+    /// were copied, which is none if `dst` doesn't exist.
+    ///
+    /// # Arguments
+    /// * `src`: The id of the vertex to copy the properties from.
+    /// * `dst`: The id of the vertex to copy the properties to.
+    pub fn copy_vertex_properties(&mut self, src: Uuid, dst: Uuid) -> indradb::Result<u32> {
+        self.check_writable()?;
+        if !self.vertex_manager.exists(dst)? {
+            return Ok(0);
+        }
+        let properties = self
+            .vertex_property_manager
+            .iterate_for_owner(src)?
+            .collect::<indradb::Result<Vec<_>>>()?;
+        for ((_, name), value) in properties.iter() {
+            self.vertex_property_manager.set(dst, *name, value)?;
+        }
+        Ok(properties.len() as u32)
+    }
+
+    /// Same as `create_vertex`, for callers that create a vertex if it's
+    /// absent and carry on otherwise. Returns whether the vertex was created.
+    /// An existing vertex is left as it is, even if its type differs; use
+    /// `fetch_or_create_vertex` to get the stored vertex.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create.
+    pub fn upsert_vertex(&mut self, vertex: &Vertex) -> indradb::Result<bool> {
+        self.create_vertex(vertex)
+    }
+
+    /// Creates a vertex unless one with the same id exists, atomically, so
+    /// concurrent callers can't both create it. Returns whether the vertex
+    /// was created, along with the type stored under its id, which is the
+    /// existing vertex's type if it wasn't.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create.
+    pub fn get_or_create_vertex(&mut self, vertex: &Vertex) -> indradb::Result<(bool, Identifier)> {
+        self.check_writable()?;
+        self.vertex_manager.get_or_create(vertex)
+    }
+
+    /// Like `get_or_create_vertex`, but returns the vertex stored under the
+    /// id of `vertex`: `vertex` itself if it was created, otherwise the
+    /// existing vertex, whose type may differ.
+    ///
+    /// # Arguments
+    /// * `vertex`: The vertex to create.
+    pub fn fetch_or_create_vertex(&mut self, vertex: &Vertex) -> indradb::Result<Vertex> {
+        let (_, t) = self.get_or_create_vertex(vertex)?;
+        Ok(Vertex::with_id(vertex.id, t))
+    }
+
+    /// Changes the type of an existing vertex. Its id, edges and properties
+    /// are kept. Returns `false` if the vertex doesn't exist.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `new_type`: The type to give the vertex.
+    pub fn update_vertex_type(&mut self, id: Uuid, new_type: Identifier) -> indradb::Result<bool> {
+        self.check_writable()?;
+        self.vertex_manager.set_type(id, new_type)
+    }
+
+    /// Changes the type of an existing edge. The type is part of the key in
+    /// every edge tree and in the edge's property keys, so the edge is
+    /// written under its new type with its properties, and the old edge is
+    /// removed, all in one batch per tree. An edge that already exists under
+    /// the new type is merged into, with the old edge's properties taking
+    /// precedence. Returns `false` if the edge doesn't exist.
+    ///
+    /// # Arguments
+    /// * `old_edge`: The edge to change.
+    /// * `new_type`: The type to give the edge.
+    pub fn update_edge_type(&mut self, old_edge: &Edge, new_type: Identifier) -> indradb::Result<bool> {
+        self.check_writable()?;
+        if !self.edge_range_manager.contains(old_edge)? {
+            return Ok(false);
+        }
+        if old_edge.t == new_type {
+            return Ok(true);
+        }
+        let new_edge = Edge::new(old_edge.outbound_id, new_type, old_edge.inbound_id);
+        let mut items = vec![BulkInsertItem::Edge(new_edge.clone())];
+        for item in self.edge_property_manager.iterate_for_owner(old_edge)? {
+            let ((_, name), value) = item?;
+            items.push(BulkInsertItem::EdgeProperty(new_edge.clone(), name, Json::new(value)));
+        }
+        let mut batch = IndraSledBatch::stage(self.holder, &items)?;
+        batch.stage_edge_removal(self.holder, old_edge)?;
+        batch.apply(self.holder)?;
+        Ok(true)
+    }
+
+    /// Fixes what `validate_integrity` reports, taking the edges, vertices and
+    /// property trees as authoritative: the edge ranges are rebuilt from the
+    /// edges, properties of missing vertices and edges are removed, and the
+    /// value indexes are made to match the remaining properties. Edges whose
+    /// vertices are missing are left alone.
+    pub fn repair_integrity(&mut self) -> indradb::Result<RepairStats> {
+        self.check_writable()?;
+        let mut stats = RepairStats::default();
+        stats.record(
+            "edge_ranges",
+            self.edge_range_manager.rebuild(&self.edge_manager, false)?,
+        );
+        if self.holder.reversed_edges {
+            stats.record(
+                "reversed_edge_ranges",
+                self.edge_range_manager_rev.rebuild(&self.edge_manager, true)?,
+            );
+        }
+        let (properties, values) = self
+            .vertex_property_manager
+            .repair(|id| self.vertex_manager.exists(id))?;
+        stats.record("vertex_properties", properties);
+        stats.record("vertex_property_values", values);
+        let (properties, values) = self.edge_property_manager.repair(|edge| {
+            let key = self.edge_manager.key(edge.clone());
+            map_err(timed!(Get, self.holder.edges.contains_key(key)))
+        })?;
+        stats.record("edge_properties", properties);
+        stats.record("edge_property_values", values);
+        Ok(stats)
+    }
+
+    /// Whether a vertex with the given id exists.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn vertex_exists(&self, id: Uuid) -> indradb::Result<bool> {
+        self.vertex_manager.exists(id)
+    }
+
+    /// Whether the given edge exists. This is a single key lookup in the
+    /// edge range tree.
+    ///
+    /// # Arguments
+    /// * `edge`: The edge to look up.
+    pub fn edge_exists(&self, edge: &Edge) -> indradb::Result<bool> {
+        self.edge_range_manager.contains(edge)
+    }
+
+    /// Like `create_edge`, but reports why an edge wasn't written, and
+    /// whether an edge that was written already existed. The outbound vertex
+    /// is checked first, so an edge missing both vertices is reported as
+    /// `MissingOutbound`.
+    ///
+    /// # Arguments
+    /// * `edge`: The edge to create.
+    pub fn create_edge_checked(&mut self, edge: &Edge) -> indradb::Result<CreateEdgeOutcome> {
+        self.check_writable()?;
+        if !self.vertex_manager.exists(edge.outbound_id)? {
+            return Ok(CreateEdgeOutcome::MissingOutbound);
+        }
+        if !self.vertex_manager.exists(edge.inbound_id)? {
+            return Ok(CreateEdgeOutcome::MissingInbound);
+        }
+        if self.edge_manager.set(edge)? {
+            Ok(CreateEdgeOutcome::Created)
+        } else {
+            Ok(CreateEdgeOutcome::Overwritten)
+        }
+    }
+
+    /// Gets all vertices of the given type.
+    ///
+    /// There is no secondary index on vertex types, so this filters a
+    /// sequential scan over all vertices. Adding a type index is a follow-on.
+    ///
+    /// # Arguments
+    /// * `t`: The vertex type to filter by.
+    pub fn vertices_with_type(&'a self, t: Identifier) -> indradb::Result<DynIter<'a, Vertex>> {
+        let iter = self
+            .vertex_manager
+            .iterate_for_range(Uuid::default())
+            .filter(move |item| match item {
+                Ok((_, vertex_t)) => *vertex_t == t,
+                Err(_) => true,
+            })
+            .map(|item| item.map(|(id, t)| Vertex::with_id(id, t)));
+        Ok(Box::new(iter))
+    }
+
+    /// Gets the given vertices along with all of their properties, so that
+    /// callers don't need a separate `all_vertex_properties_for_vertex` call
+    /// per vertex. Ids of vertices that don't exist are skipped, as with
+    /// `specific_vertices`.
+    ///
+    /// # Arguments
+    /// * `ids`: The ids of the vertices to get.
+    pub fn vertices_with_all_properties(
+        &'a self,
+        ids: Vec<Uuid>,
+    ) -> indradb::Result<DynIter<'a, VertexWithProperties>> {
+        let iter = ids
+            .into_iter()
+            .filter_map(move |id| self.vertex_with_all_properties(id).transpose());
+        Ok(Box::new(iter))
+    }
+
+    fn vertex_with_all_properties(&self, id: Uuid) -> indradb::Result<Option<VertexWithProperties>> {
+        let Some(t) = self.vertex_manager.get(id)? else {
+            return Ok(None);
+        };
+        let properties = self
+            .vertex_property_manager
+            .iterate_for_owner(id)?
+            .map(|item| item.map(|((_, name), value)| (name, Json::new(value))))
+            .collect::<indradb::Result<Vec<_>>>()?;
+        Ok(Some((Vertex::with_id(id, t), properties)))
+    }
+
+    /// Deletes every vertex of the given type through the batched
+    /// `delete_vertices` path, returning how many were deleted. Like
+    /// `vertices_with_type`, this scans all vertices.
+    ///
+    /// # Arguments
+    /// * `t`: The type of the vertices to delete.
+    pub fn delete_vertices_by_type(&mut self, t: Identifier) -> indradb::Result<u64> {
+        self.check_writable()?;
+        let mut vertices = Vec::new();
+        for item in self.vertex_manager.iterate_for_range(Uuid::default()) {
+            let (id, vertex_t) = item?;
+            if vertex_t == t {
+                vertices.push(Vertex::with_id(id, vertex_t));
+            }
+        }
+        let count = vertices.len() as u64;
+        if count > 0 {
+            self.delete_vertices(vertices)?;
+        }
+        Ok(count)
+    }
+
+    /// Deletes every edge of the given type through `delete_edges_batch`,
+    /// returning how many were deleted. The edges are found with a prefix
+    /// scan over the edge type index rather than a scan of all edges.
+    ///
+    /// # Arguments
+    /// * `t`: The type of the edges to delete.
+    pub fn delete_edges_by_type(&mut self, t: Identifier) -> indradb::Result<u64> {
+        self.check_writable()?;
+        let edges = EdgeTypeManager::new(self.holder)
+            .iterate_for_type(t)
+            .collect::<indradb::Result<Vec<Edge>>>()?;
+        let count = edges.len() as u64;
+        if count > 0 {
+            self.delete_edges_batch(edges)?;
+        }
+        Ok(count)
+    }
+
+    /// Gets the vertex types in use, sorted and without duplicates. They are
+    /// read from the per-type vertex counters in the metadata tree, so this
+    /// takes time proportional to the number of types rather than vertices.
+    /// A type is listed until its last vertex is deleted or given another
+    /// type. The counters can drift from the vertices after a crash.
+    pub fn distinct_vertex_types(&self) -> indradb::Result<Vec<Identifier>> {
+        let mut types: Vec<Identifier> = self
+            .vertex_manager
+            .type_counts()?
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(t, _)| t)
+            .collect();
+        types.sort();
+        Ok(types)
+    }
+
+    /// Another name for `distinct_vertex_types`.
+    pub fn vertex_types(&self) -> indradb::Result<Vec<Identifier>> {
+        self.distinct_vertex_types()
+    }
+
+    /// Gets the edge types in use, sorted and without duplicates, from the
+    /// per-type edge counters in the metadata tree. Like
+    /// `distinct_vertex_types`, this takes time proportional to the number
+    /// of types rather than edges, but the counters can drift from the edges
+    /// after a crash.
+    pub fn distinct_edge_types(&self) -> indradb::Result<Vec<Identifier>> {
+        let mut types: Vec<Identifier> = self
+            .edge_manager
+            .type_counts()?
+            .into_iter()
+            .filter(|(_, count)| *count > 0)
+            .map(|(t, _)| t)
+            .collect();
+        types.sort();
+        Ok(types)
+    }
+
+    /// Another name for `distinct_edge_types`.
+    pub fn edge_types(&self) -> indradb::Result<Vec<Identifier>> {
+        self.distinct_edge_types()
+    }
+
+    /// Gets the number of edges of the given type, from counters maintained
+    /// in the metadata tree.
+    ///
+    /// # Arguments
+    /// * `t`: The edge type to count.
+    pub fn edge_count_by_type(&self, t: Identifier) -> indradb::Result<u64> {
+        self.edge_manager.count_for_type(t)
+    }
+
+    /// Picks a random outbound neighbor of a vertex, weighted by an edge
+    /// property. Edges are scanned once with weighted reservoir sampling, so
+    /// memory use doesn't depend on the vertex's degree.
+    ///
+    /// Edges without a numeric weight use a weight of 1, and edges with a
+    /// weight of zero or less are never picked. Returns `None` if there is
+    /// no edge to pick.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex to sample a neighbor of.
+    /// * `weight_property`: The edge property holding the weights.
+    /// * `rng_seed`: The seed of the random number generator.
+    pub fn sample_neighbor(
+        &self,
+        id: Uuid,
+        weight_property: Identifier,
+        rng_seed: u64,
+    ) -> indradb::Result<Option<Uuid>> {
+        let mut rng_state = rng_seed;
+        let mut total_weight = 0.0;
+        let mut picked = None;
+        for item in self.edge_range_manager.iterate_for_owner(id) {
+            let edge = item?;
+            let weight = match self.edge_property_manager.get(&edge, weight_property)? {
+                Some(value) => value.as_f64().unwrap_or(DEFAULT_NEIGHBOR_WEIGHT),
+                None => DEFAULT_NEIGHBOR_WEIGHT,
+            };
+            if weight <= 0.0 {
+                continue;
+            }
+            total_weight += weight;
+            if next_unit_f64(&mut rng_state) * total_weight < weight {
+                picked = Some(edge.inbound_id);
+            }
+        }
+        Ok(picked)
+    }
+
+    /// Gets all edges of the given type, ordered by outbound and then
+    /// inbound id. This is a prefix scan over the edge type index.
+    ///
+    /// # Arguments
+    /// * `t`: The edge type to filter by.
+    pub fn edges_with_type(&'a self, t: Identifier) -> indradb::Result<DynIter<'a, Edge>> {
+        let iter = EdgeTypeManager::new(self.holder).iterate_for_type(t);
+        Ok(Box::new(iter))
+    }
+
+    /// Gets the outbound edges of a vertex with the given type. The vertex
+    /// id and type form a prefix of the edge range keys, so only matching
+    /// edges are scanned.
+    ///
+    /// # Arguments
+    /// * `outbound_id`: The id of the vertex the edges go out of.
+    /// * `t`: The edge type to filter by.
+    pub fn outbound_edges_with_type(&'a self, outbound_id: Uuid, t: Identifier) -> indradb::Result<DynIter<'a, Edge>> {
+        let iter = self.edge_range_manager.iterate_for_type(outbound_id, t);
+        Ok(Box::new(iter))
+    }
+
+    /// Gets the number of outbound edges of a vertex, without reading the
+    /// edges themselves.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn out_degree(&self, id: Uuid) -> indradb::Result<u64> {
+        self.edge_range_manager.count_for_owner(id)
+    }
+
+    /// Gets the number of inbound edges of a vertex, using the reversed edge
+    /// ranges.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn in_degree(&self, id: Uuid) -> indradb::Result<u64> {
+        self.check_reversed_edges()?;
+        self.edge_range_manager_rev.count_for_owner(id)
+    }
+
+    /// Gets the number of outbound edges of a vertex with the given type.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `t`: The edge type to count.
+    pub fn out_degree_with_type(&self, id: Uuid, t: Identifier) -> indradb::Result<u64> {
+        self.edge_range_manager.count_for_type(id, t)
+    }
+
+    /// Gets the number of inbound edges of a vertex with the given type.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `t`: The edge type to count.
+    pub fn in_degree_with_type(&self, id: Uuid, t: Identifier) -> indradb::Result<u64> {
+        self.check_reversed_edges()?;
+        self.edge_range_manager_rev.count_for_type(id, t)
+    }
+
+    /// Same as `out_degree`.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn vertex_out_degree(&self, id: Uuid) -> indradb::Result<u64> {
+        self.out_degree(id)
+    }
+
+    /// Same as `in_degree`.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn vertex_in_degree(&self, id: Uuid) -> indradb::Result<u64> {
+        self.in_degree(id)
+    }
+
+    /// Gets the `(in_degree, out_degree)` of a vertex, see `in_degree` and
+    /// `out_degree`.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn vertex_degree(&self, id: Uuid) -> indradb::Result<(u64, u64)> {
+        Ok((self.in_degree(id)?, self.out_degree(id)?))
+    }
+
+    /// Gets the vertices connected to `id` in the given direction, along
+    /// with the type of the edge connecting them. When `t` is set, only edges
+    /// of that type are followed, using a prefix scan. Only the edge range
+    /// keys are read, so a neighbor reached through several edges is yielded
+    /// once per edge: with `NeighborDirection::Both`, a vertex connected in
+    /// both directions, such as `id` itself when it has a self-loop, is
+    /// yielded once per direction. Inbound neighbors need the reversed edge
+    /// ranges.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `direction`: Which edges of the vertex to follow.
+    /// * `t`: The edge type to follow, or `None` for all types.
+    pub fn neighbors(
+        &'a self,
+        id: Uuid,
+        direction: NeighborDirection,
+        t: Option<Identifier>,
+    ) -> indradb::Result<DynIter<'a, (Identifier, Uuid)>> {
+        if direction != NeighborDirection::Outbound {
+            self.check_reversed_edges()?;
+        }
+        let outbound = || Self::neighbor_ids(&self.edge_range_manager, id, t);
+        let inbound = || Self::neighbor_ids(&self.edge_range_manager_rev, id, t);
+        Ok(match direction {
+            NeighborDirection::Outbound => outbound(),
+            NeighborDirection::Inbound => inbound(),
+            NeighborDirection::Both => Box::new(outbound().chain(inbound())),
+        })
+    }
+
+    /// Gets the vertices that `id` has outbound edges to, along with the type
+    /// of each edge. Same as `neighbors(id, NeighborDirection::Outbound,
+    /// None)`.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn outbound_neighbors(&'a self, id: Uuid) -> indradb::Result<DynIter<'a, (Identifier, Uuid)>> {
+        self.neighbors(id, NeighborDirection::Outbound, None)
+    }
+
+    /// Gets the vertices that have outbound edges to `id`, along with the
+    /// type of each edge. Same as `neighbors(id, NeighborDirection::Inbound,
+    /// None)`.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    pub fn inbound_neighbors(&'a self, id: Uuid) -> indradb::Result<DynIter<'a, (Identifier, Uuid)>> {
+        self.neighbors(id, NeighborDirection::Inbound, None)
+    }
+
+    /// Gets the vertices that have outbound edges to `id`, without the edge
+    /// types. When `t` is set, only edges of that type are followed. Needs
+    /// the reversed edge ranges.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `t`: The edge type to follow, or `None` for all types.
+    pub fn reverse_neighbors(&'a self, id: Uuid, t: Option<Identifier>) -> indradb::Result<DynIter<'a, Uuid>> {
+        let iter = self
+            .neighbors(id, NeighborDirection::Inbound, t)?
+            .map(|item| item.map(|(_, id)| id));
+        Ok(Box::new(iter))
+    }
+
+    /// Gets the edges going from `a` to `b`, of any type. The inbound id
+    /// comes last in the edge range keys, so this filters the outbound edges
+    /// of `a`.
+    ///
+    /// # Arguments
+    /// * `a`: The id of the outbound vertex.
+    /// * `b`: The id of the inbound vertex.
+    pub fn edges_between(&'a self, a: Uuid, b: Uuid) -> indradb::Result<DynIter<'a, Edge>> {
+        let iter = self
+            .edge_range_manager
+            .iterate_for_owner(a)
+            .filter(move |item| match item {
+                Ok(edge) => edge.inbound_id == b,
+                Err(_) => true,
+            });
+        Ok(Box::new(iter))
+    }
+
+    /// Gets the vertices that both `a` and `b` have outbound edges to, each
+    /// once. If `a == b`, this is the set of outbound neighbors of `a`.
+    ///
+    /// # Arguments
+    /// * `a`: The id of the first vertex.
+    /// * `b`: The id of the second vertex.
+    pub fn common_neighbors(&'a self, a: Uuid, b: Uuid) -> indradb::Result<Vec<Uuid>> {
+        if !self.vertex_manager.exists(a)? || !self.vertex_manager.exists(b)? {
+            return Ok(Vec::new());
+        }
+        let mut remaining = self
+            .outbound_neighbors(a)?
+            .map(|item| item.map(|(_, id)| id))
+            .collect::<indradb::Result<HashSet<Uuid>>>()?;
+        let mut common = Vec::new();
+        for item in self.outbound_neighbors(b)? {
+            let (_, id) = item?;
+            if remaining.remove(&id) {
+                common.push(id);
+            }
+        }
+        Ok(common)
+    }
+
+    /// Gets the ids of vertices whose `name` property is a number between
+    /// `min` and `max`, inclusive. Returns `None` if the property isn't
+    /// indexed.
+    ///
+    /// Only JSON numbers are supported: bounds that aren't numbers are an
+    /// error, and vertices whose value isn't a number never match. Numbers
+    /// are compared as `f64`, so integers beyond 2^53 may compare inexactly.
+    /// The value index is hashed rather than ordered, so this reads every
+    /// indexed value of the property.
+    ///
+    /// # Arguments
+    /// * `name`: The property name.
+    /// * `min`: The lower bound.
+    /// * `max`: The upper bound.
+    pub fn vertex_ids_with_property_value_range(
+        &'a self,
+        name: Identifier,
+        min: &Json,
+        max: &Json,
+    ) -> indradb::Result<Option<DynIter<'a, Uuid>>> {
+        let bound = |value: &Json| {
+            value
+                .as_f64()
+                .ok_or_else(|| DSError::NonNumericBound((**value).to_string()))
+        };
+        let (min, max) = (bound(min)?, bound(max)?);
+        if !self.is_indexed(&name)? {
+            return Ok(None);
+        }
+        let iter = self
+            .vertex_property_manager
+            .vertex_ids_with_property_value_range(name, min, max)?;
+        Ok(Some(Box::new(iter)))
+    }
+
+    /// Gets the ids of vertices whose property `name` is a string starting
+    /// with `prefix`, or `None` if the property isn't indexed. Vertices whose
+    /// value isn't a string never match. The value index is hashed rather
+    /// than ordered, so this reads every indexed value of the property.
+    ///
+    /// # Arguments
+    /// * `name`: The property name.
+    /// * `prefix`: The prefix to match.
+    pub fn vertex_ids_with_property_value_prefix(
+        &'a self,
+        name: Identifier,
+        prefix: &'a str,
+    ) -> indradb::Result<Option<DynIter<'a, Uuid>>> {
+        if !self.is_indexed(&name)? {
+            return Ok(None);
+        }
+        let iter = self
+            .vertex_property_manager
+            .vertex_ids_with_property_value_prefix(name, prefix)?;
+        Ok(Some(Box::new(iter)))
+    }
+
+    /// Gets the vertices with an id of at most `offset`, in descending id
+    /// order: the reverse of `range_vertices`. As there, `offset` is
+    /// inclusive, so a vertex with that id comes first, and the two ranges
+    /// from the same offset share exactly that vertex. With time-ordered v1
+    /// ids this yields the newest vertices first.
+    ///
+    /// # Arguments
+    /// * `offset`: The id to start at, inclusive.
+    pub fn range_vertices_reversed(&'a self, offset: Uuid) -> indradb::Result<DynIter<'a, Vertex>> {
+        let iter = self
+            .vertex_manager
+            .iterate_for_range_rev(offset)
+            .map(|e| e.map(|v| Vertex::with_id(v.0, v.1)));
+        Ok(Box::new(iter))
+    }
+
+    /// An alias of `range_vertices_reversed`, named after `range_edges_rev`.
+    ///
+    /// # Arguments
+    /// * `offset`: The id to start at, inclusive.
+    pub fn range_vertices_rev(&'a self, offset: Uuid) -> indradb::Result<DynIter<'a, Vertex>> {
+        self.range_vertices_reversed(offset)
+    }
+
+    /// Gets the edges that sort at or before `offset`, in descending order:
+    /// the reverse of `range_edges`. As there, `offset` is inclusive, like
+    /// for `range_vertices_reversed`. Edges sort by outbound id, then type,
+    /// then inbound id, so with time-ordered v1 ids the edges of the newest
+    /// vertices come first.
+    ///
+    /// # Arguments
+    /// * `offset`: The edge to start at, inclusive.
+    pub fn range_edges_rev(&'a self, offset: Edge) -> indradb::Result<DynIter<'a, Edge>> {
+        Ok(Box::new(self.edge_range_manager.iterate_for_range_rev(&offset)))
+    }
+
+    /// Gets up to `limit` vertices starting at `offset`, in the same order as
+    /// `range_vertices`. Also returns the id to resume from, or `None` if
+    /// there are no more vertices. The scan stops after one vertex past the
+    /// page.
+    ///
+    /// # Arguments
+    /// * `offset`: The id to start at, inclusive.
+    /// * `limit`: The maximum number of vertices to return.
+    pub fn range_vertices_limited(&self, offset: Uuid, limit: usize) -> indradb::Result<(Vec<Vertex>, Option<Uuid>)> {
+        let mut vertices = Vec::with_capacity(limit);
+        for item in self.vertex_manager.iterate_for_range(offset).take(limit + 1) {
+            let (id, t) = item?;
+            if vertices.len() == limit {
+                return Ok((vertices, Some(id)));
+            }
+            vertices.push(Vertex::with_id(id, t));
+        }
+        Ok((vertices, None))
+    }
+
+    /// Gets up to `limit` edges starting at `offset`, in the same order as
+    /// `range_edges`. Also returns the offset of the next page, or `None` if
+    /// there are no more edges. The scan stops after one edge past the page.
+    ///
+    /// # Arguments
+    /// * `offset`: The edge to start at, inclusive.
+    /// * `limit`: The maximum number of edges to return.
+    pub fn range_edges_limited(&self, offset: Edge, limit: usize) -> indradb::Result<(Vec<Edge>, Option<Edge>)> {
+        let mut edges = Vec::with_capacity(limit);
+        for item in self.edge_range_manager.iterate_for_range(&offset) {
+            let edge = item?;
+            if edges.len() == limit {
+                return Ok((edges, Some(edge)));
+            }
+            edges.push(edge);
+        }
+        Ok((edges, None))
+    }
+
+    fn neighbor_ids(
+        manager: &'a EdgeRangeManager<'a>,
+        id: Uuid,
+        t: Option<Identifier>,
+    ) -> DynIter<'a, (Identifier, Uuid)> {
+        // Reversed ranges store edges with their ends swapped, so the
+        // neighbor is always the inbound id
+        match t {
+            Some(t) => Box::new(
+                manager
+                    .iterate_for_type(id, t)
+                    .map(|item| item.map(|edge| (edge.t, edge.inbound_id))),
+            ),
+            None => Box::new(
+                manager
+                    .iterate_for_owner(id)
+                    .map(|item| item.map(|edge| (edge.t, edge.inbound_id))),
+            ),
+        }
+    }
+
+    /// Gets a vertex property, deserialized straight from the stored bytes.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `name`: The property name.
+    pub fn vertex_property_as<T: DeserializeOwned>(&self, id: Uuid, name: Identifier) -> indradb::Result<Option<T>> {
+        match self.vertex_property_manager.get_bytes(id, name)? {
+            Some(value_bytes) => {
+                let codec = &self.vertex_property_manager.codec;
+                let value = match codec.encoding {
+                    PropertyEncoding::Json => serde_json::from_slice(&codec.serialized(&value_bytes)?),
+                    _ => serde_json::from_value(codec.decode(&value_bytes)?),
+                }
+                .map_err(property_conversion_error(format!("vertex {}", id), name))?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Gets an edge property, deserialized straight from the stored bytes.
+    ///
+    /// # Arguments
+    /// * `edge`: The edge.
+    /// * `name`: The property name.
+    pub fn edge_property_as<T: DeserializeOwned>(&self, edge: &Edge, name: Identifier) -> indradb::Result<Option<T>> {
+        match self.edge_property_manager.get_bytes(edge, name)? {
+            Some(value_bytes) => {
+                let codec = &self.edge_property_manager.codec;
+                let value = match codec.encoding {
+                    PropertyEncoding::Json => serde_json::from_slice(&codec.serialized(&value_bytes)?),
+                    _ => serde_json::from_value(codec.decode(&value_bytes)?),
+                }
+                .map_err(property_conversion_error(format!("edge {:?}", edge), name))?;
+                Ok(Some(value))
+            }
+            None => Ok(None),
+        }
+    }
+
+    /// Sets a vertex property from any serializable value. The value index
+    /// is keyed by the JSON value, so the value is converted once rather than
+    /// going through a `Json` wrapper.
+    ///
+    /// Values must serialize to JSON, so maps whose keys aren't strings
+    /// (e.g. keyed by a tuple or a non-unit enum) are rejected.
+    ///
+    /// # Arguments
+    /// * `id`: The id of the vertex.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub fn set_vertex_property_from<T: Serialize>(
+        &mut self,
+        id: Uuid,
+        name: Identifier,
+        value: &T,
+    ) -> indradb::Result<()> {
+        self.check_writable()?;
+        let value = serde_json::to_value(value).map_err(property_conversion_error(format!("vertex {}", id), name))?;
+        self.vertex_property_manager.set(id, name, &value)
+    }
+
+    /// Sets an edge property from any serializable value. See
+    /// `set_vertex_property_from`.
+    ///
+    /// # Arguments
+    /// * `edge`: The edge.
+    /// * `name`: The property name.
+    /// * `value`: The property value.
+    pub fn set_edge_property_from<T: Serialize>(
+        &mut self,
+        edge: &Edge,
+        name: Identifier,
+        value: &T,
+    ) -> indradb::Result<()> {
+        self.check_writable()?;
+        let value = serde_json::to_value(value).map_err(property_conversion_error(format!("edge {:?}", edge), name))?;
+        self.edge_property_manager.set(edge, name, &value)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+    use std::thread;
+
+    use indradb::{BulkInsertItem, Datastore, DynIter, Edge, Identifier, Json, Transaction, Vertex};
+    use serde::{Deserialize, Serialize};
+    use serde_json::json;
+    use sled::Config;
+    use tempfile::tempdir;
+    use uuid::Uuid;
+
+    use super::NeighborDirection::{Both, Inbound, Outbound};
+    use super::VertexWithProperties;
+    use crate::errors::DSError;
+    use crate::{
+        reverse_edge, CreateEdgeOutcome, IndexInconsistency, IntegrityViolation, RebuildStats, RepairStats, SledConfig,
+        SledDatastore,
+    };
+
+    fn datastore() -> SledDatastore {
+        SledDatastore::new(tempdir().unwrap().keep()).unwrap()
+    }
+
+    #[test]
+    fn test_indexed_properties_after_index_property() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let [name, age] = ["name", "age"].map(|name| Identifier::new(name).unwrap());
+        txn.index_property(name).unwrap();
+        txn.index_property(age).unwrap();
+        assert_eq!(txn.meta_data_manager.indexed_properties().unwrap(), [name, age].into());
+    }
+
+    #[test]
+    fn test_index_properties_with_backfill() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let age = Identifier::new("age").unwrap();
+        let v = Vertex::new(t);
+        let e = Edge::new(v.id, t, v.id);
+        txn.create_vertex(&v).unwrap();
+        txn.create_edge(&e).unwrap();
+        txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("alice")))
+            .unwrap();
+        txn.set_vertex_properties(vec![v.id], age, &Json::new(json!(42)))
+            .unwrap();
+        txn.set_edge_properties(vec![e.clone()], name, &Json::new(json!("self")))
+            .unwrap();
+        ds.holder.vertex_property_values.clear().unwrap();
+        ds.holder.edge_property_values.clear().unwrap();
+
+        txn.index_properties_with_backfill(vec![name, age]).unwrap();
+
+        let vertices: Vec<_> = txn
+            .vertex_ids_with_property_value(age, &Json::new(json!(42)))
+            .unwrap()
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(vertices, vec![v.id]);
+        let edges: Vec<_> = txn
+            .edges_with_property_value(name, &Json::new(json!("self")))
+            .unwrap()
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(edges, vec![e]);
+    }
+
+    #[test]
+    fn test_index_property_backfills_existing_values() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let vertices: Vec<Vertex> = (0..3).map(|_| Vertex::new(t)).collect();
+        for v in vertices.iter() {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.set_vertex_properties(vertices.iter().map(|v| v.id).collect(), name, &Json::new(json!("bob")))
+            .unwrap();
+        ds.holder.vertex_property_values.clear().unwrap();
+        assert!(txn.vertex_ids_with_property(name).unwrap().is_none());
+
+        txn.index_property(name).unwrap();
+
+        let mut found: Vec<_> = txn
+            .vertex_ids_with_property(name)
+            .unwrap()
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        found.sort();
+        let mut expected: Vec<_> = vertices.iter().map(|v| v.id).collect();
+        expected.sort();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_vertices_with_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let person = Identifier::new("person").unwrap();
+        let city = Identifier::new("city").unwrap();
+        let people: Vec<Vertex> = (0..3).map(|_| Vertex::new(person)).collect();
+        for v in people.iter() {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_vertex(&Vertex::new(city)).unwrap();
+
+        let mut found: Vec<Vertex> = txn
+            .vertices_with_type(person)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        found.sort_by_key(|v| v.id);
+        let mut expected = people.clone();
+        expected.sort_by_key(|v| v.id);
+        assert_eq!(found, expected);
+
+        let missing = Identifier::new("country").unwrap();
+        assert_eq!(txn.vertices_with_type(missing).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_distinct_vertex_types() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        assert!(txn.distinct_vertex_types().unwrap().is_empty());
+
+        let names = ["person", "city", "person", "movie", "zebra", "city", "actor", "movie"];
+        for name in names {
+            txn.create_vertex(&Vertex::new(Identifier::new(name).unwrap())).unwrap();
+        }
+        let expected: Vec<Identifier> = ["actor", "city", "movie", "person", "zebra"]
+            .into_iter()
+            .map(|name| Identifier::new(name).unwrap())
+            .collect();
+        assert_eq!(txn.distinct_vertex_types().unwrap(), expected);
+    }
+
+    #[test]
+    fn test_vertex_types() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let [person, city, movie] = ["person", "city", "movie"].map(|t| Identifier::new(t).unwrap());
+        assert!(txn.vertex_types().unwrap().is_empty());
+
+        let (alice, bob, berlin) = (Vertex::new(person), Vertex::new(person), Vertex::new(city));
+        txn.create_vertex(&alice).unwrap();
+        txn.create_vertex(&alice).unwrap();
+        txn.bulk_insert(vec![
+            BulkInsertItem::Vertex(bob.clone()),
+            BulkInsertItem::Vertex(berlin.clone()),
+        ])
+        .unwrap();
+        assert_eq!(txn.vertex_types().unwrap(), vec![city, person]);
+
+        // A type stays listed until its last vertex is gone
+        txn.delete_vertices(vec![alice]).unwrap();
+        assert_eq!(txn.vertex_types().unwrap(), vec![city, person]);
+        txn.delete_vertices(vec![bob.clone(), bob]).unwrap();
+        assert_eq!(txn.vertex_types().unwrap(), vec![city]);
+
+        txn.update_vertex_type(berlin.id, movie).unwrap();
+        assert_eq!(txn.vertex_types().unwrap(), vec![movie]);
+        txn.bulk_insert(vec![BulkInsertItem::Vertex(Vertex::with_id(berlin.id, city))])
+            .unwrap();
+        assert_eq!(txn.vertex_types().unwrap(), vec![city]);
+    }
+
+    #[test]
+    fn test_distinct_edge_types() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let blocks = Identifier::new("blocks").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        let mut edges = Vec::new();
+        for edge_t in [follows, likes, blocks] {
+            edges.push(Edge::new(a.id, edge_t, b.id));
+            edges.push(Edge::new(b.id, edge_t, a.id));
+        }
+        for edge in &edges {
+            txn.create_edge(edge).unwrap();
+        }
+        assert_eq!(txn.distinct_edge_types().unwrap(), vec![blocks, follows, likes]);
+
+        txn.delete_edges(edges.into_iter().filter(|edge| edge.t == likes).collect())
+            .unwrap();
+        assert_eq!(txn.distinct_edge_types().unwrap(), vec![blocks, follows]);
+        assert_eq!(txn.edge_types().unwrap(), vec![blocks, follows]);
+    }
+
+    #[test]
+    fn test_verify_indexes() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let v = Vertex::new(t);
+        let e = Edge::new(v.id, t, v.id);
+        txn.create_vertex(&v).unwrap();
+        txn.create_edge(&e).unwrap();
+        txn.index_property(name).unwrap();
+        txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("alice")))
+            .unwrap();
+        txn.set_edge_properties(vec![e.clone()], name, &Json::new(json!("self")))
+            .unwrap();
+        assert_eq!(txn.verify_indexes().unwrap(), vec![]);
+
+        ds.holder.vertex_property_values.clear().unwrap();
+        ds.holder.edge_properties.clear().unwrap();
+        assert_eq!(
+            txn.verify_indexes().unwrap(),
+            vec![
+                IndexInconsistency::MissingVertexIndex { id: v.id, name },
+                IndexInconsistency::DanglingEdgeIndex { edge: e, name },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_integrity() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let e = Edge::new(a.id, t, b.id);
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&e).unwrap();
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!("alice")))
+            .unwrap();
+        assert!(txn.validate_integrity().unwrap().is_consistent());
+
+        // An edge whose range was lost, and a range with no edge, reversed
+        // range or vertices behind it
+        let stray = Edge::new(Vertex::new(t).id, t, Vertex::new(t).id);
+        ds.holder.edge_ranges.clear().unwrap();
+        txn.edge_range_manager.set(&stray).unwrap();
+        ds.holder
+            .vertex_properties
+            .remove(txn.vertex_property_manager.key(a.id, name))
+            .unwrap();
+        assert_eq!(
+            txn.validate_integrity().unwrap().violations,
+            vec![
+                IntegrityViolation::MissingEdgeRange { edge: e.clone() },
+                IntegrityViolation::MissingEdge { edge: stray.clone() },
+                IntegrityViolation::MissingReversedEdgeRange { edge: stray.clone() },
+                IntegrityViolation::MissingOutboundVertex { edge: stray.clone() },
+                IntegrityViolation::MissingInboundVertex { edge: stray },
+                IntegrityViolation::OrphanedReversedEdgeRange { edge: e },
+                IntegrityViolation::DanglingVertexPropertyValue { id: a.id, name },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_delete_vertices_in_bulk() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let vertices: Vec<Vertex> = (0..10_000).map(|_| Vertex::new(t)).collect();
+        let mut items: Vec<BulkInsertItem> = vertices.iter().cloned().map(BulkInsertItem::Vertex).collect();
+        for (i, v) in vertices.iter().enumerate() {
+            let edge = Edge::new(v.id, t, vertices[(i + 1) % vertices.len()].id);
+            items.push(BulkInsertItem::VertexProperty(v.id, name, Json::new(json!(i))));
+            items.push(BulkInsertItem::EdgeProperty(edge.clone(), name, Json::new(json!(i))));
+            items.push(BulkInsertItem::Edge(edge));
+        }
+        txn.bulk_insert(items).unwrap();
+
+        // Only the outbound edges of a deleted vertex go with it
+        let (odd, even): (Vec<_>, Vec<_>) = vertices.iter().cloned().enumerate().partition(|(i, _)| i % 2 == 1);
+        txn.delete_vertices(odd.into_iter().map(|(_, v)| v).collect()).unwrap();
+        assert_eq!(txn.vertex_count(), 5_000);
+        assert_eq!(txn.edge_count(), 5_000);
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 5_000);
+        for tree in [
+            &ds.holder.reversed_edge_ranges,
+            &ds.holder.edge_types,
+            &ds.holder.vertex_property_values,
+            &ds.holder.edge_property_values,
+        ] {
+            assert_eq!(tree.len(), 5_000);
+        }
+
+        txn.delete_vertices(even.into_iter().map(|(_, v)| v).collect()).unwrap();
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 0);
+        for (tree_name, tree) in ds.holder.named_trees() {
+            assert!(
+                tree_name == "metadata" || tree.is_empty(),
+                "{} wasn't emptied",
+                tree_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_repair_integrity() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edges = vec![Edge::new(a.id, t, b.id), Edge::new(b.id, t, a.id)];
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        for edge in &edges {
+            txn.create_edge(edge).unwrap();
+        }
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!("alice")))
+            .unwrap();
+        assert_eq!(txn.repair_integrity().unwrap(), RepairStats::default());
+
+        ds.holder.edge_ranges.clear().unwrap();
+        assert_eq!(txn.all_edges().unwrap().count(), 0);
+        // A property of a vertex that doesn't exist
+        let ghost = Vertex::new(t);
+        txn.vertex_property_manager
+            .set(ghost.id, name, &json!("ghost"))
+            .unwrap();
+
+        let stats = txn.repair_integrity().unwrap();
+        let removed = |removed| RebuildStats { added: 0, removed };
+        assert_eq!(
+            stats.trees,
+            [
+                ("edge_ranges".to_string(), RebuildStats { added: 2, removed: 0 }),
+                ("vertex_properties".to_string(), removed(1)),
+                ("vertex_property_values".to_string(), removed(1)),
+            ]
+            .into()
+        );
+        let mut found: Vec<Edge> = txn.all_edges().unwrap().map(|e| e.unwrap()).collect();
+        found.sort_by_key(|edge| edge.outbound_id);
+        let mut expected = edges;
+        expected.sort_by_key(|edge| edge.outbound_id);
+        assert_eq!(found, expected);
+        assert!(txn.validate_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_get_or_create_vertex() {
+        let ds = Arc::new(datastore());
+        let id = Vertex::new(Identifier::new("first").unwrap()).id;
+        let types = ["first", "second"].map(|t| Identifier::new(t).unwrap());
+        let handles: Vec<_> = types
+            .into_iter()
+            .map(|t| {
+                let ds = ds.clone();
+                thread::spawn(move || ds.transaction().get_or_create_vertex(&Vertex::with_id(id, t)).unwrap())
+            })
+            .collect();
+        let results: Vec<(bool, Identifier)> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+        // Exactly one thread created the vertex, and both saw its type
+        assert_eq!(results.iter().filter(|(created, _)| *created).count(), 1);
+        assert_eq!(results[0].1, results[1].1);
+        let txn = ds.transaction();
+        let stored = txn.specific_vertices(vec![id]).unwrap().next().unwrap().unwrap();
+        assert_eq!(stored.t, results[0].1);
+        assert_eq!(txn.vertex_count(), 1);
+    }
+
+    #[test]
+    fn test_upsert_vertex() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let v = Vertex::new(Identifier::new("person").unwrap());
+        assert!(txn.upsert_vertex(&v).unwrap());
+        assert!(!txn.upsert_vertex(&v).unwrap());
+        assert!(!txn
+            .upsert_vertex(&Vertex::with_id(v.id, Identifier::new("place").unwrap()))
+            .unwrap());
+        assert_eq!(txn.vertex_count(), 1);
+        let stored = txn.specific_vertices(vec![v.id]).unwrap().next().unwrap().unwrap();
+        assert_eq!(stored, v);
+
+        let place = Identifier::new("place").unwrap();
+        assert_eq!(txn.fetch_or_create_vertex(&Vertex::with_id(v.id, place)).unwrap(), v);
+        let new = Vertex::new(place);
+        assert_eq!(txn.fetch_or_create_vertex(&new).unwrap(), new);
+        assert_eq!(txn.fetch_or_create_vertex(&new).unwrap(), new);
+        assert_eq!(txn.vertex_count(), 2);
+    }
+
+    #[test]
+    fn test_update_vertex_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let v = Vertex::new(Identifier::new("person").unwrap());
+        let other = Vertex::new(Identifier::new("place").unwrap());
+        let name = Identifier::new("name").unwrap();
+        txn.create_vertex(&v).unwrap();
+        txn.create_vertex(&other).unwrap();
+        txn.create_edge(&Edge::new(v.id, Identifier::new("visited").unwrap(), other.id))
+            .unwrap();
+        txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("alice")))
+            .unwrap();
+
+        let employee = Identifier::new("employee").unwrap();
+        assert!(txn.update_vertex_type(v.id, employee).unwrap());
+        assert!(!txn.update_vertex_type(Vertex::new(employee).id, employee).unwrap());
+
+        let vertices: Vec<Vertex> = txn.all_vertices().unwrap().map(|v| v.unwrap()).collect();
+        assert_eq!(vertices.len(), 2);
+        assert!(vertices.contains(&Vertex::with_id(v.id, employee)));
+        assert!(vertices.contains(&other));
+        let updated = Vertex::with_id(v.id, employee);
+        assert_eq!(
+            txn.vertex_property(&updated, name).unwrap(),
+            Some(Json::new(json!("alice")))
+        );
+        assert_eq!(txn.out_degree(v.id).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_update_edge_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let [knows, likes, weight] = ["knows", "likes", "weight"].map(|name| Identifier::new(name).unwrap());
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        let old_edge = Edge::new(a.id, knows, b.id);
+        txn.create_edge(&old_edge).unwrap();
+        txn.index_property(weight).unwrap();
+        txn.set_edge_properties(vec![old_edge.clone()], weight, &Json::new(json!(0.5)))
+            .unwrap();
+
+        assert!(txn.update_edge_type(&old_edge, likes).unwrap());
+        assert!(!txn.update_edge_type(&old_edge, likes).unwrap());
+
+        let new_edge = Edge::new(a.id, likes, b.id);
+        let edges: Vec<Edge> = txn.all_edges().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(edges, vec![new_edge.clone()]);
+        let reversed: Vec<Edge> = txn
+            .range_reversed_edges(reverse_edge(&new_edge))
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(reversed, vec![reverse_edge(&new_edge)]);
+        assert_eq!(
+            txn.edge_property(&new_edge, weight).unwrap(),
+            Some(Json::new(json!(0.5)))
+        );
+        assert_eq!(txn.edge_property(&old_edge, weight).unwrap(), None);
+        let found: Vec<Edge> = txn
+            .edges_with_property_value(weight, &Json::new(json!(0.5)))
+            .unwrap()
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(found, vec![new_edge.clone()]);
+        assert_eq!(txn.edge_count_by_type(knows).unwrap(), 0);
+        assert_eq!(txn.edge_count_by_type(likes).unwrap(), 1);
+        assert_eq!(txn.distinct_edge_types().unwrap(), vec![likes]);
+
+        // Merging into an existing edge keeps the old edge's properties
+        txn.create_edge(&old_edge).unwrap();
+        txn.set_edge_properties(vec![old_edge.clone()], weight, &Json::new(json!(0.9)))
+            .unwrap();
+        assert!(txn.update_edge_type(&old_edge, likes).unwrap());
+        assert_eq!(
+            txn.edge_property(&new_edge, weight).unwrap(),
+            Some(Json::new(json!(0.9)))
+        );
+        assert!(txn
+            .edges_with_property_value(weight, &Json::new(json!(0.5)))
+            .unwrap()
+            .unwrap()
+            .next()
+            .is_none());
+        assert_eq!(txn.edge_count_by_type(knows).unwrap(), 0);
+        assert_eq!(txn.edge_count_by_type(likes).unwrap(), 1);
+        assert!(txn.validate_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_clear() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edge = Edge::new(a.id, t, b.id);
+        txn.index_property(name).unwrap();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        txn.set_vertex_properties(vec![a.id], name, &Json::new(json!("alice")))
+            .unwrap();
+        txn.set_edge_properties(vec![edge.clone()], name, &Json::new(json!(1)))
+            .unwrap();
+        assert_eq!(txn.distinct_vertex_types().unwrap(), vec![t]);
+        assert_eq!(txn.distinct_edge_types().unwrap(), vec![t]);
+
+        txn.clear().unwrap();
+        txn.sync().unwrap();
+
+        assert_eq!(txn.vertex_count(), 0);
+        assert_eq!(txn.edge_count(), 0);
+        assert_eq!(txn.all_vertices().unwrap().count(), 0);
+        assert_eq!(txn.all_edges().unwrap().count(), 0);
+        assert_eq!(txn.range_reversed_edges(reverse_edge(&edge)).unwrap().count(), 0);
+        assert_eq!(txn.all_vertex_properties_for_vertex(&a).unwrap().count(), 0);
+        assert_eq!(txn.all_edge_properties_for_edge(&edge).unwrap().count(), 0);
+        assert!(txn.vertex_ids_with_property(name).unwrap().is_none());
+        assert!(txn.edges_with_property(name).unwrap().is_none());
+        assert!(txn.distinct_vertex_types().unwrap().is_empty());
+        assert!(txn.distinct_edge_types().unwrap().is_empty());
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 0);
+        for (tree_name, tree) in ds.holder.named_trees() {
+            if tree_name != "metadata" {
+                assert!(tree.is_empty(), "{}", tree_name);
+            }
+        }
+        assert!(ds.transaction().vertex_ids_with_property(name).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_copy_vertex_properties() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let [name, age, city] = ["name", "age", "city"].map(|name| Identifier::new(name).unwrap());
+        let (src, dst) = (Vertex::new(t), Vertex::new(t));
+        txn.create_vertex(&src).unwrap();
+        txn.create_vertex(&dst).unwrap();
+        txn.index_property(name).unwrap();
+        txn.set_vertex_properties(vec![src.id], name, &Json::new(json!("alice")))
+            .unwrap();
+        txn.set_vertex_properties(vec![src.id], age, &Json::new(json!(42)))
+            .unwrap();
+        txn.set_vertex_properties(vec![dst.id], name, &Json::new(json!("bob")))
+            .unwrap();
+        txn.set_vertex_properties(vec![dst.id], city, &Json::new(json!("Berlin")))
+            .unwrap();
+
+        assert_eq!(txn.copy_vertex_properties(src.id, dst.id).unwrap(), 2);
+
+        let properties: HashMap<Identifier, Json> = txn
+            .all_vertex_properties_for_vertex(&dst)
+            .unwrap()
+            .map(|p| p.unwrap())
+            .collect();
+        let expected: HashMap<Identifier, Json> = [
+            (name, Json::new(json!("alice"))),
+            (age, Json::new(json!(42))),
+            (city, Json::new(json!("Berlin"))),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(properties, expected);
+        assert_eq!(txn.all_vertex_properties_for_vertex(&src).unwrap().count(), 2);
+        let found: Vec<Uuid> = txn
+            .vertex_ids_with_property_value(name, &Json::new(json!("alice")))
+            .unwrap()
+            .unwrap()
+            .map(|id| id.unwrap())
+            .collect();
+        assert_eq!(found.len(), 2);
+        assert!(txn
+            .vertex_ids_with_property_value(name, &Json::new(json!("bob")))
+            .unwrap()
+            .unwrap()
+            .next()
+            .is_none());
+        assert_eq!(txn.copy_vertex_properties(src.id, Vertex::new(t).id).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_set_vertex_properties_overwrites_in_bulk() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("status").unwrap();
+        txn.index_property(name).unwrap();
+        let vertices: Vec<Vertex> = (0..5_000).map(|_| Vertex::new(t)).collect();
+        txn.bulk_insert(vertices.iter().cloned().map(BulkInsertItem::Vertex).collect())
+            .unwrap();
+        let ids: Vec<Uuid> = vertices.iter().map(|v| v.id).collect();
+
+        let (old, new) = (Json::new(json!("old")), Json::new(json!("new")));
+        txn.set_vertex_properties(ids[..2_500].to_vec(), name, &old).unwrap();
+        txn.set_vertex_properties(ids.clone(), name, &new).unwrap();
+
+        let with_value = |value: &Json| {
+            txn.vertex_ids_with_property_value(name, value)
+                .unwrap()
+                .unwrap()
+                .count()
+        };
+        assert_eq!(with_value(&old), 0);
+        assert_eq!(with_value(&new), 5_000);
+        assert_eq!(txn.vertex_property(&vertices[0], name).unwrap(), Some(new.clone()));
+        assert_eq!(txn.verify_indexes().unwrap(), vec![]);
+        assert!(txn.validate_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_delete_edges_batch() {
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("weight").unwrap();
+        let vertices: Vec<Vertex> = (0..100).map(|_| Vertex::new(t)).collect();
+        let edges: Vec<Edge> = (0..1_000)
+            .map(|i| Edge::new(vertices[i % 100].id, t, vertices[(i / 100 + i + 1) % 100].id))
+            .collect();
+        let populated = || {
+            let ds = datastore();
+            let mut items: Vec<BulkInsertItem> = vertices.iter().cloned().map(BulkInsertItem::Vertex).collect();
+            for (i, edge) in edges.iter().enumerate() {
+                items.push(BulkInsertItem::Edge(edge.clone()));
+                items.push(BulkInsertItem::EdgeProperty(edge.clone(), name, Json::new(json!(i))));
+            }
+            ds.transaction().bulk_insert(items).unwrap();
+            ds
+        };
+
+        // Every other edge, plus one that doesn't exist
+        let mut doomed: Vec<Edge> = edges.iter().step_by(2).cloned().collect();
+        doomed.push(Edge::new(
+            vertices[0].id,
+            Identifier::new("other").unwrap(),
+            vertices[1].id,
+        ));
+        let one_by_one = populated();
+        one_by_one.transaction().delete_edges(doomed.clone()).unwrap();
+        let batched = populated();
+        batched.transaction().delete_edges_batch(doomed).unwrap();
+
+        // Compared first, as reading a type counter can mark the counters as
+        // backfilled in the metadata tree
+        assert_eq!(batched.tree_checksums().unwrap(), one_by_one.tree_checksums().unwrap());
+        assert_eq!(batched.transaction().edge_count(), 500);
+        assert_eq!(batched.transaction().edge_count_by_type(t).unwrap(), 500);
+    }
+
+    #[test]
+    fn test_edges_with_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let blocks = Identifier::new("blocks").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        for edge_t in [follows, likes, blocks] {
+            txn.create_edge(&Edge::new(a.id, edge_t, b.id)).unwrap();
+        }
+        txn.create_edge(&Edge::new(b.id, likes, a.id)).unwrap();
+
+        let mut found: Vec<Edge> = txn
+            .edges_with_type(likes)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        found.sort();
+        let mut expected = vec![Edge::new(a.id, likes, b.id), Edge::new(b.id, likes, a.id)];
+        expected.sort();
+        assert_eq!(found, expected);
+
+        txn.bulk_insert(vec![BulkInsertItem::Edge(Edge::new(b.id, blocks, a.id))])
+            .unwrap();
+        txn.delete_edges(vec![Edge::new(a.id, blocks, b.id)]).unwrap();
+        let found: Vec<Edge> = txn
+            .edges_with_type(blocks)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(found, vec![Edge::new(b.id, blocks, a.id)]);
+        assert_eq!(txn.edges_with_type(follows).unwrap().count(), 1);
+        // Deleting a vertex removes its outbound edges only
+        txn.delete_vertices(vec![a.clone()]).unwrap();
+        let found: Vec<Edge> = txn
+            .edges_with_type(likes)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(found, vec![Edge::new(b.id, likes, a.id)]);
+    }
+
+    #[test]
+    fn test_outbound_edges_with_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let like = Identifier::new("like").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let c = Vertex::new(t);
+        for v in [&a, &b, &c] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&Edge::new(a.id, likes, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, likes, c.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, like, b.id)).unwrap();
+        txn.create_edge(&Edge::new(b.id, likes, c.id)).unwrap();
+
+        let found: Vec<Edge> = txn
+            .outbound_edges_with_type(a.id, likes)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(found.len(), 2);
+        assert!(found.iter().all(|e| e.outbound_id == a.id && e.t == likes));
+
+        let found: Vec<Edge> = txn
+            .outbound_edges_with_type(a.id, like)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(found, vec![Edge::new(a.id, like, b.id)]);
+        assert_eq!(txn.outbound_edges_with_type(c.id, likes).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_edge_count_by_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let c = Vertex::new(t);
+        for v in [&a, &b, &c] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&Edge::new(a.id, follows, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, follows, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, follows, c.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, likes, c.id)).unwrap();
+        assert_eq!(txn.edge_count_by_type(follows).unwrap(), 2);
+        assert_eq!(txn.edge_count_by_type(likes).unwrap(), 1);
+
+        txn.delete_edges(vec![Edge::new(a.id, follows, c.id)]).unwrap();
+        txn.delete_edges(vec![Edge::new(a.id, follows, c.id)]).unwrap();
+        assert_eq!(txn.edge_count_by_type(follows).unwrap(), 1);
+
+        txn.bulk_insert(vec![
+            BulkInsertItem::Edge(Edge::new(a.id, follows, b.id)),
+            BulkInsertItem::Edge(Edge::new(b.id, follows, c.id)),
+            BulkInsertItem::Edge(Edge::new(b.id, follows, c.id)),
+            BulkInsertItem::Edge(Edge::new(c.id, likes, a.id)),
+        ])
+        .unwrap();
+        assert_eq!(txn.edge_count_by_type(follows).unwrap(), 2);
+        assert_eq!(txn.edge_count_by_type(likes).unwrap(), 2);
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_degrees() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let hub = Vertex::new(t);
+        txn.create_vertex(&hub).unwrap();
+        let mut leaves = Vec::new();
+        for i in 0..100 {
+            let leaf = Vertex::new(t);
+            txn.create_vertex(&leaf).unwrap();
+            let edge_t = if i % 4 == 0 { likes } else { follows };
+            txn.create_edge(&Edge::new(hub.id, edge_t, leaf.id)).unwrap();
+            leaves.push(leaf);
+        }
+        txn.create_edge(&Edge::new(leaves[0].id, follows, hub.id)).unwrap();
+
+        assert_eq!(txn.out_degree(hub.id).unwrap(), 100);
+        assert_eq!(txn.in_degree(hub.id).unwrap(), 1);
+        assert_eq!(txn.out_degree_with_type(hub.id, follows).unwrap(), 75);
+        assert_eq!(txn.out_degree_with_type(hub.id, likes).unwrap(), 25);
+        assert_eq!(txn.in_degree_with_type(hub.id, likes).unwrap(), 0);
+        assert_eq!(txn.out_degree(leaves[0].id).unwrap(), 1);
+        assert_eq!(txn.in_degree(leaves[0].id).unwrap(), 1);
+        assert_eq!(txn.in_degree_with_type(leaves[1].id, follows).unwrap(), 1);
+        assert_eq!(txn.out_degree(leaves[1].id).unwrap(), 0);
+    }
+
+    #[test]
+    fn test_vertex_degree_star() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("node").unwrap();
+        let spoke = Identifier::new("spoke").unwrap();
+        let back = Identifier::new("back").unwrap();
+        let hub = Vertex::new(t);
+        txn.create_vertex(&hub).unwrap();
+        let leaves: Vec<Vertex> = (0..10).map(|_| Vertex::new(t)).collect();
+        for leaf in &leaves {
+            txn.create_vertex(leaf).unwrap();
+            txn.create_edge(&Edge::new(hub.id, spoke, leaf.id)).unwrap();
+        }
+        for leaf in &leaves[..3] {
+            txn.create_edge(&Edge::new(leaf.id, back, hub.id)).unwrap();
+        }
+
+        assert_eq!(txn.vertex_out_degree(hub.id).unwrap(), 10);
+        assert_eq!(txn.vertex_in_degree(hub.id).unwrap(), 3);
+        assert_eq!(txn.vertex_degree(hub.id).unwrap(), (3, 10));
+        assert_eq!(txn.vertex_degree(leaves[0].id).unwrap(), (1, 1));
+        assert_eq!(txn.vertex_degree(leaves[9].id).unwrap(), (1, 0));
+        assert_eq!(txn.vertex_degree(Vertex::new(t).id).unwrap(), (0, 0));
+    }
+
+    fn collect_sorted(iter: indradb::Result<DynIter<'_, Uuid>>) -> Vec<Uuid> {
+        let mut ids: Vec<Uuid> = iter.unwrap().collect::<indradb::Result<_>>().unwrap();
+        ids.sort();
+        ids
+    }
+
+    fn neighbor_ids_sorted(iter: indradb::Result<DynIter<'_, (Identifier, Uuid)>>) -> Vec<Uuid> {
+        collect_sorted(iter.map(|iter| Box::new(iter.map(|item| item.map(|(_, id)| id))) as DynIter<'_, Uuid>))
+    }
+
+    #[test]
+    fn test_neighbors() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let c = Vertex::new(t);
+        for v in [&a, &b, &c] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&Edge::new(a.id, follows, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, likes, c.id)).unwrap();
+        txn.create_edge(&Edge::new(c.id, follows, b.id)).unwrap();
+
+        let mut expected = vec![b.id, c.id];
+        expected.sort();
+        assert_eq!(neighbor_ids_sorted(txn.neighbors(a.id, Outbound, None)), expected);
+        assert_eq!(
+            neighbor_ids_sorted(txn.neighbors(a.id, Outbound, Some(follows))),
+            vec![b.id]
+        );
+        assert_eq!(
+            neighbor_ids_sorted(txn.neighbors(a.id, Outbound, Some(likes))),
+            vec![c.id]
+        );
+        assert!(neighbor_ids_sorted(txn.neighbors(b.id, Outbound, None)).is_empty());
+
+        let mut expected = vec![a.id, c.id];
+        expected.sort();
+        assert_eq!(neighbor_ids_sorted(txn.neighbors(b.id, Inbound, None)), expected);
+        assert_eq!(
+            neighbor_ids_sorted(txn.neighbors(b.id, Inbound, Some(follows))),
+            expected
+        );
+        assert!(neighbor_ids_sorted(txn.neighbors(b.id, Inbound, Some(likes))).is_empty());
+        assert_eq!(neighbor_ids_sorted(txn.neighbors(c.id, Inbound, None)), vec![a.id]);
+        assert_eq!(collect_sorted(txn.reverse_neighbors(b.id, None)), expected);
+        assert!(collect_sorted(txn.reverse_neighbors(b.id, Some(likes))).is_empty());
+
+        let mut expected = vec![a.id, b.id];
+        expected.sort();
+        assert_eq!(neighbor_ids_sorted(txn.neighbors(c.id, Both, None)), expected);
+        assert_eq!(neighbor_ids_sorted(txn.neighbors(c.id, Both, Some(likes))), vec![a.id]);
+    }
+
+    #[test]
+    fn test_neighbors_self_loop_and_disconnected() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let (looped, lonely) = (Vertex::new(t), Vertex::new(t));
+        txn.create_vertex(&looped).unwrap();
+        txn.create_vertex(&lonely).unwrap();
+        txn.create_edge(&Edge::new(looped.id, t, looped.id)).unwrap();
+
+        assert_eq!(neighbor_ids_sorted(txn.outbound_neighbors(looped.id)), vec![looped.id]);
+        assert_eq!(neighbor_ids_sorted(txn.inbound_neighbors(looped.id)), vec![looped.id]);
+        assert_eq!(
+            neighbor_ids_sorted(txn.neighbors(looped.id, Both, None)),
+            vec![looped.id, looped.id]
+        );
+        assert!(neighbor_ids_sorted(txn.outbound_neighbors(lonely.id)).is_empty());
+        assert!(neighbor_ids_sorted(txn.inbound_neighbors(lonely.id)).is_empty());
+        assert!(neighbor_ids_sorted(txn.neighbors(lonely.id, Both, None)).is_empty());
+    }
+
+    #[test]
+    fn test_neighbors_with_types() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let c = Vertex::new(t);
+        for v in [&a, &b, &c] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&Edge::new(a.id, follows, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, likes, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, likes, c.id)).unwrap();
+        txn.create_edge(&Edge::new(c.id, follows, b.id)).unwrap();
+
+        let collect = |iter: indradb::Result<DynIter<'_, (Identifier, Uuid)>>| {
+            let mut items: Vec<(Identifier, Uuid)> = iter.unwrap().collect::<indradb::Result<_>>().unwrap();
+            items.sort();
+            items
+        };
+        let mut expected = vec![(follows, b.id), (likes, b.id), (likes, c.id)];
+        expected.sort();
+        let outbound = collect(txn.outbound_neighbors(a.id));
+        assert_eq!(outbound, expected);
+        let liked: Vec<Uuid> = outbound
+            .iter()
+            .filter(|(t, _)| *t == likes)
+            .map(|(_, id)| *id)
+            .collect();
+        let mut expected = vec![b.id, c.id];
+        expected.sort();
+        assert_eq!(liked, expected);
+        assert!(collect(txn.outbound_neighbors(b.id)).is_empty());
+
+        let mut expected = vec![(follows, a.id), (likes, a.id), (follows, c.id)];
+        expected.sort();
+        assert_eq!(collect(txn.inbound_neighbors(b.id)), expected);
+        assert_eq!(collect(txn.inbound_neighbors(c.id)), vec![(likes, a.id)]);
+    }
+
+    #[test]
+    fn test_neighbors_both_directions() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let lonely = Vertex::new(t);
+        for v in [&a, &b, &lonely] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&Edge::new(a.id, t, a.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, t, b.id)).unwrap();
+
+        let mut expected = vec![a.id, b.id];
+        expected.sort();
+        assert_eq!(neighbor_ids_sorted(txn.outbound_neighbors(a.id)), expected);
+        assert_eq!(neighbor_ids_sorted(txn.inbound_neighbors(a.id)), vec![a.id]);
+        assert_eq!(neighbor_ids_sorted(txn.inbound_neighbors(b.id)), vec![a.id]);
+
+        let mut expected = vec![a.id, a.id, b.id];
+        expected.sort();
+        assert_eq!(neighbor_ids_sorted(txn.neighbors(a.id, Both, None)), expected);
+        assert_eq!(neighbor_ids_sorted(txn.neighbors(b.id, Both, None)), vec![a.id]);
+        assert!(neighbor_ids_sorted(txn.neighbors(lonely.id, Both, None)).is_empty());
+        assert!(neighbor_ids_sorted(txn.neighbors(Uuid::nil(), Both, None)).is_empty());
+    }
+
+    #[test]
+    fn test_edges_between() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let follows = Identifier::new("follows").unwrap();
+        let likes = Identifier::new("likes").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let c = Vertex::new(t);
+        for v in [&a, &b, &c] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&Edge::new(a.id, follows, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, likes, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, likes, c.id)).unwrap();
+        txn.create_edge(&Edge::new(b.id, follows, a.id)).unwrap();
+
+        let between = |from: Uuid, to: Uuid| -> Vec<Edge> {
+            let mut edges: Vec<Edge> = txn
+                .edges_between(from, to)
+                .unwrap()
+                .collect::<indradb::Result<_>>()
+                .unwrap();
+            edges.sort();
+            edges
+        };
+        let mut expected = vec![Edge::new(a.id, follows, b.id), Edge::new(a.id, likes, b.id)];
+        expected.sort();
+        assert_eq!(between(a.id, b.id), expected);
+        assert_eq!(between(b.id, a.id), vec![Edge::new(b.id, follows, a.id)]);
+        assert_eq!(between(a.id, c.id), vec![Edge::new(a.id, likes, c.id)]);
+        assert!(between(c.id, a.id).is_empty());
+    }
+
+    #[test]
+    fn test_common_neighbors() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let other = Identifier::new("other").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let c = Vertex::new(t);
+        for v in [&a, &b, &c] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.create_edge(&Edge::new(a.id, t, b.id)).unwrap();
+        txn.create_edge(&Edge::new(a.id, t, c.id)).unwrap();
+        txn.create_edge(&Edge::new(b.id, t, c.id)).unwrap();
+        txn.create_edge(&Edge::new(b.id, other, c.id)).unwrap();
+
+        assert_eq!(txn.common_neighbors(a.id, b.id).unwrap(), vec![c.id]);
+        assert_eq!(txn.common_neighbors(b.id, a.id).unwrap(), vec![c.id]);
+        let mut own = txn.common_neighbors(a.id, a.id).unwrap();
+        own.sort();
+        let mut expected = vec![b.id, c.id];
+        expected.sort();
+        assert_eq!(own, expected);
+        assert!(txn.common_neighbors(a.id, c.id).unwrap().is_empty());
+        assert!(txn.common_neighbors(a.id, Uuid::nil()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_vertex_ids_with_property_value_prefix() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("city").unwrap();
+        let name = Identifier::new("name").unwrap();
+        txn.index_property(name).unwrap();
+        let mut by_name = Vec::new();
+        for value in [
+            json!("Berlin"),
+            json!("Bern"),
+            json!("Bergen"),
+            json!("Boston"),
+            json!("berlin"),
+            json!(42),
+        ] {
+            let v = Vertex::new(t);
+            txn.create_vertex(&v).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(value)).unwrap();
+            by_name.push(v.id);
+        }
+
+        let prefix = |prefix: &'static str| {
+            collect_sorted(Ok(txn
+                .vertex_ids_with_property_value_prefix(name, prefix)
+                .unwrap()
+                .unwrap()))
+        };
+        let mut expected = by_name[..3].to_vec();
+        expected.sort();
+        assert_eq!(prefix("Ber"), expected);
+        assert_eq!(prefix("Berl"), vec![by_name[0]]);
+        assert_eq!(prefix("Bern"), vec![by_name[1]]);
+        assert_eq!(prefix("Bergen"), vec![by_name[2]]);
+        assert_eq!(prefix("b"), vec![by_name[4]]);
+        assert_eq!(prefix("").len(), 5);
+        assert!(prefix("Z").is_empty());
+
+        let unindexed = Identifier::new("unindexed").unwrap();
+        assert!(txn
+            .vertex_ids_with_property_value_prefix(unindexed, "B")
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_vertex_ids_with_property_value_range() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let score = Identifier::new("score").unwrap();
+        txn.index_property(score).unwrap();
+        let mut by_score = Vec::new();
+        for value in [json!(5), json!(10), json!(50.5), json!(100), json!(101), json!("50")] {
+            let v = Vertex::new(t);
+            txn.create_vertex(&v).unwrap();
+            txn.set_vertex_properties(vec![v.id], score, &Json::new(value)).unwrap();
+            by_score.push(v.id);
+        }
+
+        let range = |min, max| {
+            collect_sorted(Ok(txn
+                .vertex_ids_with_property_value_range(score, &Json::new(min), &Json::new(max))
+                .unwrap()
+                .unwrap()))
+        };
+        let mut expected = by_score[1..4].to_vec();
+        expected.sort();
+        assert_eq!(range(json!(10), json!(100)), expected);
+        assert_eq!(range(json!(10.5), json!(99.9)), vec![by_score[2]]);
+        assert_eq!(range(json!(101), json!(101)), vec![by_score[4]]);
+        assert!(range(json!(102), json!(1000)).is_empty());
+        assert!(range(json!(100), json!(10)).is_empty());
+
+        assert!(txn
+            .vertex_ids_with_property_value_range(score, &Json::new(json!("a")), &Json::new(json!(1)))
+            .is_err());
+        let unindexed = Identifier::new("unindexed").unwrap();
+        assert!(txn
+            .vertex_ids_with_property_value_range(unindexed, &Json::new(json!(0)), &Json::new(json!(1)))
+            .unwrap()
+            .is_none());
+    }
+
+    #[test]
+    fn test_range_vertices_reversed() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        for _ in 0..10 {
+            txn.create_vertex(&Vertex::new(t)).unwrap();
+        }
+        let mut all: Vec<Vertex> = txn.all_vertices().unwrap().collect::<indradb::Result<_>>().unwrap();
+        all.reverse();
+
+        let reversed: Vec<Vertex> = txn
+            .range_vertices_reversed(Uuid::max())
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(reversed, all);
+        assert!(reversed.windows(2).all(|pair| pair[0].id > pair[1].id));
+
+        let from_middle: Vec<Vertex> = txn
+            .range_vertices_reversed(all[3].id)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(from_middle, all[3..].to_vec());
+        assert_eq!(txn.range_vertices_reversed(Uuid::nil()).unwrap().count(), 0);
+        assert_eq!(txn.range_vertices_rev(all[3].id).unwrap().count(), from_middle.len());
+
+        // The offset is inclusive, as with `range_vertices`
+        let forward: Vec<Vertex> = txn
+            .range_vertices(all[3].id)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(forward.first(), from_middle.first());
+        assert_eq!(forward.len() + from_middle.len(), 11);
+    }
+
+    #[test]
+    fn test_range_edges_rev() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("follows").unwrap();
+        let vertices: Vec<Vertex> = (0..5).map(|_| Vertex::new(t)).collect();
+        for v in &vertices {
+            txn.create_vertex(v).unwrap();
+        }
+        for a in &vertices {
+            for b in &vertices {
+                txn.create_edge(&Edge::new(a.id, t, b.id)).unwrap();
+            }
+        }
+        let mut all: Vec<Edge> = txn.all_edges().unwrap().collect::<indradb::Result<_>>().unwrap();
+        all.reverse();
+
+        let max = Edge::new(Uuid::max(), t, Uuid::max());
+        let reversed: Vec<Edge> = txn
+            .range_edges_rev(max)
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(reversed, all);
+        assert_eq!(reversed.len(), 25);
+
+        // The offset is inclusive, as with `range_edges`
+        let from_middle: Vec<Edge> = txn
+            .range_edges_rev(all[7].clone())
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(from_middle, all[7..].to_vec());
+        let forward: Vec<Edge> = txn
+            .range_edges(all[7].clone())
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(forward.first(), from_middle.first());
+        assert_eq!(forward.len() + from_middle.len(), 26);
+        let min = Edge::new(Uuid::nil(), t, Uuid::nil());
+        assert_eq!(txn.range_edges_rev(min).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_range_vertices_limited() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        assert_eq!(txn.range_vertices_limited(Uuid::nil(), 5).unwrap(), (vec![], None));
+
+        for _ in 0..10 {
+            txn.create_vertex(&Vertex::new(t)).unwrap();
+        }
+        let all: Vec<Vertex> = txn.all_vertices().unwrap().collect::<indradb::Result<_>>().unwrap();
+
+        let (page, cursor) = txn.range_vertices_limited(Uuid::nil(), 5).unwrap();
+        assert_eq!(page, all[..5].to_vec());
+        assert_eq!(cursor, Some(all[5].id));
+        let (page, cursor) = txn.range_vertices_limited(cursor.unwrap(), 5).unwrap();
+        assert_eq!(page, all[5..].to_vec());
+        assert_eq!(cursor, None);
+
+        let (page, cursor) = txn.range_vertices_limited(Uuid::nil(), 10).unwrap();
+        assert_eq!(page, all);
+        assert_eq!(cursor, None);
+        let (page, cursor) = txn.range_vertices_limited(all[9].id, 1).unwrap();
+        assert_eq!(page, vec![all[9].clone()]);
+        assert_eq!(cursor, None);
+        assert_eq!(txn.range_vertices_limited(Uuid::max(), 5).unwrap(), (vec![], None));
+    }
+
+    #[test]
+    fn test_property_value_range_mixed_numbers() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("reading").unwrap();
+        let temperature = Identifier::new("temperature").unwrap();
+        txn.index_property(temperature).unwrap();
+        let values = [
+            json!(-10),
+            json!(-0.5),
+            json!(0),
+            json!(3),
+            json!(3.25),
+            json!(u64::MAX),
+        ];
+        let mut ids = Vec::new();
+        for value in values {
+            let v = Vertex::new(t);
+            txn.create_vertex(&v).unwrap();
+            txn.set_vertex_properties(vec![v.id], temperature, &Json::new(value))
+                .unwrap();
+            ids.push(v.id);
+        }
+
+        let range = |min: f64, max: f64| {
+            collect_sorted(Ok(txn
+                .vertex_ids_with_property_value_range(temperature, &Json::new(json!(min)), &Json::new(json!(max)))
+                .unwrap()
+                .unwrap()))
+        };
+        let sorted = |mut ids: Vec<Uuid>| {
+            ids.sort();
+            ids
+        };
+        assert_eq!(range(-1.0, 3.0), sorted(vec![ids[1], ids[2], ids[3]]));
+        assert_eq!(range(3.0, 3.5), sorted(vec![ids[3], ids[4]]));
+        assert_eq!(range(-10.0, -10.0), vec![ids[0]]);
+        assert_eq!(range(1e19, f64::MAX), vec![ids[5]]);
+    }
+
+    #[test]
+    fn test_range_edges_limited() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let vertices: Vec<Vertex> = (0..6).map(|_| Vertex::new(t)).collect();
+        for v in &vertices {
+            txn.create_vertex(v).unwrap();
+        }
+        for a in &vertices {
+            for b in &vertices[..4] {
+                txn.create_edge(&Edge::new(a.id, t, b.id)).unwrap();
+            }
+        }
+        let all: Vec<Edge> = txn.all_edges().unwrap().collect::<indradb::Result<_>>().unwrap();
+        assert_eq!(all.len(), 24);
+
+        let mut paged = Vec::new();
+        let mut offset = Some(all[0].clone());
+        while let Some(next) = offset {
+            let (page, cursor) = txn.range_edges_limited(next, 10).unwrap();
+            assert!(page.len() == 10 || cursor.is_none());
+            paged.extend(page);
+            offset = cursor;
+        }
+        assert_eq!(paged, all);
+
+        let (page, cursor) = txn.range_edges_limited(all[20].clone(), 4).unwrap();
+        assert_eq!(page, all[20..].to_vec());
+        assert_eq!(cursor, None);
+        let (page, cursor) = txn.range_edges_limited(all[3].clone(), 0).unwrap();
+        assert!(page.is_empty());
+        assert_eq!(cursor, Some(all[3].clone()));
+    }
+
+    #[derive(Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+    enum Shape {
+        Circle { radius: u32 },
+        Square(u32),
+    }
+
+    #[derive(Debug, PartialEq, Serialize, Deserialize)]
+    struct Profile {
+        nickname: String,
+        shapes: Vec<Shape>,
+        score: Option<f64>,
+    }
+
+    #[test]
+    fn test_typed_properties() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let profile_name = Identifier::new("profile").unwrap();
+        let nickname = Identifier::new("nickname").unwrap();
+        let v = Vertex::new(t);
+        let w = Vertex::new(t);
+        txn.create_vertex(&v).unwrap();
+        txn.create_vertex(&w).unwrap();
+        txn.index_property(nickname).unwrap();
+
+        let profile = Profile {
+            nickname: "bob".to_string(),
+            shapes: vec![Shape::Circle { radius: 2 }, Shape::Square(3)],
+            score: None,
+        };
+        txn.set_vertex_property_from(v.id, profile_name, &profile).unwrap();
+        assert_eq!(
+            txn.vertex_property_as::<Profile>(v.id, profile_name).unwrap(),
+            Some(profile)
+        );
+        assert_eq!(txn.vertex_property_as::<Profile>(w.id, profile_name).unwrap(), None);
+
+        let edge = Edge::new(v.id, t, w.id);
+        txn.create_edge(&edge).unwrap();
+        txn.set_edge_property_from(&edge, profile_name, &Shape::Square(4))
+            .unwrap();
+        assert_eq!(
+            txn.edge_property_as::<Shape>(&edge, profile_name).unwrap(),
+            Some(Shape::Square(4))
+        );
+
+        // The value index works for values written through the typed setter
+        txn.set_vertex_property_from(w.id, nickname, &"alice").unwrap();
+        let found: Vec<_> = txn
+            .vertex_ids_with_property_value(nickname, &Json::new(json!("alice")))
+            .unwrap()
+            .unwrap()
+            .collect::<indradb::Result<_>>()
+            .unwrap();
+        assert_eq!(found, vec![w.id]);
+
+        // Deserialization errors name the property and its owner
+        let Err(indradb::Error::Datastore(err)) = txn.vertex_property_as::<u32>(w.id, nickname) else {
+            panic!("expected a datastore error");
+        };
+        let err = err.to_string();
+        assert!(err.contains("nickname") && err.contains(&w.id.to_string()), "{}", err);
+
+        // JSON maps need string keys
+        let keyed_by_shape: HashMap<Shape, u32> = [(Shape::Square(1), 1)].into_iter().collect();
+        assert!(txn
+            .set_vertex_property_from(v.id, profile_name, &keyed_by_shape)
+            .is_err());
+    }
+
+    #[test]
+    fn test_sample_neighbor() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let weight = Identifier::new("weight").unwrap();
+        let vertices: Vec<Vertex> = (0..4).map(|_| Vertex::new(t)).collect();
+        for v in vertices.iter() {
+            txn.create_vertex(v).unwrap();
+        }
+        let (hub, heavy, ignored, unweighted) = (&vertices[0], &vertices[1], &vertices[2], &vertices[3]);
+        assert_eq!(txn.sample_neighbor(hub.id, weight, 1).unwrap(), None);
+
+        let heavy_edge = Edge::new(hub.id, t, heavy.id);
+        let ignored_edge = Edge::new(hub.id, t, ignored.id);
+        txn.create_edge(&heavy_edge).unwrap();
+        txn.create_edge(&ignored_edge).unwrap();
+        txn.set_edge_properties(vec![heavy_edge], weight, &Json::new(json!(5.0)))
+            .unwrap();
+        txn.set_edge_properties(vec![ignored_edge], weight, &Json::new(json!(0)))
+            .unwrap();
+        for seed in 0..20 {
+            assert_eq!(txn.sample_neighbor(hub.id, weight, seed).unwrap(), Some(heavy.id));
+        }
+
+        txn.create_edge(&Edge::new(hub.id, t, unweighted.id)).unwrap();
+        let picks: Vec<_> = (0..200)
+            .map(|seed| txn.sample_neighbor(hub.id, weight, seed).unwrap().unwrap())
+            .collect();
+        assert!(picks.contains(&unweighted.id));
+        assert!(!picks.contains(&ignored.id));
+        assert_eq!(
+            txn.sample_neighbor(hub.id, weight, 42).unwrap(),
+            txn.sample_neighbor(hub.id, weight, 42).unwrap()
+        );
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn test_sync_async() {
+        let path = tempdir().unwrap().keep();
+        let v = Vertex::new(Identifier::new("foo").unwrap());
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            let mut txn = ds.transaction();
+            txn.create_vertex(&v).unwrap();
+            txn.sync_async().await.unwrap();
+        }
+        let ds = SledDatastore::new(&path).unwrap();
+        assert_eq!(ds.transaction().specific_vertices(vec![v.id]).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn test_request_flush() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        txn.create_vertex(&Vertex::new(Identifier::new("foo").unwrap()))
+            .unwrap();
+        txn.request_flush().unwrap();
+        assert_eq!(txn.vertex_count(), 1);
+    }
+
+    #[test]
+    fn test_bulk_insert_checked() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let a = Vertex::new(t);
+        let b = Vertex::new(t);
+        let edge = Edge::new(a.id, t, b.id);
+        let batch = || {
+            vec![
+                BulkInsertItem::Vertex(a.clone()),
+                BulkInsertItem::Vertex(b.clone()),
+                BulkInsertItem::Vertex(a.clone()),
+                BulkInsertItem::Edge(edge.clone()),
+                BulkInsertItem::VertexProperty(a.id, name, Json::new(json!("a"))),
+                BulkInsertItem::EdgeProperty(edge.clone(), name, Json::new(json!("e"))),
+                BulkInsertItem::Edge(edge.clone()),
+            ]
+        };
+
+        assert_eq!(
+            txn.bulk_insert_checked(batch()).unwrap(),
+            vec![true, true, false, true, true, true, false]
+        );
+        assert_eq!(txn.bulk_insert_checked(batch()).unwrap(), vec![false; 7]);
+        assert_eq!(txn.vertex_count(), 2);
+        assert_eq!(txn.edge_count(), 1);
+        assert_eq!(txn.edge_count_by_type(t).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_read_only() {
+        let path = tempdir().unwrap().keep();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let v = Vertex::new(t);
+        let e = Edge::new(v.id, t, v.id);
+        {
+            let ds = SledDatastore::new(&path).unwrap();
+            let mut txn = ds.transaction();
+            txn.index_property(name).unwrap();
+            txn.create_vertex(&v).unwrap();
+            txn.create_edge(&e).unwrap();
+            txn.set_vertex_properties(vec![v.id], name, &Json::new(json!("alice")))
+                .unwrap();
+            txn.set_edge_properties(vec![e.clone()], name, &Json::new(json!("knows")))
+                .unwrap();
+            txn.sync().unwrap();
+        }
+
+        let ds = SledConfig::default().read_only().open(&path).unwrap();
+        let mut txn = ds.transaction();
+        let value = Json::new(json!(1));
+        assert!(txn.create_vertex(&Vertex::new(t)).is_err());
+        assert!(txn.create_edge(&e).is_err());
+        assert!(txn.set_vertex_properties(vec![v.id], name, &value).is_err());
+        assert!(txn.set_edge_properties(vec![e.clone()], name, &value).is_err());
+        assert!(txn.delete_vertices(vec![v.clone()]).is_err());
+        assert!(txn.delete_edges(vec![e.clone()]).is_err());
+        assert!(txn.delete_vertex_properties(vec![(v.id, name)]).is_err());
+        assert!(txn.delete_edge_properties(vec![(e.clone(), name)]).is_err());
+        assert!(txn.bulk_insert(vec![BulkInsertItem::Vertex(Vertex::new(t))]).is_err());
+        assert!(txn.index_property(name).is_err());
+        assert!(txn
+            .bulk_insert_checked(vec![BulkInsertItem::Vertex(Vertex::new(t))])
+            .is_err());
+        let Err(indradb::Error::Datastore(err)) = txn.create_vertex(&Vertex::new(t)) else {
+            panic!("expected a datastore error");
+        };
+        assert!(matches!(err.downcast_ref::<DSError>(), Some(DSError::ReadOnly)));
+
+        assert_eq!(txn.vertex_count(), 1);
+        assert_eq!(txn.edge_count(), 1);
+        assert_eq!(txn.all_vertices().unwrap().count(), 1);
+        assert_eq!(txn.range_vertices(Uuid::nil()).unwrap().count(), 1);
+        assert_eq!(txn.specific_vertices(vec![v.id]).unwrap().count(), 1);
+        assert_eq!(txn.all_edges().unwrap().count(), 1);
+        assert_eq!(txn.range_edges(e.clone()).unwrap().count(), 1);
+        assert_eq!(txn.range_reversed_edges(e.clone()).unwrap().count(), 1);
+        assert_eq!(txn.specific_edges(vec![e.clone()]).unwrap().count(), 1);
+        assert_eq!(txn.vertex_property(&v, name).unwrap(), Some(Json::new(json!("alice"))));
+        assert_eq!(txn.edge_property(&e, name).unwrap(), Some(Json::new(json!("knows"))));
+        assert_eq!(txn.all_vertex_properties_for_vertex(&v).unwrap().count(), 1);
+        assert_eq!(txn.all_edge_properties_for_edge(&e).unwrap().count(), 1);
+        assert_eq!(txn.vertex_ids_with_property(name).unwrap().unwrap().count(), 1);
+        let alice = Json::new(json!("alice"));
+        assert_eq!(
+            txn.vertex_ids_with_property_value(name, &alice)
+                .unwrap()
+                .unwrap()
+                .count(),
+            1
+        );
+        assert_eq!(txn.edges_with_property(name).unwrap().unwrap().count(), 1);
+        assert!(txn.sync().is_ok());
+    }
+
+    /// Items touching the same properties from different partitions, with
+    /// the last value of each property differing from the first.
+    #[cfg(feature = "rayon")]
+    fn overlapping_items(t: Identifier, name: Identifier) -> Vec<BulkInsertItem> {
+        let vertices: Vec<Vertex> = (0..5_000).map(|_| Vertex::new(t)).collect();
+        let mut items: Vec<BulkInsertItem> = vertices.iter().cloned().map(BulkInsertItem::Vertex).collect();
+        for round in 0..3 {
+            for (i, v) in vertices.iter().enumerate() {
+                let edge = Edge::new(v.id, t, vertices[(i + 1) % vertices.len()].id);
+                items.push(BulkInsertItem::Edge(edge.clone()));
+                items.push(BulkInsertItem::VertexProperty(v.id, name, Json::new(json!(i + round))));
+                items.push(BulkInsertItem::EdgeProperty(edge, name, Json::new(json!(i * round))));
+            }
+        }
+        items
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn test_parallel_bulk_insert_matches_serial() {
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("rank").unwrap();
+        let items = overlapping_items(t, name);
+
+        let serial = datastore();
+        super::IndraSledBatch::stage(&serial.holder, &items)
+            .unwrap()
+            .apply(&serial.holder)
+            .unwrap();
+        let parallel = datastore();
+        parallel.transaction().bulk_insert(items).unwrap();
+
+        assert_eq!(parallel.tree_checksums().unwrap(), serial.tree_checksums().unwrap());
+        assert_eq!(parallel.transaction().edge_count(), 5_000);
+        assert_eq!(parallel.holder.vertex_property_values.len(), 5_000);
+        assert_eq!(parallel.holder.edge_property_values.len(), 5_000);
+        assert!(parallel.verify_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_vertices_with_all_properties() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let age = Identifier::new("age").unwrap();
+        let (a, b, bare) = (Vertex::new(t), Vertex::new(t), Vertex::new(t));
+        for v in [&a, &b, &bare] {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.set_vertex_properties(vec![a.id, b.id], name, &Json::new(json!("x")))
+            .unwrap();
+        txn.set_vertex_properties(vec![a.id], age, &Json::new(json!(30)))
+            .unwrap();
+
+        let ids = vec![a.id, Vertex::new(t).id, bare.id, b.id];
+        let combined: Vec<VertexWithProperties> = txn
+            .vertices_with_all_properties(ids.clone())
+            .unwrap()
+            .map(|item| item.unwrap())
+            .collect();
+        let separate: Vec<VertexWithProperties> = txn
+            .specific_vertices(ids)
+            .unwrap()
+            .map(|v| {
+                let v = v.unwrap();
+                let properties = txn
+                    .all_vertex_properties_for_vertex(&v)
+                    .unwrap()
+                    .map(|p| p.unwrap())
+                    .collect();
+                (v, properties)
+            })
+            .collect();
+        assert_eq!(combined, separate);
+        assert_eq!(combined.len(), 3);
+        assert_eq!(combined[0].1.len(), 2);
+        assert!(combined[1].1.is_empty());
+    }
+
+    #[test]
+    fn test_create_edge_checked() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("follows").unwrap();
+        let (a, b, missing) = (Vertex::new(t), Vertex::new(t), Vertex::new(t));
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+
+        let edge = Edge::new(a.id, t, b.id);
+        assert_eq!(txn.create_edge_checked(&edge).unwrap(), CreateEdgeOutcome::Created);
+        assert_eq!(txn.create_edge_checked(&edge).unwrap(), CreateEdgeOutcome::Overwritten);
+        assert_eq!(
+            txn.create_edge_checked(&Edge::new(missing.id, t, b.id)).unwrap(),
+            CreateEdgeOutcome::MissingOutbound
+        );
+        assert_eq!(
+            txn.create_edge_checked(&Edge::new(a.id, t, missing.id)).unwrap(),
+            CreateEdgeOutcome::MissingInbound
+        );
+        assert_eq!(
+            txn.create_edge_checked(&Edge::new(missing.id, t, missing.id)).unwrap(),
+            CreateEdgeOutcome::MissingOutbound
+        );
+        assert_eq!(txn.edge_count(), 1);
+        assert!(txn.create_edge(&edge).unwrap());
+        assert!(!txn.create_edge(&Edge::new(a.id, t, missing.id)).unwrap());
+    }
+
+    #[test]
+    fn test_vertex_and_edge_exists() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let t = Identifier::new("follows").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        let edge = Edge::new(a.id, t, b.id);
+        txn.create_edge(&edge).unwrap();
+
+        assert!(txn.vertex_exists(a.id).unwrap());
+        assert!(!txn.vertex_exists(Vertex::new(t).id).unwrap());
+        assert!(txn.edge_exists(&edge).unwrap());
+        assert!(!txn.edge_exists(&reverse_edge(&edge)).unwrap());
+        assert!(!txn
+            .edge_exists(&Edge::new(a.id, Identifier::new("likes").unwrap(), b.id))
+            .unwrap());
+
+        txn.delete_vertices(vec![a.clone()]).unwrap();
+        assert!(!txn.vertex_exists(a.id).unwrap());
+        assert!(!txn.edge_exists(&edge).unwrap());
+    }
+
+    #[test]
+    fn test_delete_vertices_by_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let (person, place) = (Identifier::new("person").unwrap(), Identifier::new("place").unwrap());
+        let name = Identifier::new("name").unwrap();
+        let people: Vec<Vertex> = (0..5).map(|_| Vertex::new(person)).collect();
+        let places: Vec<Vertex> = (0..3).map(|_| Vertex::new(place)).collect();
+        for v in people.iter().chain(places.iter()) {
+            txn.create_vertex(v).unwrap();
+        }
+        txn.set_vertex_properties(vec![people[0].id, places[0].id], name, &Json::new(json!("x")))
+            .unwrap();
+        txn.create_edge(&Edge::new(places[0].id, person, places[1].id)).unwrap();
+
+        assert_eq!(txn.delete_vertices_by_type(person).unwrap(), 5);
+        assert_eq!(txn.delete_vertices_by_type(person).unwrap(), 0);
+        assert_eq!(
+            txn.delete_vertices_by_type(Identifier::new("thing").unwrap()).unwrap(),
+            0
+        );
+
+        let remaining: Vec<Vertex> = txn.all_vertices().unwrap().map(|v| v.unwrap()).collect();
+        assert_eq!(remaining.len(), 3);
+        assert!(remaining.iter().all(|v| v.t == place));
+        assert!(txn.vertex_property(&people[0], name).unwrap().is_none());
+        assert!(txn.vertex_property(&places[0], name).unwrap().is_some());
+        assert_eq!(txn.edge_count(), 1);
+    }
+
+    #[test]
+    fn test_delete_edges_by_type() {
+        let ds = datastore();
+        let mut txn = ds.transaction();
+        let (follows, likes) = (Identifier::new("follows").unwrap(), Identifier::new("likes").unwrap());
+        let weight = Identifier::new("weight").unwrap();
+        let vertices: Vec<Vertex> = (0..4).map(|_| Vertex::new(follows)).collect();
+        for v in vertices.iter() {
+            txn.create_vertex(v).unwrap();
+        }
+        let followed: Vec<Edge> = (1..4)
+            .map(|i| Edge::new(vertices[0].id, follows, vertices[i].id))
+            .collect();
+        let liked = Edge::new(vertices[1].id, likes, vertices[2].id);
+        for edge in followed.iter().chain([&liked]) {
+            txn.create_edge(edge).unwrap();
+        }
+        txn.set_edge_properties(vec![followed[0].clone(), liked.clone()], weight, &Json::new(json!(1)))
+            .unwrap();
+
+        assert_eq!(txn.delete_edges_by_type(follows).unwrap(), 3);
+        assert_eq!(txn.delete_edges_by_type(follows).unwrap(), 0);
+
+        let remaining: Vec<Edge> = txn.all_edges().unwrap().map(|e| e.unwrap()).collect();
+        assert_eq!(remaining, vec![liked.clone()]);
+        assert_eq!(txn.edge_count_by_type(follows).unwrap(), 0);
+        assert!(txn.edge_property(&followed[0], weight).unwrap().is_none());
+        assert!(txn.edge_property(&liked, weight).unwrap().is_some());
+        assert_eq!(ds.holder.edge_properties.len(), 1);
+        assert_eq!(ds.holder.edge_property_values.len(), 1);
+        assert_eq!(ds.holder.reversed_edge_ranges.len(), 1);
+        assert!(ds
+            .holder
+            .reversed_edge_ranges
+            .contains_key(txn.edge_range_manager_rev.key(&reverse_edge(&liked)))
+            .unwrap());
+        assert!(txn.validate_integrity().unwrap().is_consistent());
+    }
+
+    #[test]
+    fn test_specific_edges_surfaces_errors() {
+        let db = Config::new().temporary(true).open().unwrap();
+        let ds = SledDatastore::open_with_db(db.clone()).unwrap();
+        let t = Identifier::new("follows").unwrap();
+        let (a, b) = (Vertex::new(t), Vertex::new(t));
+        let edge = Edge::new(a.id, t, b.id);
+        let mut txn = ds.transaction();
+        txn.create_vertex(&a).unwrap();
+        txn.create_vertex(&b).unwrap();
+        txn.create_edge(&edge).unwrap();
+        let missing = Edge::new(b.id, t, a.id);
+        let found: Vec<Edge> = txn
+            .specific_edges(vec![missing.clone(), edge.clone()])
+            .unwrap()
+            .map(|e| e.unwrap())
+            .collect();
+        assert_eq!(found, vec![edge.clone()]);
+
+        // Calls on the datastore's handle to a dropped tree fail
+        db.drop_tree("edge_ranges").unwrap();
+        let results: Vec<_> = txn.specific_edges(vec![edge.clone(), missing]).unwrap().collect();
+        assert_eq!(results.len(), 1);
+        assert!(results[0].is_err());
+        assert!(txn.delete_edges(vec![edge]).is_err());
+    }
+}
+
+#[cfg(all(test, feature = "bench-suite"))]
+mod bench {
+    use indradb::{BulkInsertItem, Datastore, Edge, Identifier, Json, Transaction, Vertex};
+    use serde_json::json;
+    use tempfile::tempdir;
+    use test::Bencher;
+
+    #[cfg(feature = "rayon")]
+    use super::IndraSledBatch;
+    use super::SledTransaction;
+    use crate::SledDatastore;
+
+    /// A store holding `count` vertices, each with one property and one
+    /// outbound edge to the next vertex.
+    fn populated(count: usize) -> (SledDatastore, Vec<Vertex>) {
+        let ds = SledDatastore::new(tempdir().unwrap().keep()).unwrap();
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("name").unwrap();
+        let vertices: Vec<Vertex> = (0..count).map(|_| Vertex::new(t)).collect();
+        let mut items: Vec<BulkInsertItem> = vertices.iter().cloned().map(BulkInsertItem::Vertex).collect();
+        for (i, v) in vertices.iter().enumerate() {
+            let edge = Edge::new(v.id, t, vertices[(i + 1) % vertices.len()].id);
+            items.push(BulkInsertItem::VertexProperty(v.id, name, Json::new(json!(i))));
+            items.push(BulkInsertItem::Edge(edge));
+        }
+        ds.transaction().bulk_insert(items).unwrap();
+        (ds, vertices)
+    }
+
+    /// Deletes vertices one key at a time, as `delete_vertices` used to.
+    fn delete_one_by_one(txn: &SledTransaction, vertices: &[Vertex]) {
+        for v in vertices {
+            txn.holder.vertices.remove(txn.vertex_manager.key(v.id)).unwrap();
+            for item in txn.vertex_property_manager.iterate_for_owner(v.id).unwrap() {
+                let ((id, name), _) = item.unwrap();
+                txn.vertex_property_manager.delete(id, name).unwrap();
+            }
+            for item in txn.edge_range_manager.iterate_for_owner(v.id) {
+                txn.edge_manager.delete(&item.unwrap()).unwrap();
+            }
+        }
+    }
+
+    // Both benches include populating the store, so compare the two rather
+    // than reading either in isolation.
+
+    #[bench]
+    fn bench_delete_vertices_one_by_one(b: &mut Bencher) {
+        b.iter(|| {
+            let (ds, vertices) = populated(10_000);
+            delete_one_by_one(&ds.transaction(), &vertices);
+        });
+    }
+
+    #[bench]
+    fn bench_delete_vertices_batched(b: &mut Bencher) {
+        b.iter(|| {
+            let (ds, vertices) = populated(10_000);
+            ds.transaction().delete_vertices(vertices).unwrap();
+        });
+    }
+
+    /// Items setting the same properties several times over, so that the
+    /// parallel path has to merge partitions.
+    #[cfg(feature = "rayon")]
+    fn bulk_items(count: usize) -> Vec<BulkInsertItem> {
+        let t = Identifier::new("person").unwrap();
+        let name = Identifier::new("rank").unwrap();
+        let vertices: Vec<Vertex> = (0..count).map(|_| Vertex::new(t)).collect();
+        let mut items: Vec<BulkInsertItem> = vertices.iter().cloned().map(BulkInsertItem::Vertex).collect();
+        for round in 0..3 {
+            for (i, v) in vertices.iter().enumerate() {
+                let edge = Edge::new(v.id, t, vertices[(i + 1) % vertices.len()].id);
+                items.push(BulkInsertItem::Edge(edge.clone()));
+                items.push(BulkInsertItem::VertexProperty(v.id, name, Json::new(json!(i + round))));
+                items.push(BulkInsertItem::EdgeProperty(edge, name, Json::new(json!(i * round))));
+            }
+        }
+        items
+    }
+
+    #[cfg(feature = "rayon")]
+    #[bench]
+    fn bench_bulk_insert_serial(b: &mut Bencher) {
+        let items = bulk_items(5_000);
+        b.iter(|| {
+            let ds = SledDatastore::new(tempdir().unwrap().keep()).unwrap();
+            IndraSledBatch::stage(&ds.holder, &items)
+                .unwrap()
+                .apply(&ds.holder)
+                .unwrap();
+        });
+    }
+
+    #[cfg(feature = "rayon")]
+    #[bench]
+    fn bench_bulk_insert_parallel(b: &mut Bencher) {
+        let items = bulk_items(5_000);
+        b.iter(|| {
+            let ds = SledDatastore::new(tempdir().unwrap().keep()).unwrap();
+            ds.transaction().bulk_insert_parallel(&items).unwrap();
+        });
+    }
+}
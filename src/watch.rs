@@ -0,0 +1,97 @@
+//! Subscriptions to changes of the vertex tree.
+
+use std::io::Cursor;
+use std::time::Duration;
+
+use indradb::{util, Vertex};
+use sled::{Event, Subscriber};
+use uuid::Uuid;
+
+/// A change to a vertex, as seen by a `VertexWatcher`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum VertexEvent {
+    /// The vertex was written. Sled doesn't say whether the key existed
+    /// before, so this covers both creating a vertex and overwriting one,
+    /// which only `bulk_insert` does.
+    Written { vertex: Vertex },
+    /// The vertex was deleted.
+    Deleted { id: Uuid },
+}
+
+/// Yields the changes to one vertex, or to all of them, in the order they
+/// were applied. Created with `SledDatastore::watch_vertex` or
+/// `SledDatastore::watch_all_vertices`. Iterating blocks until the next
+/// change; use `next_timeout` to wait for a bounded time instead.
+pub struct VertexWatcher {
+    pub(crate) subscriber: Subscriber,
+}
+
+impl VertexWatcher {
+    /// Waits up to `timeout` for the next change, returning `None` if there
+    /// was none.
+    ///
+    /// # Arguments
+    /// * `timeout`: How long to wait.
+    pub fn next_timeout(&mut self, timeout: Duration) -> Option<VertexEvent> {
+        self.subscriber.next_timeout(timeout).ok().map(decode)
+    }
+}
+
+impl Iterator for VertexWatcher {
+    type Item = VertexEvent;
+
+    fn next(&mut self) -> Option<VertexEvent> {
+        self.subscriber.next().map(decode)
+    }
+}
+
+fn decode(event: Event) -> VertexEvent {
+    match event {
+        Event::Insert { key, value } => {
+            let id = util::read_uuid(&mut Cursor::new(key));
+            let t = util::read_identifier(&mut Cursor::new(value));
+            VertexEvent::Written {
+                vertex: Vertex::with_id(id, t),
+            }
+        }
+        Event::Remove { key } => VertexEvent::Deleted {
+            id: util::read_uuid(&mut Cursor::new(key)),
+        },
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::time::Duration;
+
+    use indradb::{Datastore, Identifier, Transaction, Vertex};
+
+    use super::VertexEvent;
+    use crate::SledDatastore;
+
+    const TIMEOUT: Duration = Duration::from_secs(1);
+
+    #[test]
+    fn test_watch_vertex() {
+        let ds = SledDatastore::new_temporary().unwrap();
+        let t = Identifier::new("foo").unwrap();
+        let (watched, other) = (Vertex::new(t), Vertex::new(t));
+        let mut one = ds.watch_vertex(watched.id);
+        let mut all = ds.watch_all_vertices();
+
+        let mut txn = ds.transaction();
+        txn.create_vertex(&other).unwrap();
+        txn.create_vertex(&watched).unwrap();
+        txn.delete_vertices(vec![watched.clone()]).unwrap();
+
+        let written = VertexEvent::Written {
+            vertex: watched.clone(),
+        };
+        let deleted = VertexEvent::Deleted { id: watched.id };
+        assert_eq!(one.next_timeout(TIMEOUT), Some(written.clone()));
+        assert_eq!(one.next_timeout(TIMEOUT), Some(deleted.clone()));
+        assert_eq!(one.next_timeout(Duration::from_millis(50)), None);
+        let events: Vec<VertexEvent> = (0..3).filter_map(|_| all.next_timeout(TIMEOUT)).collect();
+        assert_eq!(events, vec![VertexEvent::Written { vertex: other }, written, deleted]);
+    }
+}